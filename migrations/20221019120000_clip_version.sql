@@ -0,0 +1,2 @@
+-- Incremented on every change to a clip, used for ETags and optimistic concurrency
+ALTER TABLE clips ADD COLUMN version bigint NOT NULL DEFAULT 1;
@@ -0,0 +1,2 @@
+-- The API key that created a clip; it may update or delete the clip without its password
+ALTER TABLE clips ADD COLUMN owner_key blob REFERENCES api_keys (api_key) ON DELETE SET NULL;
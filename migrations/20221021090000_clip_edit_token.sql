@@ -0,0 +1,2 @@
+-- SHA-256 of the edit token issued to whoever created the clip on the web
+ALTER TABLE clips ADD COLUMN edit_token_hash text;
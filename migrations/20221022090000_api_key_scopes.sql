@@ -0,0 +1,2 @@
+-- Space separated scopes granted to each key; existing keys keep full access to clips
+ALTER TABLE api_keys ADD COLUMN scopes text NOT NULL DEFAULT 'clip:read clip:write clip:delete';
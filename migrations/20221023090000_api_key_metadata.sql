@@ -0,0 +1,8 @@
+-- Public identifier, label and lifetime of each API key
+ALTER TABLE api_keys ADD COLUMN key_id text;
+ALTER TABLE api_keys ADD COLUMN name text NOT NULL DEFAULT '';
+ALTER TABLE api_keys ADD COLUMN created_at datetime NOT NULL DEFAULT 0;
+ALTER TABLE api_keys ADD COLUMN last_used_at datetime;
+ALTER TABLE api_keys ADD COLUMN expires_at datetime;
+UPDATE api_keys SET key_id = lower(hex(randomblob(8))), created_at = strftime('%s', 'now');
+CREATE UNIQUE INDEX api_keys_key_id ON api_keys (key_id);
@@ -0,0 +1,4 @@
+-- Per-key overrides of the server wide clip quotas; NULL uses the server default
+ALTER TABLE api_keys ADD COLUMN max_clips integer;
+ALTER TABLE api_keys ADD COLUMN max_bytes integer;
+ALTER TABLE api_keys ADD COLUMN max_clips_per_day integer;
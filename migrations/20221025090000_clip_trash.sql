@@ -0,0 +1,3 @@
+-- Deleted and expired clips stay in the trash until purged; NULL means the clip is live
+ALTER TABLE clips ADD COLUMN deleted_at datetime;
+CREATE INDEX clips_deleted_at ON clips (deleted_at);
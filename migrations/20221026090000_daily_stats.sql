@@ -0,0 +1,8 @@
+-- Daily rollup of clip analytics; day is the unix time of midnight UTC
+CREATE TABLE daily_stats (
+    day integer primary key NOT NULL,
+    clips_created integer NOT NULL,
+    live_clips integer NOT NULL,
+    live_bytes integer NOT NULL,
+    hits integer NOT NULL
+);
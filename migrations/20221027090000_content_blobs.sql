@@ -0,0 +1,26 @@
+-- Clip bodies, stored once per distinct content and keyed by its SHA-256 in hex
+CREATE TABLE blobs (
+    hash text primary key NOT NULL,
+    content text NOT NULL,
+    refs integer NOT NULL DEFAULT 0
+);
+-- Clips with a content hash leave `content` empty; older clips keep their content inline
+-- until the content dedup job moves it to `blobs`
+ALTER TABLE clips ADD COLUMN content_hash text REFERENCES blobs (hash);
+CREATE INDEX clips_content_hash ON clips (content_hash);
+CREATE INDEX blobs_orphaned ON blobs (refs) WHERE refs = 0;
+-- Keep `refs` at the number of clips, in the trash or not, using each blob
+CREATE TRIGGER clips_ref_blob AFTER INSERT ON clips WHEN new.content_hash IS NOT NULL
+BEGIN
+    UPDATE blobs SET refs = refs + 1 WHERE hash = new.content_hash;
+END;
+CREATE TRIGGER clips_unref_blob AFTER DELETE ON clips WHEN old.content_hash IS NOT NULL
+BEGIN
+    UPDATE blobs SET refs = refs - 1 WHERE hash = old.content_hash;
+END;
+CREATE TRIGGER clips_reref_blob AFTER UPDATE OF content_hash ON clips
+    WHEN old.content_hash IS NOT new.content_hash
+BEGIN
+    UPDATE blobs SET refs = refs - 1 WHERE hash = old.content_hash;
+    UPDATE blobs SET refs = refs + 1 WHERE hash = new.content_hash;
+END;
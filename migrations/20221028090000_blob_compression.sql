@@ -0,0 +1,7 @@
+-- How blob content is stored: 'plain' text or a 'zstd' frame of it; NULL for plain text
+-- stored before compression, which the compression job has yet to look at
+ALTER TABLE blobs ADD COLUMN codec text;
+-- Length of the uncompressed content in bytes, which quotas and statistics count
+ALTER TABLE blobs ADD COLUMN size integer NOT NULL DEFAULT 0;
+UPDATE blobs SET size = length(CAST(content AS BLOB));
+CREATE INDEX blobs_uncompressed ON blobs (size) WHERE codec IS NULL;
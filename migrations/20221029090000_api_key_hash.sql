@@ -0,0 +1,3 @@
+-- API keys are stored as their SHA-256 from now on; keys saved before are still raw
+-- until hashed at startup, along with the owner_key of their clips
+ALTER TABLE api_keys ADD COLUMN hashed boolean NOT NULL DEFAULT false;
@@ -1,7 +1,7 @@
 use clipstash::domain::clip::field::{Content, ExpiresAt, Password, ShortCode, Title};
-use clipstash::service::ask::{GetClip, NewClip, UpdateClip};
-use clipstash::web::api::{ApiKey, API_KEY_HEADER};
-use clipstash::Clip;
+use clipstash::web::api::v1::{ClipResponse, NewClipRequest, PatchClipRequest};
+use clipstash::web::api::{ApiKey, ErrorBody, API_KEY_HEADER, CLIP_PASSWORD_HEADER};
+use serde::de::DeserializeOwned;
 use std::error::Error;
 use structopt::StructOpt;
 
@@ -19,18 +19,43 @@ enum Command {
         content: String,
         #[structopt(short, long, help = "password")]
         password: Option<Password>,
-        #[structopt(short, long, help = "expiration date")]
+        #[structopt(
+            short,
+            long,
+            help = "expiration date (yyyy-mm-dd in UTC, or RFC 3339 with an offset)"
+        )]
         expires_at: Option<ExpiresAt>,
+        #[structopt(
+            long,
+            help = "return an identical live clip made with this API key, if there is one"
+        )]
+        reuse_existing: bool,
     },
     Update {
         shortcode: ShortCode,
-        content: String,
+        #[structopt(help = "content")]
+        content: Option<String>,
         #[structopt(short, long, help = "password")]
         password: Option<Password>,
-        #[structopt(short, long, help = "expiration date")]
+        #[structopt(long, help = "remove the password", conflicts_with = "password")]
+        no_password: bool,
+        #[structopt(
+            short,
+            long,
+            help = "expiration date (yyyy-mm-dd in UTC, or RFC 3339 with an offset)"
+        )]
         expires_at: Option<ExpiresAt>,
+        #[structopt(long, help = "never expire", conflicts_with = "expires-at")]
+        no_expiry: bool,
         #[structopt(short, long, help = "title")]
         title: Option<Title>,
+        #[structopt(long, help = "current password, unless the API key created the clip")]
+        current_password: Option<String>,
+    },
+    Delete {
+        shortcode: ShortCode,
+        #[structopt(long, help = "current password, unless the API key created the clip")]
+        current_password: Option<String>,
     },
 }
 
@@ -47,32 +72,90 @@ struct Opt {
     api_key: ApiKey,
 }
 
-fn get_clip(addr: &str, ask_svc: GetClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
+/// Decodes a successful response, or turns the API [`ErrorBody`] into an error.
+fn parse<T: DeserializeOwned>(response: reqwest::blocking::Response) -> Result<T, Box<dyn Error>> {
+    if response.status().is_success() {
+        Ok(response.json()?)
+    } else {
+        let body: ErrorBody = response.json()?;
+        let field = body.field.map(|f| format!(" [{}]", f)).unwrap_or_default();
+        Err(format!(
+            "{}{}: {} (request {})",
+            body.code, field, body.message, body.request_id
+        )
+        .into())
+    }
+}
+
+fn get_clip(
+    addr: &str,
+    shortcode: &str,
+    password: Option<String>,
+    api_key: ApiKey,
+) -> Result<ClipResponse, Box<dyn Error>> {
     let client = reqwest::blocking::Client::builder().build()?;
-    let addr = format!("{}/api/clip/{}", addr, ask_svc.shortcode.into_inner());
+    let addr = format!("{}/api/v1/clip/{}", addr, shortcode);
     let mut request = client.get(addr);
-    request = match ask_svc.password.into_inner() {
-        Some(password) => request.header(reqwest::header::COOKIE, format!("password={}", password)),
+    request = match password {
+        Some(password) => request.header(CLIP_PASSWORD_HEADER, password),
         None => request,
     };
     request = request.header(API_KEY_HEADER, api_key.to_base64());
-    Ok(request.send()?.json()?)
+    parse(request.send()?)
 }
 
-fn new_clip(addr: &str, ask_svc: NewClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
+fn new_clip(
+    addr: &str,
+    req: NewClipRequest,
+    api_key: ApiKey,
+) -> Result<ClipResponse, Box<dyn Error>> {
     let client = reqwest::blocking::Client::builder().build()?;
-    let addr = format!("{}/api/clip", addr);
+    let addr = format!("{}/api/v1/clip", addr);
     let mut request = client.post(addr);
     request = request.header(API_KEY_HEADER, api_key.to_base64());
-    Ok(request.json(&ask_svc).send()?.json()?)
+    parse(request.json(&req).send()?)
+}
+
+fn patch_clip(
+    addr: &str,
+    shortcode: &str,
+    req: PatchClipRequest,
+    current_password: Option<String>,
+    api_key: ApiKey,
+) -> Result<ClipResponse, Box<dyn Error>> {
+    let client = reqwest::blocking::Client::builder().build()?;
+    let addr = format!("{}/api/v1/clip/{}", addr, shortcode);
+    let mut request = client.patch(addr);
+    request = request.header(API_KEY_HEADER, api_key.to_base64());
+    if let Some(password) = current_password {
+        request = request.header(CLIP_PASSWORD_HEADER, password);
+    }
+    request = request.header(
+        reqwest::header::CONTENT_TYPE,
+        "application/merge-patch+json",
+    );
+    parse(request.body(serde_json::to_string(&req)?).send()?)
 }
 
-fn update_clip(addr: &str, ask_svc: UpdateClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
+fn delete_clip(
+    addr: &str,
+    shortcode: &str,
+    current_password: Option<String>,
+    api_key: ApiKey,
+) -> Result<(), Box<dyn Error>> {
     let client = reqwest::blocking::Client::builder().build()?;
-    let addr = format!("{}/api/clip", addr);
-    let mut request = client.put(addr);
+    let addr = format!("{}/api/v1/clip/{}", addr, shortcode);
+    let mut request = client.delete(addr);
     request = request.header(API_KEY_HEADER, api_key.to_base64());
-    Ok(request.json(&ask_svc).send()?.json()?)
+    if let Some(password) = current_password {
+        request = request.header(CLIP_PASSWORD_HEADER, password);
+    }
+    let response = request.send()?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        parse(response)
+    }
 }
 
 fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
@@ -81,11 +164,7 @@ fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
             shortcode,
             password,
         } => {
-            let req = GetClip {
-                password: Password::new(password.unwrap_or_default())?,
-                shortcode,
-            };
-            let clip = get_clip(opt.addr.as_str(), req, opt.api_key)?;
+            let clip = get_clip(opt.addr.as_str(), shortcode.as_str(), password, opt.api_key)?;
             println!("{:#?}", clip);
             Ok(())
         }
@@ -94,12 +173,17 @@ fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
             content,
             password,
             expires_at,
+            reuse_existing,
         } => {
-            let req = NewClip {
-                title: title.unwrap_or_default(),
-                content: Content::new(content.as_str())?,
-                expires_at: expires_at.unwrap_or_default(),
-                password: password.unwrap_or_default(),
+            let req = NewClipRequest {
+                title: title.unwrap_or_default().into_inner(),
+                content: Content::new(content.as_str())?.into_inner(),
+                expires_at: expires_at
+                    .unwrap_or_default()
+                    .into_inner()
+                    .map(|time| time.into_inner()),
+                password: password.unwrap_or_default().into_inner(),
+                reuse_existing,
             };
             let clip = new_clip(opt.addr.as_str(), req, opt.api_key)?;
             println!("{:#?}", clip);
@@ -109,26 +193,52 @@ fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
             title,
             content,
             password,
+            no_password,
             expires_at,
+            no_expiry,
             shortcode,
+            current_password,
         } => {
-            let password = password.unwrap_or_default();
-            let svc_req = GetClip {
-                password: password.clone(),
-                shortcode: shortcode.clone(),
-            };
-            let original_clip = get_clip(opt.addr.as_str(), svc_req, opt.api_key.clone())?;
-            let svc_req = UpdateClip {
-                title: title.unwrap_or(original_clip.title),
-                content: Content::new(content.as_str())?,
-                expires_at: expires_at.unwrap_or(original_clip.expires_at),
-                password,
-                shortcode,
+            let req = PatchClipRequest {
+                title: title.map(Title::into_inner),
+                content: content
+                    .map(|content| Content::new(content.as_str()).map(Content::into_inner))
+                    .transpose()?
+                    .map(Some),
+                password: match password {
+                    Some(password) => Some(password.into_inner()),
+                    None if no_password => Some(None),
+                    None => None,
+                },
+                expires_at: match expires_at {
+                    Some(expires_at) => Some(expires_at.into_inner().map(|time| time.into_inner())),
+                    None if no_expiry => Some(None),
+                    None => None,
+                },
             };
-            let clip = update_clip(opt.addr.as_str(), svc_req, opt.api_key)?;
+            let clip = patch_clip(
+                opt.addr.as_str(),
+                shortcode.as_str(),
+                req,
+                current_password,
+                opt.api_key,
+            )?;
             println!("{:#?}", clip);
             Ok(())
         }
+        Command::Delete {
+            shortcode,
+            current_password,
+        } => {
+            delete_clip(
+                opt.addr.as_str(),
+                shortcode.as_str(),
+                current_password,
+                opt.api_key,
+            )?;
+            println!("deleted {}", shortcode.as_str());
+            Ok(())
+        }
     }
 }
 
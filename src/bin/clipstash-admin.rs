@@ -0,0 +1,189 @@
+use clipstash::data::{snapshot, DatabasePool, MIGRATOR};
+use clipstash::service::action;
+use clipstash::service::backup::{self, OnConflict};
+use clipstash::service::import::{self, Format};
+use dotenv::dotenv;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Write every clip, including the trash, and every API key to a backup archive.
+    Backup {
+        #[structopt(parse(from_os_str), help = "archive to write, e.g. clipstash.tar")]
+        archive: PathBuf,
+    },
+    /// Restore clips and API keys from a backup archive; records already present are kept.
+    Restore {
+        #[structopt(parse(from_os_str), help = "archive to read")]
+        archive: PathBuf,
+        #[structopt(
+            long,
+            default_value = "rename",
+            help = "what to do with clips whose shortcode is taken: rename or skip"
+        )]
+        on_conflict: OnConflict,
+    },
+    /// Import clips from a directory of files, or a JSON or CSV export of another service.
+    Import {
+        #[structopt(parse(from_os_str), help = "directory or export file to read")]
+        source: PathBuf,
+        #[structopt(
+            long,
+            help = "dir, json or csv; by default told from the directory or file extension"
+        )]
+        format: Option<Format>,
+        #[structopt(long, help = "only report what would be imported")]
+        dry_run: bool,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "clipstash-admin", about = "ClipStash administration")]
+struct Opt {
+    #[structopt(subcommand)]
+    command: Command,
+    #[structopt(long, env = "DATABASE_URL", default_value = "sqlite:data.db")]
+    database: String,
+}
+
+/// The file of a `sqlite:` connection string.
+fn database_path(database: &str) -> &Path {
+    let path = database
+        .trim_start_matches("sqlite://")
+        .trim_start_matches("sqlite:");
+    Path::new(path.split('?').next().unwrap_or(path))
+}
+
+/// Creates `path` readable and writable by the owner only, as the archive holds every clip.
+fn create_private(path: &Path) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+async fn backup(database: &str, archive: &Path) -> Result<(), Box<dyn Error>> {
+    // Read from a snapshot, so that the server can keep writing meanwhile. The snapshot
+    // holds every clip too, so it goes in an owner-only directory removed when dropped.
+    let dir = tempfile::Builder::new()
+        .prefix("clipstash-backup")
+        .tempdir()?;
+    let copy = dir.path().join("snapshot.db");
+    snapshot(database_path(database), &copy)?;
+    let options = SqliteConnectOptions::new().filename(&copy);
+    let pool: DatabasePool = SqlitePoolOptions::new().connect_with(options).await?;
+    // Keys the server has not hashed yet must not reach the archive as they are.
+    action::hash_legacy_api_keys(&pool).await?;
+    let writer = BufWriter::new(create_private(archive)?);
+    let manifest = backup::write_archive(&pool, writer).await?;
+    pool.close().await;
+    println!(
+        "backed up {} clips and {} API keys to {}",
+        manifest.clips,
+        manifest.api_keys,
+        archive.display()
+    );
+    Ok(())
+}
+
+async fn restore(
+    database: &str,
+    archive: &Path,
+    on_conflict: OnConflict,
+) -> Result<(), Box<dyn Error>> {
+    let options = SqliteConnectOptions::from_str(database)?.create_if_missing(true);
+    let pool: DatabasePool = SqlitePoolOptions::new().connect_with(options).await?;
+    MIGRATOR.run(&pool).await?;
+    let reader = BufReader::new(File::open(archive)?);
+    let (manifest, report) = backup::restore_archive(&pool, reader, on_conflict).await?;
+    println!(
+        "restored backup from {}: {} clips ({} already present, {} skipped), {} API keys ({} already present)",
+        manifest.created_at.into_inner(),
+        report.clips_restored,
+        report.clips_present,
+        report.clips_skipped,
+        report.api_keys_restored,
+        report.api_keys_present
+    );
+    for renamed in report.renamed {
+        println!(
+            "shortcode {} was taken, restored as {}",
+            renamed.from, renamed.to
+        );
+    }
+    Ok(())
+}
+
+async fn import(
+    database: &str,
+    source: &Path,
+    format: Option<Format>,
+    dry_run: bool,
+) -> Result<(), Box<dyn Error>> {
+    let format = match format {
+        Some(format) => format,
+        None => Format::detect(source)?,
+    };
+    let entries = import::read_export(source, format)?;
+    let options = SqliteConnectOptions::from_str(database)?.create_if_missing(!dry_run);
+    let pool: DatabasePool = SqlitePoolOptions::new().connect_with(options).await?;
+    if !dry_run {
+        MIGRATOR.run(&pool).await?;
+    }
+    let report = import::import(entries, dry_run, &pool).await?;
+    for conflict in &report.conflicts {
+        println!(
+            "{}: shortcode {} is {}, {} as {}",
+            conflict.source,
+            conflict.shortcode,
+            conflict.reason,
+            if dry_run { "would import" } else { "imported" },
+            conflict.imported_as
+        );
+    }
+    for failure in &report.failed {
+        println!("{}: {}", failure.source, failure.error);
+    }
+    println!(
+        "{} {} clips ({} under a new shortcode), {} records failed validation",
+        if dry_run { "would import" } else { "imported" },
+        report.imported,
+        report.conflicts.len(),
+        report.failed.len()
+    );
+    Ok(())
+}
+
+fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
+    let rt = tokio::runtime::Runtime::new()?;
+    rt.block_on(async {
+        match opt.command {
+            Command::Backup { archive } => backup(&opt.database, &archive).await,
+            Command::Restore {
+                archive,
+                on_conflict,
+            } => restore(&opt.database, &archive, on_conflict).await,
+            Command::Import {
+                source,
+                format,
+                dry_run,
+            } => import(&opt.database, &source, format, dry_run).await,
+        }
+    })
+}
+
+fn main() {
+    dotenv().ok();
+    let opt = Opt::from_args();
+    if let Err(e) = run(opt) {
+        eprintln!("An error occurred: {}", e);
+        std::process::exit(1);
+    }
+}
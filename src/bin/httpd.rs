@@ -1,9 +1,22 @@
+use clipstash::data::codec::Compression;
 use clipstash::data::AppDatabase;
-use clipstash::domain::maintenance::Maintenance;
+use clipstash::domain::clip::field::Limits;
+use clipstash::domain::maintenance::{jobs, Maintenance};
+use clipstash::domain::Quota;
+use clipstash::service::action;
 use clipstash::web::hit_counter::HitCounter;
+use clipstash::web::key_generation::KeyGeneration;
+use clipstash::web::key_usage::KeyUsage;
+use clipstash::web::password_attempts::PasswordAttempts;
+use clipstash::web::rate_limit::{Limit, RateLimiter};
 use clipstash::web::renderer::Renderer;
+use clipstash::web::ApiKey;
 use dotenv::dotenv;
-use std::path::PathBuf;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
@@ -13,25 +26,162 @@ struct Opt {
     connection_string: String,
     #[structopt(short, long, parse(from_os_str), default_value = "templates/")]
     template_directory: PathBuf,
+    /// API key granted the admin scope on startup; required to create further keys.
+    #[structopt(long, env = "CLIPSTASH_ADMIN_KEY", hide_env_values = true)]
+    admin_key: Option<ApiKey>,
+    /// File a generated admin key is written to, readable by the owner only.
+    #[structopt(
+        long,
+        env = "CLIPSTASH_ADMIN_KEY_FILE",
+        parse(from_os_str),
+        default_value = "admin-key.txt"
+    )]
+    admin_key_file: PathBuf,
+    /// Whether visitors may generate their own API keys on the web page.
+    #[structopt(
+        long,
+        env = "CLIPSTASH_SELF_SERVICE_KEYS",
+        parse(try_from_str),
+        default_value = "true"
+    )]
+    self_service_keys: bool,
+    /// How many keys each client may generate per hour on the web page.
+    #[structopt(long, env = "CLIPSTASH_KEYS_PER_HOUR", default_value = "5")]
+    keys_per_hour: u32,
+    /// Clips each client may create, e.g. `10/min`.
+    #[structopt(long, env = "CLIPSTASH_RATE_LIMIT_CREATE", default_value = "10/min")]
+    rate_limit_create: Limit,
+    /// Clips each client may read, e.g. `120/min`.
+    #[structopt(long, env = "CLIPSTASH_RATE_LIMIT_READ", default_value = "120/min")]
+    rate_limit_read: Limit,
+    /// Clip passwords each client may try, e.g. `5/min`.
+    #[structopt(long, env = "CLIPSTASH_RATE_LIMIT_PASSWORD", default_value = "5/min")]
+    rate_limit_password: Limit,
+    /// Comma separated proxy addresses whose `X-Forwarded-For` header is trusted.
+    #[structopt(long, env = "CLIPSTASH_TRUSTED_PROXIES", use_delimiter = true)]
+    trusted_proxies: Vec<IpAddr>,
+    /// Live clips each API key may own, unless the key sets its own quota.
+    #[structopt(long, env = "CLIPSTASH_QUOTA_CLIPS")]
+    quota_clips: Option<u64>,
+    /// Bytes of clip content each API key may store, unless the key sets its own quota.
+    #[structopt(long, env = "CLIPSTASH_QUOTA_BYTES")]
+    quota_bytes: Option<u64>,
+    /// Clips each API key may create per day, unless the key sets its own quota.
+    #[structopt(long, env = "CLIPSTASH_QUOTA_CLIPS_PER_DAY")]
+    quota_clips_per_day: Option<u64>,
+    /// Largest clip content accepted, in bytes.
+    #[structopt(long, env = "CLIPSTASH_MAX_CONTENT_BYTES", default_value = "1048576")]
+    max_content_bytes: usize,
+    /// Longest clip title accepted, in characters.
+    #[structopt(long, env = "CLIPSTASH_MAX_TITLE_CHARS", default_value = "200")]
+    max_title_chars: usize,
+    /// Clip content larger than this many bytes is stored compressed with zstd.
+    #[structopt(long, env = "CLIPSTASH_COMPRESS_ABOVE_BYTES", default_value = "4096")]
+    compress_above_bytes: usize,
+    /// The zstd level to compress clip content at, from 1 (fastest) to 19.
+    #[structopt(long, env = "CLIPSTASH_COMPRESSION_LEVEL", default_value = "3")]
+    compression_level: i32,
+    /// Hours that deleted and expired clips stay in the trash before they are purged.
+    #[structopt(long, env = "CLIPSTASH_TRASH_GRACE_HOURS", default_value = "168")]
+    trash_grace_hours: u64,
+    /// Days that expired API keys are kept before maintenance deletes them.
+    #[structopt(long, env = "CLIPSTASH_STALE_KEY_DAYS", default_value = "30")]
+    stale_key_days: u64,
+    /// Longest random delay added to each maintenance job run, in seconds.
+    #[structopt(long, env = "CLIPSTASH_MAINTENANCE_JITTER_SECS", default_value = "30")]
+    maintenance_jitter_secs: u64,
+}
+
+/// Writes `contents` to a new file at `path`, readable and writable by the owner only.
+fn save_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    writeln!(options.open(path)?, "{}", contents)
 }
 
 fn main() {
     dotenv().ok();
     let opt = Opt::from_args();
+    let limits = Limits {
+        max_content_bytes: opt.max_content_bytes,
+        max_title_chars: opt.max_title_chars,
+    };
+    limits
+        .install()
+        .expect("field limits must be installed before any clip is parsed");
+    let compression = Compression {
+        threshold: opt.compress_above_bytes,
+        level: opt.compression_level,
+    };
+    compression
+        .install()
+        .expect("compression must be installed before any clip is stored");
     let rt = tokio::runtime::Runtime::new().expect("failed to spawn tokio runtime");
 
     let handle = rt.handle().clone();
     let renderer = Renderer::new(opt.template_directory.clone());
 
-    let database = rt.block_on(async move { AppDatabase::new(&opt.connection_string).await });
-    let maintenance = Maintenance::spawn(database.get_pool().clone(), handle.clone());
+    let database = rt.block_on(async { AppDatabase::new(&opt.connection_string).await });
+    match rt.block_on(action::hash_legacy_api_keys(database.get_pool())) {
+        Ok(0) => (),
+        Ok(hashed) => println!("Hashed {} API keys saved before keys were hashed", hashed),
+        Err(e) => eprintln!("error hashing API keys: {}", e),
+    }
+    let bootstrap = action::bootstrap_admin_key(opt.admin_key.clone(), database.get_pool());
+    match rt.block_on(bootstrap) {
+        Ok(Some(api_key)) => match save_private(&opt.admin_key_file, &api_key.to_base64()) {
+            Ok(()) => println!(
+                "Generated an admin API key and saved it to {} (set CLIPSTASH_ADMIN_KEY to choose your own)",
+                opt.admin_key_file.display()
+            ),
+            Err(e) => eprintln!(
+                "error saving the generated admin API key to {}: {}; set CLIPSTASH_ADMIN_KEY instead",
+                opt.admin_key_file.display(),
+                e
+            ),
+        },
+        Ok(None) => (),
+        Err(e) => eprintln!("error creating admin API key: {}", e),
+    }
+    let rate_limiter = RateLimiter::new(
+        opt.rate_limit_create,
+        opt.rate_limit_read,
+        opt.rate_limit_password,
+    )
+    .with_trusted_proxies(opt.trusted_proxies.clone());
+    let key_generation = match opt.self_service_keys {
+        true => KeyGeneration::enabled(opt.keys_per_hour),
+        false => KeyGeneration::disabled(),
+    };
+    let settings = jobs::Settings {
+        trash_grace: Duration::from_secs(opt.trash_grace_hours.saturating_mul(60 * 60)),
+        stale_key_grace: Duration::from_secs(opt.stale_key_days.saturating_mul(24 * 60 * 60)),
+    };
+    let maintenance = Maintenance::spawn(
+        database.get_pool().clone(),
+        handle.clone(),
+        jobs::builtin(&settings),
+        Duration::from_secs(opt.maintenance_jitter_secs),
+    );
 
     let hit_counter = HitCounter::new(database.get_pool().clone(), handle.clone());
+    let key_usage = KeyUsage::new(database.get_pool().clone(), handle.clone());
     let config = clipstash::RocketConfig {
         renderer,
         database,
         hit_counter,
-        maintenance,
+        key_usage,
+        key_generation,
+        rate_limiter,
+        password_attempts: PasswordAttempts::default(),
+        quota: Quota {
+            max_clips: opt.quota_clips,
+            max_bytes: opt.quota_bytes,
+            max_clips_per_day: opt.quota_clips_per_day,
+        },
+        maintenance: maintenance.clone(),
     };
 
     let _ = rt.block_on(async move {
@@ -40,4 +190,5 @@ fn main() {
             .await
             .expect("failed to launch rocket server")
     });
+    rt.block_on(maintenance.shutdown());
 }
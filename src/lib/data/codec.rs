@@ -0,0 +1,147 @@
+//! Encoding of stored clip content.
+//!
+//! Content above the [`Compression`] threshold is stored as a zstd frame, marked by the
+//! `codec` column of the `blobs` table. Everything else is stored as plain text. Blobs
+//! stored before compression have no marker, and are compressed by a maintenance job.
+
+use std::io::{Cursor, Read};
+use std::sync::OnceLock;
+
+/// How a blob's content is stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    /// UTF-8 text, as written.
+    Plain,
+    /// A zstd frame of the UTF-8 text.
+    Zstd,
+}
+
+impl Codec {
+    /// Reads the `codec` column, where `NULL` means plain text stored before compression.
+    pub fn from_marker(marker: Option<&str>) -> std::io::Result<Self> {
+        match marker {
+            None | Some("plain") => Ok(Self::Plain),
+            Some("zstd") => Ok(Self::Zstd),
+            Some(other) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unknown content codec `{}`", other),
+            )),
+        }
+    }
+
+    /// The value of the `codec` column.
+    pub fn marker(self) -> &'static str {
+        match self {
+            Self::Plain => "plain",
+            Self::Zstd => "zstd",
+        }
+    }
+}
+
+/// When clip content is compressed.
+///
+/// Changing the settings only affects content stored afterwards. Each blob records its
+/// [`Codec`], so content stored under earlier settings still reads back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Compression {
+    /// Content longer than this many bytes is compressed.
+    pub threshold: usize,
+    /// The zstd compression level.
+    pub level: i32,
+}
+
+/// Compresses content over 4 KiB at zstd's default level.
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            threshold: 4 * 1024,
+            level: zstd::DEFAULT_COMPRESSION_LEVEL,
+        }
+    }
+}
+
+static COMPRESSION: OnceLock<Compression> = OnceLock::new();
+
+impl Compression {
+    /// The settings in effect: those installed at startup, or the defaults.
+    pub fn get() -> Compression {
+        *COMPRESSION.get_or_init(Compression::default)
+    }
+
+    /// Puts these settings into effect for the rest of the process.
+    ///
+    /// Fails, returning `self`, if settings are already in effect because they were
+    /// installed or used before.
+    pub fn install(self) -> Result<(), Compression> {
+        COMPRESSION.set(self)
+    }
+
+    /// Encodes `content` for storage, compressing it if it is over the threshold and
+    /// compression makes it smaller.
+    pub fn encode(&self, content: &str) -> (Codec, Vec<u8>) {
+        if content.len() > self.threshold {
+            if let Ok(compressed) = zstd::encode_all(content.as_bytes(), self.level) {
+                if compressed.len() < content.len() {
+                    return (Codec::Zstd, compressed);
+                }
+            }
+        }
+        (Codec::Plain, content.as_bytes().to_vec())
+    }
+}
+
+/// Reads stored `bytes`, decoding them as they are read.
+pub fn reader(codec: Codec, bytes: Vec<u8>) -> std::io::Result<Box<dyn Read + Send + Sync>> {
+    Ok(match codec {
+        Codec::Plain => Box::new(Cursor::new(bytes)),
+        Codec::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(Cursor::new(
+            bytes,
+        ))?),
+    })
+}
+
+/// Decodes stored `bytes` into the original text.
+pub fn decode(codec: Codec, bytes: Vec<u8>) -> std::io::Result<String> {
+    let bytes = match codec {
+        Codec::Plain => bytes,
+        Codec::Zstd => zstd::decode_all(bytes.as_slice())?,
+    };
+    String::from_utf8(bytes).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn compresses_content_over_the_threshold() {
+        let compression = Compression {
+            threshold: 64,
+            level: 3,
+        };
+        let short = "a short clip";
+        assert_eq!(
+            compression.encode(short),
+            (Codec::Plain, short.as_bytes().to_vec())
+        );
+
+        let log = "GET /clip/raw/abc 200\n".repeat(100);
+        let (codec, bytes) = compression.encode(&log);
+        assert_eq!(codec, Codec::Zstd);
+        assert!(bytes.len() < log.len() / 10);
+        assert_eq!(decode(codec, bytes.clone()).unwrap(), log);
+        let mut streamed = String::new();
+        reader(codec, bytes)
+            .unwrap()
+            .read_to_string(&mut streamed)
+            .unwrap();
+        assert_eq!(streamed, log);
+
+        assert_eq!(
+            Codec::from_marker(Some(codec.marker())).unwrap(),
+            Codec::Zstd
+        );
+        assert_eq!(Codec::from_marker(None).unwrap(), Codec::Plain);
+        assert!(Codec::from_marker(Some("lz4")).is_err());
+    }
+}
@@ -1,17 +1,26 @@
 use derive_more::{Display, From};
 use serde::{Deserialize, Serialize};
+use sqlx::migrate::Migrator;
 use sqlx::Sqlite;
+use std::path::Path;
 use std::str::FromStr;
+use std::time::Duration;
 use thiserror::Error;
 use uuid::Uuid;
 
+pub mod codec;
 pub mod model;
 pub mod query;
 
+/// The migrations that ship with this build of the application.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
 #[derive(Debug, Error)]
 pub enum DataError {
     #[error("database error: {0}")]
     DatabaseError(#[from] sqlx::Error),
+    #[error("version mismatch, clip is at version {0}")]
+    VersionMismatch(i64),
 }
 pub struct Database<D: sqlx::Database>(sqlx::Pool<D>);
 
@@ -29,11 +38,37 @@ impl Database<Sqlite> {
         }
     }
 
+    /// Opens a private in-memory database.
+    ///
+    /// Every SQLite connection to `:memory:` sees its own database, so the pool is
+    /// limited to a single connection that is never recycled.
+    pub async fn in_memory() -> Self {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect(":memory:")
+            .await
+            .expect("failed to open in-memory database");
+        Self(pool)
+    }
+
     pub fn get_pool(&self) -> &DatabasePool {
         &self.0
     }
 }
 
+/// Copies the SQLite database at `source` to `destination` using SQLite's online backup
+/// API, which gives a consistent snapshot even while the server writes to it.
+pub fn snapshot(source: &Path, destination: &Path) -> Result<(), rusqlite::Error> {
+    use rusqlite::{backup::Backup, Connection, OpenFlags};
+
+    let source = Connection::open_with_flags(source, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut destination = Connection::open(destination)?;
+    let backup = Backup::new(&source, &mut destination)?;
+    backup.run_to_completion(256, Duration::from_millis(10), None)
+}
+
 pub type DatabasePool = sqlx::sqlite::SqlitePool;
 pub type AppDatabase = Database<Sqlite>;
 pub type Transaction<'a> = sqlx::Transaction<'a, Sqlite>;
@@ -1,20 +1,51 @@
+use crate::data::codec::{self, Codec};
 use crate::data::DatabaseId;
+use crate::domain::Quota;
 use crate::{ClipError, ShortCode, Time};
-use chrono::{NaiveDateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use derive_more::From;
-use std::convert::TryFrom;
+use serde::{Deserialize, Serialize};
+use std::convert::{TryFrom, TryInto};
 use std::str::FromStr;
 
 #[derive(Debug, sqlx::FromRow)]
 pub struct Clip {
     pub(in crate::data) id: String,
     pub(in crate::data) title: Option<String>,
-    pub content: String, // public for testing
+    pub content: Vec<u8>, // public for testing
+    /// How `content` is encoded, as stored in the `codec` column of `blobs`.
+    pub(in crate::data) codec: Option<String>,
     pub(in crate::data) shortcode: String,
     pub(in crate::data) created_at: NaiveDateTime,
     pub(in crate::data) expires_at: Option<NaiveDateTime>,
     pub(in crate::data) password: Option<String>,
     pub(in crate::data) hits: i64,
+    pub(in crate::data) version: i64,
+    pub(in crate::data) owner_key: Option<Vec<u8>>,
+    pub(in crate::data) edit_token_hash: Option<String>,
+    pub(in crate::data) deleted_at: Option<NaiveDateTime>,
+}
+
+impl TryFrom<Clip> for crate::domain::clip::TrashedClip {
+    type Error = ClipError;
+
+    fn try_from(clip: Clip) -> Result<Self, Self::Error> {
+        let deleted_at = clip
+            .deleted_at
+            .map(Time::from_naive_utc)
+            .ok_or_else(|| ClipError::InvalidDate("clip is not in the trash".to_owned()))?;
+        Ok(Self {
+            clip: clip.try_into()?,
+            deleted_at,
+        })
+    }
+}
+
+impl Clip {
+    fn codec(&self) -> Result<Codec, ClipError> {
+        Codec::from_marker(self.codec.as_deref())
+            .map_err(|e| ClipError::InvalidContent(e.to_string()))
+    }
 }
 
 impl TryFrom<Clip> for crate::domain::Clip {
@@ -23,15 +54,36 @@ impl TryFrom<Clip> for crate::domain::Clip {
     fn try_from(clip: Clip) -> Result<Self, Self::Error> {
         use crate::domain::clip::field;
 
+        let content = codec::decode(clip.codec()?, clip.content)
+            .map_err(|e| ClipError::InvalidContent(e.to_string()))?;
         Ok(Self {
             id: field::ClipId::new(DatabaseId::from_str(clip.id.as_str())?),
-            title: field::Title::new(clip.title),
-            content: field::Content::new(clip.content.as_str())?,
+            title: field::Title::stored(clip.title),
+            content: field::Content::stored(content),
             shortcode: field::ShortCode::from(clip.shortcode),
             created_at: field::CreatedAt::new(Time::from_naive_utc(clip.created_at)),
-            expires_at: field::ExpiresAt::new(clip.expires_at.map(|dt| Time::from_naive_utc(dt))),
+            expires_at: field::ExpiresAt::new(clip.expires_at.map(Time::from_naive_utc)),
             password: field::Password::new(clip.password.unwrap_or_default())?,
             hits: field::Hits::new(clip.hits),
+            version: field::Version::new(clip.version),
+            owner: field::Owner::new(clip.owner_key, clip.edit_token_hash),
+        })
+    }
+}
+
+impl TryFrom<Clip> for crate::domain::clip::StreamedClip {
+    type Error = ClipError;
+
+    fn try_from(clip: Clip) -> Result<Self, Self::Error> {
+        use crate::domain::clip::field;
+
+        let content = codec::reader(clip.codec()?, clip.content)
+            .map_err(|e| ClipError::InvalidContent(e.to_string()))?;
+        Ok(Self {
+            shortcode: field::ShortCode::from(clip.shortcode),
+            expires_at: field::ExpiresAt::new(clip.expires_at.map(Time::from_naive_utc)),
+            password: field::Password::new(clip.password.unwrap_or_default())?,
+            content,
         })
     }
 }
@@ -65,10 +117,13 @@ pub struct NewClip {
     pub(in crate::data) shortcode: String,
     pub(in crate::data) created_at: i64,
     pub(in crate::data) expires_at: Option<i64>,
+    pub(in crate::data) owner_key: Option<Vec<u8>>,
+    pub(in crate::data) edit_token_hash: Option<String>,
 }
 
 impl From<crate::service::ask::NewClip> for NewClip {
     fn from(req: crate::service::ask::NewClip) -> Self {
+        let (owner_key, edit_token_hash) = req.owner.into_inner();
         Self {
             id: DatabaseId::new().into(),
             title: req.title.into_inner(),
@@ -77,16 +132,33 @@ impl From<crate::service::ask::NewClip> for NewClip {
             shortcode: ShortCode::default().into(),
             created_at: Utc::now().timestamp(),
             expires_at: req.expires_at.into_inner().map(|time| time.to_timestamp()),
+            owner_key,
+            edit_token_hash,
         }
     }
 }
 
+impl NewClip {
+    /// Saves the clip under `shortcode` instead of a generated one.
+    pub fn with_shortcode(mut self, shortcode: ShortCode) -> Self {
+        self.shortcode = shortcode.into();
+        self
+    }
+
+    /// Records the clip as created at `created_at` instead of now.
+    pub fn with_created_at(mut self, created_at: Time) -> Self {
+        self.created_at = created_at.to_timestamp();
+        self
+    }
+}
+
 pub struct UpdateClip {
     pub(in crate::data) title: Option<String>,
     pub(in crate::data) content: String,
     pub(in crate::data) password: Option<String>,
     pub(in crate::data) shortcode: String,
     pub(in crate::data) expires_at: Option<i64>,
+    pub(in crate::data) version: Option<i64>,
 }
 
 impl From<crate::service::ask::UpdateClip> for UpdateClip {
@@ -97,6 +169,242 @@ impl From<crate::service::ask::UpdateClip> for UpdateClip {
             password: req.password.into_inner(),
             shortcode: req.shortcode.into_inner(),
             expires_at: req.expires_at.into_inner().map(|time| time.to_timestamp()),
+            version: req.version.map(|version| version.into_inner()),
+        }
+    }
+}
+
+/// Columns to change in a clip; `None` leaves the column untouched.
+pub struct PatchClip {
+    pub(in crate::data) shortcode: String,
+    pub(in crate::data) title: Option<Option<String>>,
+    pub(in crate::data) content: Option<String>,
+    pub(in crate::data) password: Option<Option<String>>,
+    pub(in crate::data) expires_at: Option<Option<i64>>,
+    pub(in crate::data) version: Option<i64>,
+}
+
+impl From<crate::service::ask::PatchClip> for PatchClip {
+    fn from(req: crate::service::ask::PatchClip) -> Self {
+        Self {
+            shortcode: req.shortcode.into_inner(),
+            title: req.title.map(|title| title.into_inner()),
+            content: req.content.map(|content| content.into_inner()),
+            password: req.password.map(|password| password.into_inner()),
+            expires_at: req
+                .expires_at
+                .map(|expires_at| expires_at.into_inner().map(|time| time.to_timestamp())),
+            version: req.version.map(|version| version.into_inner()),
+        }
+    }
+}
+
+pub struct DeleteClip {
+    pub(in crate::data) shortcode: String,
+}
+
+impl From<crate::service::ask::DeleteClip> for DeleteClip {
+    fn from(req: crate::service::ask::DeleteClip) -> Self {
+        Self {
+            shortcode: req.shortcode.into_inner(),
+        }
+    }
+}
+
+/// The metadata of an API key; the key bytes are never read back.
+#[derive(Debug, sqlx::FromRow)]
+pub struct ApiKeyInfo {
+    pub(in crate::data) key_id: String,
+    pub(in crate::data) name: String,
+    pub(in crate::data) scopes: String,
+    pub(in crate::data) created_at: NaiveDateTime,
+    pub(in crate::data) last_used_at: Option<NaiveDateTime>,
+    pub(in crate::data) expires_at: Option<NaiveDateTime>,
+    pub(in crate::data) max_clips: Option<i64>,
+    pub(in crate::data) max_bytes: Option<i64>,
+    pub(in crate::data) max_clips_per_day: Option<i64>,
+}
+
+impl From<ApiKeyInfo> for crate::domain::ApiKeyInfo {
+    /// Scopes that cannot be parsed grant nothing, so a corrupt entry fails closed.
+    fn from(key: ApiKeyInfo) -> Self {
+        Self {
+            id: key.key_id,
+            name: key.name,
+            scopes: key.scopes.parse().unwrap_or_default(),
+            created_at: Time::from_naive_utc(key.created_at),
+            last_used_at: key.last_used_at.map(Time::from_naive_utc),
+            expires_at: key.expires_at.map(Time::from_naive_utc),
+            quota: Quota {
+                max_clips: key.max_clips.map(unsigned),
+                max_bytes: key.max_bytes.map(unsigned),
+                max_clips_per_day: key.max_clips_per_day.map(unsigned),
+            },
+        }
+    }
+}
+
+/// A stored count or quota limit. Negative values are never stored, so they allow nothing.
+fn unsigned(value: i64) -> u64 {
+    u64::try_from(value).unwrap_or(0)
+}
+
+/// The clips of an API key that count against its quota.
+#[derive(Debug, sqlx::FromRow)]
+pub struct Usage {
+    pub(in crate::data) clips: i64,
+    pub(in crate::data) bytes: i64,
+    pub(in crate::data) clips_today: i64,
+}
+
+impl From<Usage> for crate::domain::Usage {
+    fn from(usage: Usage) -> Self {
+        Self {
+            clips: unsigned(usage.clips),
+            bytes: unsigned(usage.bytes),
+            clips_today: unsigned(usage.clips_today),
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct DailyStats {
+    pub(in crate::data) day: i64,
+    pub(in crate::data) clips_created: i64,
+    pub(in crate::data) live_clips: i64,
+    pub(in crate::data) live_bytes: i64,
+    pub(in crate::data) hits: i64,
+}
+
+impl From<DailyStats> for crate::domain::DailyStats {
+    fn from(stats: DailyStats) -> Self {
+        Self {
+            day: DateTime::from_timestamp(stats.day, 0)
+                .unwrap_or_default()
+                .into(),
+            clips_created: unsigned(stats.clips_created),
+            live_clips: unsigned(stats.live_clips),
+            live_bytes: unsigned(stats.live_bytes),
+            hits: unsigned(stats.hits),
+        }
+    }
+}
+
+pub struct ContentStorage {
+    pub(in crate::data) blobs: i64,
+    pub(in crate::data) size: i64,
+    pub(in crate::data) stored: i64,
+}
+
+impl From<ContentStorage> for crate::domain::ContentStorage {
+    fn from(storage: ContentStorage) -> Self {
+        Self {
+            blobs: unsigned(storage.blobs),
+            size: unsigned(storage.size),
+            stored: unsigned(storage.stored),
+        }
+    }
+}
+
+pub struct NewApiKey {
+    pub(in crate::data) key_id: String,
+    pub(in crate::data) name: String,
+    pub(in crate::data) scopes: String,
+    pub(in crate::data) created_at: i64,
+    pub(in crate::data) expires_at: Option<i64>,
+    pub(in crate::data) max_clips: Option<i64>,
+    pub(in crate::data) max_bytes: Option<i64>,
+    pub(in crate::data) max_clips_per_day: Option<i64>,
+}
+
+impl From<crate::service::ask::NewApiKey> for NewApiKey {
+    fn from(req: crate::service::ask::NewApiKey) -> Self {
+        Self {
+            key_id: format!("{:016x}", rand::random::<u64>()),
+            name: req.name,
+            scopes: req.scopes.to_string(),
+            created_at: Utc::now().timestamp(),
+            expires_at: req.expires_at.map(|time| time.to_timestamp()),
+            max_clips: req.quota.max_clips.map(stored_limit),
+            max_bytes: req.quota.max_bytes.map(stored_limit),
+            max_clips_per_day: req.quota.max_clips_per_day.map(stored_limit),
+        }
+    }
+}
+
+/// A quota limit as stored in SQLite, which has no unsigned integers.
+fn stored_limit(value: u64) -> i64 {
+    i64::try_from(value).unwrap_or(i64::MAX)
+}
+
+/// A clip row as written to and read from backups, with every column.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize, Deserialize)]
+pub struct ClipRecord {
+    pub id: String,
+    pub shortcode: String,
+    pub title: Option<String>,
+    pub content: String,
+    pub password: Option<String>,
+    pub created_at: i64,
+    pub expires_at: Option<i64>,
+    pub deleted_at: Option<i64>,
+    pub hits: i64,
+    pub version: i64,
+    /// The hash of the owning API key, like [`ApiKeyRecord::key_hash`].
+    #[serde(with = "base64_bytes::option")]
+    pub owner_key: Option<Vec<u8>>,
+    pub edit_token_hash: Option<String>,
+}
+
+/// An API key row as written to and read from backups, with every column.
+///
+/// Only the hash of the key is stored, as in the database.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow, Serialize, Deserialize)]
+pub struct ApiKeyRecord {
+    #[serde(with = "base64_bytes")]
+    pub key_hash: Vec<u8>,
+    pub key_id: String,
+    pub name: String,
+    pub scopes: String,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+    pub expires_at: Option<i64>,
+    pub max_clips: Option<i64>,
+    pub max_bytes: Option<i64>,
+    pub max_clips_per_day: Option<i64>,
+}
+
+/// Serializes raw bytes as base64 strings.
+mod base64_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        base64::decode(String::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            bytes: &Option<Vec<u8>>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match bytes {
+                Some(bytes) => super::serialize(bytes, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Vec<u8>>, D::Error> {
+            Option::<String>::deserialize(deserializer)?
+                .map(|encoded| base64::decode(encoded).map_err(serde::de::Error::custom))
+                .transpose()
         }
     }
 }
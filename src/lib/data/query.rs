@@ -1,73 +1,247 @@
 use super::model;
-use crate::data::{DataError, DatabasePool};
+use crate::data::codec::{self, Codec, Compression};
+use crate::data::{DataError, DatabasePool, Transaction};
 use crate::web::ApiKey;
 use crate::ShortCode;
-use sqlx::Row;
+use sha2::{Digest, Sha256};
+use sqlx::{Row, Sqlite};
 
 type Result<T> = std::result::Result<T, DataError>;
 
-pub async fn get_clip<M>(m: M, pool: &DatabasePool) -> Result<model::Clip>
+pub async fn get_clip<'e, M, E>(m: M, executor: E) -> Result<model::Clip>
 where
     M: Into<model::GetClip>,
+    E: sqlx::Executor<'e, Database = Sqlite>,
 {
     let m: model::GetClip = m.into();
     let shortcode = m.shortcode.as_str();
     Ok(sqlx::query_as!(
         model::Clip,
-        r#" SELECT * FROM clips WHERE shortcode = ?"#,
+        r#"
+        SELECT id, title, shortcode, created_at, expires_at, password, hits, version,
+            owner_key, edit_token_hash, deleted_at,
+            CAST(COALESCE((SELECT content FROM blobs WHERE hash = content_hash), content) AS BLOB)
+                as "content!: Vec<u8>",
+            (SELECT codec FROM blobs WHERE hash = content_hash) as "codec: String"
+        FROM clips WHERE shortcode = ? AND deleted_at IS NULL
+        "#,
         shortcode
     )
-    .fetch_one(pool)
+    .fetch_one(executor)
     .await?)
 }
 
 pub async fn new_clip<M>(m: M, pool: &DatabasePool) -> Result<model::Clip>
+where
+    M: Into<model::NewClip>,
+{
+    let mut transaction = pool.begin().await?;
+    let clip = new_clip_in(m, &mut transaction).await?;
+    transaction.commit().await?;
+    Ok(clip)
+}
+
+/// Creates a clip as part of `transaction`.
+pub async fn new_clip_in<M>(m: M, transaction: &mut Transaction<'_>) -> Result<model::Clip>
 where
     M: Into<model::NewClip>,
 {
     let m = m.into();
+    let content_hash = store_blob(&m.content, &mut *transaction).await?;
     let _ = sqlx::query!(
         r#"
         INSERT INTO clips
-        (id, title, content, password, shortcode, created_at, expires_at, hits)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        (id, title, content, content_hash, password, shortcode, created_at, expires_at, hits,
+            owner_key, edit_token_hash)
+        VALUES (?, ?, '', ?, ?, ?, ?, ?, ?, ?, ?)
         "#,
         m.id,
         m.title,
-        m.content,
+        content_hash,
         m.password,
         m.shortcode,
         m.created_at,
         m.expires_at,
-        0_i64
+        0_i64,
+        m.owner_key,
+        m.edit_token_hash
     )
-    .execute(pool)
+    .execute(&mut *transaction)
+    .await?;
+    get_clip(m.shortcode, transaction).await
+}
+
+/// The SHA-256 of `content` in hex, which is its key in the `blobs` table.
+fn content_hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+/// Stores `content` in the `blobs` table unless it is there already, returning its hash.
+///
+/// The blob is unreferenced until a clip uses it, so it must be used in the same
+/// `transaction` or it may be collected by [`delete_orphaned_blobs`].
+///
+/// Content over the [`Compression`] threshold is stored compressed.
+async fn store_blob(content: &str, transaction: &mut Transaction<'_>) -> Result<String> {
+    let hash = content_hash(content);
+    let (codec, stored) = Compression::get().encode(content);
+    let codec = codec.marker();
+    let size = content.len() as i64;
+    sqlx::query!(
+        r#"
+        INSERT INTO blobs (hash, content, codec, size) VALUES (?, ?, ?, ?)
+        ON CONFLICT (hash) DO NOTHING
+        "#,
+        hash,
+        stored,
+        codec,
+        size
+    )
+    .execute(transaction)
     .await?;
-    get_clip(m.shortcode, pool).await
+    Ok(hash)
 }
 
 pub async fn update_clip<M>(m: M, pool: &DatabasePool) -> Result<model::Clip>
+where
+    M: Into<model::UpdateClip>,
+{
+    let mut transaction = pool.begin().await?;
+    let clip = update_clip_in(m, &mut transaction).await?;
+    transaction.commit().await?;
+    Ok(clip)
+}
+
+/// Replaces a clip as part of `transaction`.
+pub async fn update_clip_in<M>(m: M, transaction: &mut Transaction<'_>) -> Result<model::Clip>
 where
     M: Into<model::UpdateClip>,
 {
     let m = m.into();
-    let _ = sqlx::query!(
-        r#"UPDATE clips SET title = ?, content = ?, password = ?, expires_at = ? WHERE shortcode = ?"#,
+    let content_hash = store_blob(&m.content, &mut *transaction).await?;
+    let result = sqlx::query!(
+        r#"
+        UPDATE clips SET
+            title = ?, content = '', content_hash = ?, password = ?, expires_at = ?,
+            version = version + 1
+        WHERE shortcode = ? AND deleted_at IS NULL AND (? IS NULL OR version = ?)
+        "#,
         m.title,
-        m.content,
+        content_hash,
         m.password,
         m.expires_at,
+        m.shortcode,
+        m.version,
+        m.version
+    )
+    .execute(&mut *transaction)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(version_mismatch(m.shortcode, transaction).await);
+    }
+    get_clip(m.shortcode, transaction).await
+}
+
+/// Explains why a conditional update matched no rows: the clip is missing or has moved on.
+async fn version_mismatch(shortcode: String, transaction: &mut Transaction<'_>) -> DataError {
+    match get_clip(shortcode, transaction).await {
+        Ok(clip) => DataError::VersionMismatch(clip.version),
+        Err(e) => e,
+    }
+}
+
+/// Updates only the columns supplied in the [`PatchClip`](model::PatchClip).
+pub async fn patch_clip<M>(m: M, pool: &DatabasePool) -> Result<model::Clip>
+where
+    M: Into<model::PatchClip>,
+{
+    let mut transaction = pool.begin().await?;
+    let clip = patch_clip_in(m, &mut transaction).await?;
+    transaction.commit().await?;
+    Ok(clip)
+}
+
+/// Updates only the columns supplied in the [`PatchClip`](model::PatchClip) as part of
+/// `transaction`.
+pub async fn patch_clip_in<M>(m: M, transaction: &mut Transaction<'_>) -> Result<model::Clip>
+where
+    M: Into<model::PatchClip>,
+{
+    let m = m.into();
+    let (set_title, title) = (m.title.is_some(), m.title.flatten());
+    let (set_password, password) = (m.password.is_some(), m.password.flatten());
+    let (set_expires_at, expires_at) = (m.expires_at.is_some(), m.expires_at.flatten());
+    let (set_content, content_hash) = match &m.content {
+        Some(content) => (true, Some(store_blob(content, &mut *transaction).await?)),
+        None => (false, None),
+    };
+    let result = sqlx::query!(
+        r#"
+        UPDATE clips SET
+            title = CASE WHEN ?1 THEN ?2 ELSE title END,
+            content = CASE WHEN ?3 THEN '' ELSE content END,
+            content_hash = CASE WHEN ?3 THEN ?4 ELSE content_hash END,
+            password = CASE WHEN ?5 THEN ?6 ELSE password END,
+            expires_at = CASE WHEN ?7 THEN ?8 ELSE expires_at END,
+            version = version + 1
+        WHERE shortcode = ?9 AND deleted_at IS NULL AND (?10 IS NULL OR version = ?10)
+        "#,
+        set_title,
+        title,
+        set_content,
+        content_hash,
+        set_password,
+        password,
+        set_expires_at,
+        expires_at,
+        m.shortcode,
+        m.version
+    )
+    .execute(&mut *transaction)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(version_mismatch(m.shortcode, transaction).await);
+    }
+    get_clip(m.shortcode, transaction).await
+}
+
+/// Takes the database write lock for the rest of `transaction`.
+///
+/// SQLite otherwise only takes it at the first write, so another transaction could read
+/// the same rows meanwhile and act on what this one is about to change.
+pub async fn lock_for_write(transaction: &mut Transaction<'_>) -> Result<()> {
+    sqlx::query("UPDATE clips SET id = id WHERE false")
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
+
+/// Moves a clip to the trash.
+pub async fn delete_clip<'e, M, E>(m: M, executor: E) -> Result<()>
+where
+    M: Into<model::DeleteClip>,
+    E: sqlx::Executor<'e, Database = Sqlite>,
+{
+    let m = m.into();
+    let result = sqlx::query!(
+        r#"
+        UPDATE clips SET deleted_at = strftime('%s', 'now')
+        WHERE shortcode = ? AND deleted_at IS NULL
+        "#,
         m.shortcode
     )
-    .execute(pool)
+    .execute(executor)
     .await?;
-    get_clip(m.shortcode, pool).await
+    match result.rows_affected() {
+        0 => Err(sqlx::Error::RowNotFound.into()),
+        _ => Ok(()),
+    }
 }
 
 pub async fn increment_hit(shortcode: &ShortCode, hits: i64, pool: &DatabasePool) -> Result<()> {
     let shortcode = shortcode.as_str();
     Ok(sqlx::query!(
-        r#"UPDATE clips SET hits = hits + ? WHERE shortcode = ?"#,
+        r#"UPDATE clips SET hits = hits + ? WHERE shortcode = ? AND deleted_at IS NULL"#,
         hits,
         shortcode
     )
@@ -76,14 +250,198 @@ pub async fn increment_hit(shortcode: &ShortCode, hits: i64, pool: &DatabasePool
     .map(|_| ())?)
 }
 
-pub async fn save_api_key(api_key: ApiKey, pool: &DatabasePool) -> Result<ApiKey> {
-    let bytes = api_key.clone().into_inner();
-    let _ = sqlx::query!("INSERT INTO api_keys (api_key) VALUES (?)", bytes)
-        .execute(pool)
-        .await
-        .map(|_| ())?;
+/// Saves the hash of an [`ApiKey`] along with its metadata.
+pub async fn save_api_key<M>(api_key: ApiKey, m: M, pool: &DatabasePool) -> Result<ApiKey>
+where
+    M: Into<model::NewApiKey>,
+{
+    let m = m.into();
+    let hash = api_key.hash();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_keys (
+            api_key, key_id, name, scopes, created_at, expires_at,
+            max_clips, max_bytes, max_clips_per_day, hashed
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, true)
+        "#,
+        hash,
+        m.key_id,
+        m.name,
+        m.scopes,
+        m.created_at,
+        m.expires_at,
+        m.max_clips,
+        m.max_bytes,
+        m.max_clips_per_day
+    )
+    .execute(pool)
+    .await?;
     Ok(api_key)
 }
+
+/// Saves an [`ApiKey`], or replaces the name, scopes, expiry and quota of an existing one.
+pub async fn upsert_api_key<M>(api_key: ApiKey, m: M, pool: &DatabasePool) -> Result<ApiKey>
+where
+    M: Into<model::NewApiKey>,
+{
+    let m = m.into();
+    let hash = api_key.hash();
+    sqlx::query!(
+        r#"
+        INSERT INTO api_keys (
+            api_key, key_id, name, scopes, created_at, expires_at,
+            max_clips, max_bytes, max_clips_per_day, hashed
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, true)
+        ON CONFLICT (api_key) DO UPDATE
+        SET name = excluded.name, scopes = excluded.scopes, expires_at = excluded.expires_at,
+            max_clips = excluded.max_clips, max_bytes = excluded.max_bytes,
+            max_clips_per_day = excluded.max_clips_per_day
+        "#,
+        hash,
+        m.key_id,
+        m.name,
+        m.scopes,
+        m.created_at,
+        m.expires_at,
+        m.max_clips,
+        m.max_bytes,
+        m.max_clips_per_day
+    )
+    .execute(pool)
+    .await?;
+    Ok(api_key)
+}
+
+/// Replaces the API keys saved before keys were hashed with their SHA-256, and the
+/// `owner_key` of their clips with it. Returns the number of keys hashed.
+pub async fn hash_legacy_api_keys(pool: &DatabasePool) -> Result<u64> {
+    let mut transaction = pool.begin().await?;
+    // The clips still reference the raw key until they are moved over as well.
+    sqlx::query("PRAGMA defer_foreign_keys = ON")
+        .execute(&mut transaction)
+        .await?;
+    let keys = sqlx::query!(r#"SELECT api_key as "api_key!" FROM api_keys WHERE NOT hashed"#)
+        .fetch_all(&mut transaction)
+        .await?;
+    for key in &keys {
+        let hash = Sha256::digest(&key.api_key).to_vec();
+        sqlx::query!(
+            "UPDATE api_keys SET api_key = ?, hashed = true WHERE api_key = ?",
+            hash,
+            key.api_key
+        )
+        .execute(&mut transaction)
+        .await?;
+        sqlx::query!(
+            "UPDATE clips SET owner_key = ? WHERE owner_key = ?",
+            hash,
+            key.api_key
+        )
+        .execute(&mut transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(keys.len() as u64)
+}
+
+/// The number of API keys granted `scope`.
+pub async fn count_api_keys_with_scope(scope: &str, pool: &DatabasePool) -> Result<i64> {
+    let pattern = format!("% {} %", scope);
+    Ok(sqlx::query!(
+        r#"SELECT COUNT(*) as "count!: i64" FROM api_keys WHERE ' ' || scopes || ' ' LIKE ?"#,
+        pattern
+    )
+    .fetch_one(pool)
+    .await?
+    .count)
+}
+
+/// The metadata of the [`ApiKey`] that hashes to `api_key`, or `None` if it does not exist.
+pub async fn get_api_key<'e, E>(api_key: &[u8], executor: E) -> Result<Option<model::ApiKeyInfo>>
+where
+    E: sqlx::Executor<'e, Database = Sqlite>,
+{
+    Ok(sqlx::query_as!(
+        model::ApiKeyInfo,
+        r#"
+        SELECT key_id as "key_id!", name, scopes, created_at, last_used_at, expires_at,
+            max_clips, max_bytes, max_clips_per_day
+        FROM api_keys WHERE api_key = ?
+        "#,
+        api_key
+    )
+    .fetch_optional(executor)
+    .await?)
+}
+
+/// The metadata of all API keys, oldest first.
+pub async fn list_api_keys(pool: &DatabasePool) -> Result<Vec<model::ApiKeyInfo>> {
+    Ok(sqlx::query_as!(
+        model::ApiKeyInfo,
+        r#"
+        SELECT key_id as "key_id!", name, scopes, created_at, last_used_at, expires_at,
+            max_clips, max_bytes, max_clips_per_day
+        FROM api_keys ORDER BY created_at, key_id
+        "#
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+/// The clips owned by the API key that hashes to `api_key` that count against its quota.
+///
+/// Clips that expired before `now` no longer count, except towards the clips created
+/// since `day_start`.
+pub async fn api_key_usage<'e, E>(
+    api_key: &[u8],
+    now: i64,
+    day_start: i64,
+    executor: E,
+) -> Result<model::Usage>
+where
+    E: sqlx::Executor<'e, Database = Sqlite>,
+{
+    Ok(sqlx::query_as!(
+        model::Usage,
+        r#"
+        SELECT
+            COALESCE(SUM(expires_at IS NULL OR expires_at > ?1), 0) as "clips!: i64",
+            COALESCE(SUM(CASE WHEN expires_at IS NULL OR expires_at > ?1
+                THEN content_bytes ELSE 0 END), 0) as "bytes!: i64",
+            COALESCE(SUM(created_at >= ?2), 0) as "clips_today!: i64"
+        FROM (
+            SELECT expires_at, created_at, COALESCE(
+                (SELECT size FROM blobs WHERE hash = content_hash),
+                length(CAST(content AS BLOB))) as content_bytes
+            FROM clips WHERE owner_key = ?3 AND deleted_at IS NULL
+        )
+        "#,
+        now,
+        day_start,
+        api_key
+    )
+    .fetch_one(executor)
+    .await?)
+}
+
+/// Records when the API key that hashes to `api_key` was last used.
+pub async fn touch_api_key(
+    api_key: &[u8],
+    used_at: i64,
+    transaction: &mut Transaction<'_>,
+) -> Result<()> {
+    Ok(sqlx::query!(
+        "UPDATE api_keys SET last_used_at = ? WHERE api_key = ?",
+        used_at,
+        api_key
+    )
+    .execute(transaction)
+    .await
+    .map(|_| ())?)
+}
+
 /// The return value from the [`revoke_api_key`] function.
 pub enum RevocationStatus {
     /// The [`ApiKey`] was successfully revoked.
@@ -94,9 +452,9 @@ pub enum RevocationStatus {
 
 /// Revokes an [`ApiKey`].
 pub async fn revoke_api_key(api_key: ApiKey, pool: &DatabasePool) -> Result<RevocationStatus> {
-    let bytes = api_key.clone().into_inner();
+    let hash = api_key.hash();
     Ok(
-        sqlx::query!("DELETE FROM api_keys WHERE api_key == ?", bytes)
+        sqlx::query!("DELETE FROM api_keys WHERE api_key == ?", hash)
             .execute(pool)
             .await
             .map(|result| match result.rows_affected() {
@@ -106,31 +464,463 @@ pub async fn revoke_api_key(api_key: ApiKey, pool: &DatabasePool) -> Result<Revo
     )
 }
 
-/// Determines if the [`ApiKey`] is valid.
-pub async fn api_key_is_valid(api_key: ApiKey, pool: &DatabasePool) -> Result<bool> {
-    let bytes = api_key.clone().into_inner();
+/// Revokes the API key with the public `key_id`.
+pub async fn revoke_api_key_by_id(key_id: &str, pool: &DatabasePool) -> Result<RevocationStatus> {
     Ok(
-        sqlx::query("SELECT COUNT(api_key) FROM api_keys WHERE api_key = ?")
-            .bind(bytes)
-            .fetch_one(pool)
+        sqlx::query!("DELETE FROM api_keys WHERE key_id = ?", key_id)
+            .execute(pool)
             .await
-            .map(|row| {
-                let count: u32 = row.get(0);
-                count > 0
+            .map(|result| match result.rows_affected() {
+                0 => RevocationStatus::NotFound,
+                _ => RevocationStatus::Revoked,
             })?,
     )
 }
 
-/// Deletes all expired [`Clips`](`crate::domain::Clip`).
+/// Moves all expired [`Clips`](`crate::domain::Clip`) to the trash.
 pub async fn delete_expired(pool: &DatabasePool) -> Result<u64> {
+    Ok(sqlx::query!(
+        r#"
+        UPDATE clips SET deleted_at = strftime('%s', 'now')
+        WHERE strftime('%s', 'now') > expires_at AND deleted_at IS NULL
+        "#
+    )
+    .execute(pool)
+    .await?
+    .rows_affected())
+}
+
+/// Permanently deletes the clips moved to the trash before `deleted_before`.
+pub async fn purge_trash(deleted_before: i64, pool: &DatabasePool) -> Result<u64> {
+    Ok(
+        sqlx::query!(r#"DELETE FROM clips WHERE deleted_at < ?"#, deleted_before)
+            .execute(pool)
+            .await?
+            .rows_affected(),
+    )
+}
+
+/// Deletes the blobs no clip uses any more.
+pub async fn delete_orphaned_blobs(pool: &DatabasePool) -> Result<u64> {
+    Ok(sqlx::query!("DELETE FROM blobs WHERE refs <= 0")
+        .execute(pool)
+        .await?
+        .rows_affected())
+}
+
+/// Moves the inline content of up to `limit` clips saved before content was deduplicated
+/// to the `blobs` table. Returns the number of clips moved.
+pub async fn dedupe_inline_content(limit: i64, pool: &DatabasePool) -> Result<u64> {
+    let mut transaction = pool.begin().await?;
+    let clips = sqlx::query!(
+        r#"SELECT id as "id!", content FROM clips WHERE content_hash IS NULL LIMIT ?"#,
+        limit
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    for clip in &clips {
+        let content_hash = store_blob(&clip.content, &mut transaction).await?;
+        sqlx::query!(
+            "UPDATE clips SET content = '', content_hash = ? WHERE id = ?",
+            content_hash,
+            clip.id
+        )
+        .execute(&mut transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(clips.len() as u64)
+}
+
+/// Compresses up to `limit` blobs stored before compression, of those larger than the
+/// [`Compression`] threshold. Returns the size of the blobs looked at, and what they take
+/// now; those that do not get smaller are kept as they are.
+pub async fn compress_blobs(limit: i64, pool: &DatabasePool) -> Result<model::ContentStorage> {
+    let compression = Compression::get();
+    let threshold = compression.threshold as i64;
+    let mut transaction = pool.begin().await?;
+    let blobs = sqlx::query!(
+        r#"
+        SELECT hash as "hash!", content, size FROM blobs
+        WHERE codec IS NULL AND size > ? LIMIT ?
+        "#,
+        threshold,
+        limit
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+    let mut storage = model::ContentStorage {
+        blobs: blobs.len() as i64,
+        size: 0,
+        stored: 0,
+    };
+    for blob in blobs {
+        let (codec, stored) = compression.encode(&blob.content);
+        let codec = codec.marker();
+        storage.size += blob.size;
+        storage.stored += stored.len() as i64;
+        sqlx::query!(
+            "UPDATE blobs SET content = ?, codec = ? WHERE hash = ?",
+            stored,
+            codec,
+            blob.hash
+        )
+        .execute(&mut transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(storage)
+}
+
+/// The size of all stored content, and the space it takes.
+pub async fn content_storage(pool: &DatabasePool) -> Result<model::ContentStorage> {
+    Ok(sqlx::query_as!(
+        model::ContentStorage,
+        r#"
+        SELECT COUNT(*) as "blobs!: i64", COALESCE(SUM(size), 0) as "size!: i64",
+            COALESCE(SUM(length(CAST(content AS BLOB))), 0) as "stored!: i64"
+        FROM blobs
+        "#
+    )
+    .fetch_one(pool)
+    .await?)
+}
+
+/// A live clip with the same content, title, password, expiry and owner as `m`, if any.
+pub async fn find_identical_clip<M>(
+    m: M,
+    now: i64,
+    pool: &DatabasePool,
+) -> Result<Option<model::Clip>>
+where
+    M: Into<model::NewClip>,
+{
+    let m = m.into();
+    let content_hash = content_hash(&m.content);
+    Ok(sqlx::query_as!(
+        model::Clip,
+        r#"
+        SELECT id, title, shortcode, created_at, expires_at, password, hits, version,
+            owner_key, edit_token_hash, deleted_at,
+            CAST(COALESCE((SELECT content FROM blobs WHERE hash = content_hash), content) AS BLOB)
+                as "content!: Vec<u8>",
+            (SELECT codec FROM blobs WHERE hash = content_hash) as "codec: String"
+        FROM clips
+        WHERE content_hash = ? AND title IS ? AND password IS ? AND expires_at IS ?
+            AND owner_key IS ? AND edit_token_hash IS ?
+            AND deleted_at IS NULL AND (expires_at IS NULL OR expires_at > ?)
+        ORDER BY created_at DESC LIMIT 1
+        "#,
+        content_hash,
+        m.title,
+        m.password,
+        m.expires_at,
+        m.owner_key,
+        m.edit_token_hash,
+        now
+    )
+    .fetch_optional(pool)
+    .await?)
+}
+
+/// Lists the clips in the trash, most recently deleted first.
+pub async fn list_trash(pool: &DatabasePool) -> Result<Vec<model::Clip>> {
+    Ok(sqlx::query_as!(
+        model::Clip,
+        r#"
+        SELECT id, title, shortcode, created_at, expires_at, password, hits, version,
+            owner_key, edit_token_hash, deleted_at,
+            CAST(COALESCE((SELECT content FROM blobs WHERE hash = content_hash), content) AS BLOB)
+                as "content!: Vec<u8>",
+            (SELECT codec FROM blobs WHERE hash = content_hash) as "codec: String"
+        FROM clips WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Takes a clip out of the trash. An expiry that passed before `now` is cleared, or the
+/// clip would be moved straight back.
+pub async fn restore_clip(
+    shortcode: &ShortCode,
+    now: i64,
+    pool: &DatabasePool,
+) -> Result<model::Clip> {
+    let shortcode = shortcode.as_str();
+    let result = sqlx::query!(
+        r#"
+        UPDATE clips SET
+            deleted_at = NULL,
+            expires_at = CASE WHEN expires_at <= ? THEN NULL ELSE expires_at END,
+            version = version + 1
+        WHERE shortcode = ? AND deleted_at IS NOT NULL
+        "#,
+        now,
+        shortcode
+    )
+    .execute(pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Err(sqlx::Error::RowNotFound.into());
+    }
+    get_clip(shortcode.to_owned(), pool).await
+}
+
+/// Permanently deletes API keys that expired before `expired_before`.
+pub async fn delete_stale_api_keys(expired_before: i64, pool: &DatabasePool) -> Result<u64> {
     Ok(
-        sqlx::query!(r#"DELETE FROM clips WHERE strftime('%s', 'now') > expires_at"#)
+        sqlx::query!("DELETE FROM api_keys WHERE expires_at < ?", expired_before)
             .execute(pool)
             .await?
             .rows_affected(),
     )
 }
 
+/// Updates the query planner statistics and rebuilds the database file to reclaim space.
+pub async fn optimize(pool: &DatabasePool) -> Result<()> {
+    sqlx::query("ANALYZE").execute(pool).await?;
+    sqlx::query("VACUUM").execute(pool).await?;
+    Ok(())
+}
+
+/// Rolls the clips up into the [`DailyStats`](model::DailyStats) of the UTC day starting at
+/// `day`. The clips created that day are always counted; the live clips and hits are a
+/// snapshot at `now`, only taken if `snapshot` is set.
+pub async fn rollup_daily_stats(
+    day: i64,
+    now: i64,
+    snapshot: bool,
+    pool: &DatabasePool,
+) -> Result<model::DailyStats> {
+    sqlx::query!(
+        r#"
+        INSERT INTO daily_stats (day, clips_created, live_clips, live_bytes, hits)
+        SELECT
+            ?1,
+            COALESCE(SUM(created_at >= ?1 AND created_at < ?1 + 86400), 0),
+            COALESCE(SUM(deleted_at IS NULL AND (expires_at IS NULL OR expires_at > ?2)), 0),
+            COALESCE(SUM(CASE WHEN deleted_at IS NULL AND (expires_at IS NULL OR expires_at > ?2)
+                THEN content_bytes ELSE 0 END), 0),
+            COALESCE(SUM(hits), 0)
+        FROM (
+            SELECT created_at, expires_at, deleted_at, hits, COALESCE(
+                (SELECT size FROM blobs WHERE hash = content_hash),
+                length(CAST(content AS BLOB))) as content_bytes
+            FROM clips
+        ) WHERE true
+        ON CONFLICT (day) DO UPDATE SET
+            clips_created = excluded.clips_created,
+            live_clips = CASE WHEN ?3 THEN excluded.live_clips ELSE live_clips END,
+            live_bytes = CASE WHEN ?3 THEN excluded.live_bytes ELSE live_bytes END,
+            hits = CASE WHEN ?3 THEN excluded.hits ELSE hits END
+        "#,
+        day,
+        now,
+        snapshot
+    )
+    .execute(pool)
+    .await?;
+    Ok(sqlx::query_as!(
+        model::DailyStats,
+        r#"SELECT day, clips_created, live_clips, live_bytes, hits FROM daily_stats WHERE day = ?"#,
+        day
+    )
+    .fetch_one(pool)
+    .await?)
+}
+
+/// Every clip, including those in the trash, oldest first, with its content decoded.
+pub async fn export_clips(pool: &DatabasePool) -> Result<Vec<model::ClipRecord>> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT id as "id!", shortcode, title,
+            CAST(COALESCE((SELECT content FROM blobs WHERE hash = content_hash), content) AS BLOB)
+                as "content!: Vec<u8>",
+            (SELECT codec FROM blobs WHERE hash = content_hash) as "codec: String",
+            password,
+            created_at as "created_at!: i64", expires_at as "expires_at: i64",
+            deleted_at as "deleted_at: i64", hits, version, owner_key, edit_token_hash
+        FROM clips ORDER BY created_at, id
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+    rows.into_iter()
+        .map(|row| {
+            let content = Codec::from_marker(row.codec.as_deref())
+                .and_then(|codec| codec::decode(codec, row.content))
+                .map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+            Ok(model::ClipRecord {
+                id: row.id,
+                shortcode: row.shortcode,
+                title: row.title,
+                content,
+                password: row.password,
+                created_at: row.created_at,
+                expires_at: row.expires_at,
+                deleted_at: row.deleted_at,
+                hits: row.hits,
+                version: row.version,
+                owner_key: row.owner_key,
+                edit_token_hash: row.edit_token_hash,
+            })
+        })
+        .collect()
+}
+
+/// Every API key, by its hash, oldest first.
+pub async fn export_api_keys(pool: &DatabasePool) -> Result<Vec<model::ApiKeyRecord>> {
+    Ok(sqlx::query_as!(
+        model::ApiKeyRecord,
+        r#"
+        SELECT api_key as "key_hash!", key_id as "key_id!", name, scopes,
+            created_at as "created_at!: i64", last_used_at as "last_used_at: i64",
+            expires_at as "expires_at: i64", max_clips, max_bytes, max_clips_per_day
+        FROM api_keys ORDER BY created_at, key_id
+        "#
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Whether a clip with the id of `record` exists, in the trash or not.
+pub async fn clip_record_exists(
+    record: &model::ClipRecord,
+    transaction: &mut Transaction<'_>,
+) -> Result<bool> {
+    Ok(sqlx::query("SELECT 1 FROM clips WHERE id = ?")
+        .bind(&record.id)
+        .fetch_optional(transaction)
+        .await?
+        .is_some())
+}
+
+/// Whether any clip, in the trash or not, uses `shortcode`.
+///
+/// Runs on the pool or inside a transaction, so that a restore sees its own clips.
+pub async fn shortcode_taken<'e, E>(shortcode: &str, executor: E) -> Result<bool>
+where
+    E: sqlx::Executor<'e, Database = Sqlite>,
+{
+    Ok(sqlx::query("SELECT 1 FROM clips WHERE shortcode = ?")
+        .bind(shortcode)
+        .fetch_optional(executor)
+        .await?
+        .is_some())
+}
+
+/// A shortcode no clip uses yet, looked up inside `transaction` so that it sees the
+/// clips the transaction itself added.
+pub async fn free_shortcode(transaction: &mut Transaction<'_>) -> Result<String> {
+    loop {
+        let shortcode = ShortCode::default().into_inner();
+        if !shortcode_taken(&shortcode, &mut *transaction).await? {
+            return Ok(shortcode);
+        }
+    }
+}
+
+/// Inserts a clip exactly as recorded.
+pub async fn insert_clip_record(
+    record: &model::ClipRecord,
+    transaction: &mut Transaction<'_>,
+) -> Result<()> {
+    let content_hash = store_blob(&record.content, transaction).await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO clips (
+            id, shortcode, title, content, content_hash, password, created_at, expires_at,
+            deleted_at, hits, version, owner_key, edit_token_hash
+        )
+        VALUES (?, ?, ?, '', ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+        record.id,
+        record.shortcode,
+        record.title,
+        content_hash,
+        record.password,
+        record.created_at,
+        record.expires_at,
+        record.deleted_at,
+        record.hits,
+        record.version,
+        record.owner_key,
+        record.edit_token_hash
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Whether the API key of `record` exists.
+pub async fn api_key_record_exists(
+    record: &model::ApiKeyRecord,
+    transaction: &mut Transaction<'_>,
+) -> Result<bool> {
+    Ok(sqlx::query("SELECT 1 FROM api_keys WHERE api_key = ?")
+        .bind(&record.key_hash)
+        .fetch_optional(transaction)
+        .await?
+        .is_some())
+}
+
+/// Whether another API key uses the public `key_id`.
+pub async fn key_id_taken(key_id: &str, transaction: &mut Transaction<'_>) -> Result<bool> {
+    Ok(sqlx::query("SELECT 1 FROM api_keys WHERE key_id = ?")
+        .bind(key_id)
+        .fetch_optional(transaction)
+        .await?
+        .is_some())
+}
+
+/// Inserts an API key exactly as recorded.
+pub async fn insert_api_key_record(
+    record: &model::ApiKeyRecord,
+    transaction: &mut Transaction<'_>,
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO api_keys (
+            api_key, key_id, name, scopes, created_at, last_used_at, expires_at,
+            max_clips, max_bytes, max_clips_per_day, hashed
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, true)
+        "#,
+        record.key_hash,
+        record.key_id,
+        record.name,
+        record.scopes,
+        record.created_at,
+        record.last_used_at,
+        record.expires_at,
+        record.max_clips,
+        record.max_bytes,
+        record.max_clips_per_day
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Runs a trivial query to check that the database is reachable.
+pub async fn ping(pool: &DatabasePool) -> Result<()> {
+    sqlx::query("SELECT 1").execute(pool).await?;
+    Ok(())
+}
+
+/// Lists the versions of all successfully applied migrations.
+pub async fn applied_migrations(pool: &DatabasePool) -> Result<Vec<i64>> {
+    Ok(
+        sqlx::query("SELECT version FROM _sqlx_migrations WHERE success = 1 ORDER BY version")
+            .fetch_all(pool)
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect(),
+    )
+}
+
 pub mod test_helpers {
     use crate::data::*;
     pub fn model_get_clip(shortcode: &str) -> model::GetClip {
@@ -149,6 +939,8 @@ pub mod test_helpers {
             created_at: Utc::now().timestamp(),
             expires_at: None,
             password: None,
+            owner_key: None,
+            edit_token_hash: None,
         }
     }
 }
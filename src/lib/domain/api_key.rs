@@ -0,0 +1,29 @@
+use crate::domain::{Quota, Scopes};
+use crate::Time;
+use chrono::Utc;
+use serde::Serialize;
+
+/// The metadata stored for an [`ApiKey`](crate::web::ApiKey).
+///
+/// The key itself is only shown once, when it is created, so keys are listed and
+/// revoked by their public `id`.
+#[derive(Clone, Debug, Serialize)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub name: String,
+    pub scopes: Scopes,
+    pub created_at: Time,
+    pub last_used_at: Option<Time>,
+    pub expires_at: Option<Time>,
+    /// Limits of this key that differ from the server defaults.
+    pub quota: Quota,
+}
+
+impl ApiKeyInfo {
+    /// Whether the key has passed its expiry time.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .as_ref()
+            .is_some_and(|expires_at| expires_at.clone().into_inner() <= Utc::now())
+    }
+}
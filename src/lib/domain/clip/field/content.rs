@@ -1,18 +1,41 @@
+use super::Limits;
 use crate::domain::clip::ClipError;
 use rocket::form::{self, FromFormField, ValueField};
 use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use utoipa::ToSchema;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(try_from = "String")]
+#[schema(as = field::Content)]
 pub struct Content(String);
 
 impl Content {
+    /// Checks that `content` is not empty, fits the [`Limits`] and has no null bytes.
     pub fn new(content: &str) -> Result<Self, ClipError> {
         if content.is_empty() {
             return Err(ClipError::EmptyContent);
         }
+        let max = Limits::get().max_content_bytes;
+        if content.len() > max {
+            return Err(ClipError::ContentTooLong(max));
+        }
+        if content.contains('\0') {
+            return Err(ClipError::InvalidContent(
+                "null bytes are not allowed".to_owned(),
+            ));
+        }
         Ok(Content(content.to_owned()))
     }
 
+    /// Content read back from the database.
+    ///
+    /// It was checked when it was saved, and stays readable if the [`Limits`] were
+    /// lowered since.
+    pub(crate) fn stored(content: String) -> Self {
+        Content(content)
+    }
+
     pub fn into_inner(self) -> String {
         self.0
     }
@@ -22,6 +45,14 @@ impl Content {
     }
 }
 
+impl TryFrom<String> for Content {
+    type Error = ClipError;
+
+    fn try_from(content: String) -> Result<Self, Self::Error> {
+        Self::new(content.as_str())
+    }
+}
+
 #[rocket::async_trait]
 impl<'r> FromFormField<'r> for Content {
     fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// A secret that lets whoever created a clip on the web edit it later.
+///
+/// Only its [`hash`](EditToken::hash) is stored.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EditToken(String);
+
+impl EditToken {
+    /// Generates a new random 128-bit token.
+    pub fn generate() -> Self {
+        let bytes: [u8; 16] = rand::random();
+        Self(base64::encode_config(bytes, base64::URL_SAFE_NO_PAD))
+    }
+
+    pub fn new(token: String) -> Self {
+        Self(token)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// The hex encoded SHA-256 of the token.
+    pub fn hash(&self) -> String {
+        format!("{:x}", Sha256::digest(self.0.as_bytes()))
+    }
+}
@@ -1,10 +1,13 @@
 use crate::domain::time::Time;
+use chrono::{FixedOffset, Offset, Utc};
 use derive_more::From;
 use rocket::form::{self, FromFormField, ValueField};
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
+use utoipa::ToSchema;
 
-#[derive(Clone, Debug, Serialize, Deserialize, From)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, From, ToSchema)]
+#[schema(as = field::ExpiresAt)]
 pub struct ExpiresAt(Option<Time>);
 
 impl ExpiresAt {
@@ -16,11 +19,19 @@ impl ExpiresAt {
     pub fn into_inner(self) -> Option<Time> {
         self.0
     }
-}
 
-impl Default for ExpiresAt {
-    fn default() -> Self {
-        Self(None)
+    /// Whether the expiry time has been reached, to the second as stored.
+    pub fn has_passed(&self) -> bool {
+        matches!(&self.0, Some(time) if time.to_timestamp() <= Utc::now().timestamp())
+    }
+
+    /// Parses `s` like [`FromStr`], reading times without an explicit offset at `offset`.
+    pub fn parse_in(s: &str, offset: FixedOffset) -> Result<Self, crate::domain::clip::ClipError> {
+        if s.is_empty() {
+            Ok(Self(None))
+        } else {
+            Ok(Self(Some(Time::parse_in(s, offset)?)))
+        }
     }
 }
 
@@ -28,11 +39,7 @@ impl FromStr for ExpiresAt {
     type Err = crate::domain::clip::ClipError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.is_empty() {
-            Ok(Self(None))
-        } else {
-            Ok(Self(Some(Time::from_str(s)?)))
-        }
+        Self::parse_in(s, Utc.fix())
     }
 }
 
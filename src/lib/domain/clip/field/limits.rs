@@ -0,0 +1,49 @@
+use std::sync::OnceLock;
+
+/// Maximum sizes of clip fields, checked by their constructors.
+///
+/// The limits are set once at startup with [`Limits::install`], since fields are
+/// parsed from forms, JSON and the command line without access to any configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Size of the clip [`Content`](super::Content), in bytes.
+    pub max_content_bytes: usize,
+    /// Length of the clip [`Title`](super::Title), in characters.
+    pub max_title_chars: usize,
+}
+
+/// 1 MiB of content and titles of 200 characters.
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_content_bytes: 1024 * 1024,
+            max_title_chars: 200,
+        }
+    }
+}
+
+static LIMITS: OnceLock<Limits> = OnceLock::new();
+
+impl Limits {
+    /// The limits in effect: those installed at startup, or the defaults.
+    pub fn get() -> Limits {
+        *LIMITS.get_or_init(Limits::default)
+    }
+
+    /// Puts these limits into effect for the rest of the process.
+    ///
+    /// Fails, returning `self`, if limits are already in effect because they were
+    /// installed or used before.
+    pub fn install(self) -> Result<(), Limits> {
+        LIMITS.set(self)
+    }
+
+    /// The largest request body that can carry a clip within these limits.
+    ///
+    /// Leaves room for percent encoding, which at most triples every byte, a title of
+    /// four byte characters and the other fields.
+    pub fn max_body_bytes(&self) -> usize {
+        let fields = self.max_content_bytes + 4 * self.max_title_chars;
+        3 * fields + 16 * 1024
+    }
+}
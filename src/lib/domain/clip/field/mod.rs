@@ -7,6 +7,9 @@ pub use title::Title;
 mod content;
 pub use content::Content;
 
+mod limits;
+pub use limits::Limits;
+
 mod short_code;
 pub use short_code::ShortCode;
 
@@ -21,3 +24,117 @@ pub use password::Password;
 
 mod hits;
 pub use hits::Hits;
+
+mod version;
+pub use version::Version;
+
+mod edit_token;
+pub use edit_token::EditToken;
+
+mod owner;
+pub use owner::Owner;
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::ClipError;
+    use proptest::prelude::*;
+    use rocket::form::{FromFormField, ValueField};
+    use std::str::FromStr;
+
+    /// Parses `value` the way a submitted form field is parsed.
+    fn from_form<'v, T: FromFormField<'v>>(value: &'v str) -> Option<T> {
+        T::from_value(ValueField::from_value(value)).ok()
+    }
+
+    fn render_title(title: &Title) -> String {
+        title.clone().into_inner().unwrap_or_default()
+    }
+
+    fn render_expires_at(expires_at: &ExpiresAt) -> String {
+        expires_at
+            .clone()
+            .into_inner()
+            .map(|time| time.into_inner().format("%Y-%m-%d").to_string())
+            .unwrap_or_default()
+    }
+
+    /// Titles without control characters, within the length limit.
+    fn valid_title() -> impl Strategy<Value = String> {
+        proptest::string::string_regex("[^\\p{Cc}]{0,200}").unwrap()
+    }
+
+    proptest! {
+        #[test]
+        fn title_round_trips(s in valid_title()) {
+            let title = Title::from_str(&s).unwrap();
+            prop_assert_eq!(title.clone().into_inner(), Some(s.trim().to_owned()).filter(|t| !t.is_empty()));
+            prop_assert_eq!(Title::from_str(&render_title(&title)).unwrap(), title.clone());
+            prop_assert_eq!(from_form::<Title>(&render_title(&title)), Some(title));
+        }
+
+        #[test]
+        fn title_rejects_control_characters(
+            head in "[a-z]{1,10}",
+            control in "\\p{Cc}",
+            tail in "[a-z]{1,10}",
+        ) {
+            let s = format!("{}{}{}", head, control, tail);
+            prop_assert!(matches!(Title::from_str(&s), Err(ClipError::InvalidTitle(_))));
+            prop_assert!(from_form::<Title>(&s).is_none());
+        }
+
+        #[test]
+        fn title_rejects_long_titles(extra in 1usize..50) {
+            let s = "t".repeat(Limits::get().max_title_chars + extra);
+            prop_assert!(matches!(Title::from_str(&s), Err(ClipError::TitleTooLong(_))));
+        }
+
+        #[test]
+        fn content_round_trips(s in "[^\\x00]{1,500}") {
+            let content = Content::new(&s).unwrap();
+            prop_assert_eq!(content.as_str(), s.as_str());
+            prop_assert_eq!(from_form::<Content>(content.as_str()), Some(content.clone()));
+            let json = serde_json::to_string(&content).unwrap();
+            prop_assert_eq!(serde_json::from_str::<Content>(&json).unwrap(), content);
+        }
+
+        #[test]
+        fn content_rejects_null_bytes(head in ".{0,10}", tail in ".{0,10}") {
+            let s = format!("{}\0{}", head, tail);
+            prop_assert!(Content::new(&s).is_err());
+            prop_assert!(from_form::<Content>(&s).is_none());
+        }
+
+        #[test]
+        fn password_round_trips(s in ".{0,100}") {
+            let password = Password::from_str(&s).unwrap();
+            prop_assert_eq!(password.has_password(), !s.trim().is_empty());
+            let rendered = password.clone().into_inner().unwrap_or_default();
+            prop_assert_eq!(Password::from_str(&rendered).unwrap(), password.clone());
+            prop_assert_eq!(from_form::<Password>(&rendered), Some(password));
+        }
+
+        #[test]
+        fn shortcode_round_trips(s in "[a-f1-4]{10}") {
+            let shortcode = ShortCode::from_str(&s).unwrap();
+            prop_assert_eq!(shortcode.as_str(), s.as_str());
+            prop_assert_eq!(ShortCode::from_str(shortcode.as_str()).unwrap(), shortcode);
+        }
+
+        #[test]
+        fn expires_at_round_trips(year in 1970i32..9999, month in 1u32..=12, day in 1u32..=28) {
+            let s = format!("{:04}-{:02}-{:02}", year, month, day);
+            let expires_at = ExpiresAt::from_str(&s).unwrap();
+            prop_assert_eq!(render_expires_at(&expires_at), s.clone());
+            prop_assert_eq!(from_form::<ExpiresAt>(&s), Some(expires_at));
+            prop_assert_eq!(ExpiresAt::from_str("").unwrap(), ExpiresAt::new(None));
+        }
+
+        #[test]
+        fn expires_at_rejects_malformed_dates(s in "[0-9]{1,3}-[a-z]{1,3}") {
+            prop_assert!(ExpiresAt::from_str(&s).is_err());
+            prop_assert!(from_form::<ExpiresAt>(&s).is_none());
+        }
+    }
+}
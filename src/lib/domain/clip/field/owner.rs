@@ -0,0 +1,45 @@
+use super::EditToken;
+use serde::{Deserialize, Serialize};
+
+/// Whoever may change a clip without knowing its password.
+///
+/// That is the API key that created the clip, or the holder of the [`EditToken`]
+/// issued when it was created on the web.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Owner {
+    key: Option<Vec<u8>>,
+    edit_token_hash: Option<String>,
+}
+
+impl Owner {
+    pub fn new(key: Option<Vec<u8>>, edit_token_hash: Option<String>) -> Self {
+        Self {
+            key,
+            edit_token_hash,
+        }
+    }
+
+    /// An owner identified by the holder of `token`.
+    pub fn with_edit_token(token: &EditToken) -> Self {
+        Self::new(None, Some(token.hash()))
+    }
+
+    pub fn into_inner(self) -> (Option<Vec<u8>>, Option<String>) {
+        (self.key, self.edit_token_hash)
+    }
+
+    /// The hash of the owning API key, if an API key created the clip.
+    pub fn key(&self) -> Option<&[u8]> {
+        self.key.as_deref()
+    }
+
+    /// Whether the API key that hashes to `key` is the owner.
+    pub fn has_key(&self, key: &[u8]) -> bool {
+        self.key.as_deref() == Some(key)
+    }
+
+    /// Whether `token` is the edit token issued to the owner.
+    pub fn accepts(&self, token: &EditToken) -> bool {
+        self.edit_token_hash.as_deref() == Some(token.hash().as_str())
+    }
+}
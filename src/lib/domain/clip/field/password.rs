@@ -2,8 +2,10 @@ use crate::domain::clip::ClipError;
 use rocket::form::{self, FromFormField, ValueField};
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
+use utoipa::ToSchema;
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, PartialOrd)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, PartialOrd, ToSchema)]
+#[schema(as = field::Password)]
 pub struct Password(Option<String>);
 
 impl Password {
@@ -30,12 +32,6 @@ impl Password {
     }
 }
 
-impl Default for Password {
-    fn default() -> Self {
-        Self(None)
-    }
-}
-
 impl FromStr for Password {
     type Err = ClipError;
 
@@ -4,10 +4,22 @@ use rocket::request::FromParam;
 use rocket::{UriDisplayPath, UriDisplayQuery};
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
+use utoipa::ToSchema;
 
 #[derive(
-    Clone, Debug, Serialize, Deserialize, From, UriDisplayQuery, UriDisplayPath, Hash, Eq, PartialEq,
+    Clone,
+    Debug,
+    Serialize,
+    Deserialize,
+    From,
+    UriDisplayQuery,
+    UriDisplayPath,
+    Hash,
+    Eq,
+    PartialEq,
+    ToSchema,
 )]
+#[schema(as = field::ShortCode)]
 pub struct ShortCode(String);
 
 impl ShortCode {
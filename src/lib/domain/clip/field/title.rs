@@ -1,25 +1,42 @@
+use super::Limits;
 use crate::domain::clip::ClipError;
-use derive_more::From;
 use rocket::form::{self, FromFormField, ValueField};
 use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
 use std::str::FromStr;
+use utoipa::ToSchema;
 
-#[derive(Clone, Debug, Serialize, Deserialize, From)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(try_from = "Option<String>")]
+#[schema(as = field::Title)]
 pub struct Title(Option<String>);
 
 impl Title {
-    pub fn new<T: Into<Option<String>>>(title: T) -> Self {
+    /// Trims `title`, keeping no title if nothing is left.
+    ///
+    /// Fails if the title has control characters or is longer than the [`Limits`] allow.
+    pub fn new<T: Into<Option<String>>>(title: T) -> Result<Self, ClipError> {
         let title: Option<String> = title.into();
-        match title {
-            Some(title) => {
-                if title.trim().is_empty() {
-                    Self(Some(title))
-                } else {
-                    Self(None)
-                }
-            }
-            None => Self(None),
+        let title = match title.as_deref().map(str::trim) {
+            Some(title) if !title.is_empty() => title,
+            _ => return Ok(Self(None)),
+        };
+        if title.chars().any(char::is_control) {
+            return Err(ClipError::InvalidTitle(
+                "control characters are not allowed".to_owned(),
+            ));
         }
+        let max = Limits::get().max_title_chars;
+        if title.chars().count() > max {
+            return Err(ClipError::TitleTooLong(max));
+        }
+        Ok(Self(Some(title.to_owned())))
+    }
+
+    /// A title read back from the database, which stays readable if the [`Limits`]
+    /// were lowered since it was saved.
+    pub(crate) fn stored(title: Option<String>) -> Self {
+        Self(title)
     }
 
     pub fn into_inner(self) -> Option<String> {
@@ -27,9 +44,11 @@ impl Title {
     }
 }
 
-impl Default for Title {
-    fn default() -> Self {
-        Self(None)
+impl TryFrom<Option<String>> for Title {
+    type Error = ClipError;
+
+    fn try_from(title: Option<String>) -> Result<Self, Self::Error> {
+        Self::new(title)
     }
 }
 
@@ -37,13 +56,14 @@ impl FromStr for Title {
     type Err = ClipError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self::new(s.to_owned()))
+        Self::new(s.to_owned())
     }
 }
 
 #[rocket::async_trait]
 impl<'r> FromFormField<'r> for Title {
     fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
-        Ok(Title::new(field.value.to_owned()))
+        Ok(Self::new(field.value.to_owned())
+            .map_err(|e| form::Error::validation(format!("{}", e)))?)
     }
 }
@@ -0,0 +1,12 @@
+use derive_more::Constructor;
+use serde::{Deserialize, Serialize};
+
+/// Revision of a clip, incremented whenever it is changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Constructor)]
+pub struct Version(i64);
+
+impl Version {
+    pub fn into_inner(self) -> i64 {
+        self.0
+    }
+}
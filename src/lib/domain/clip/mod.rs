@@ -1,5 +1,6 @@
 pub mod field;
 
+use crate::Time;
 use chrono;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -15,6 +16,12 @@ pub enum ClipError {
     InvalidDate(String),
     #[error("empty content")]
     EmptyContent,
+    #[error("content is longer than {0} bytes")]
+    ContentTooLong(usize),
+    #[error("invalid content, {0}")]
+    InvalidContent(String),
+    #[error("title is longer than {0} characters")]
+    TitleTooLong(usize),
     #[error("date parse error, {0}")]
     DateParseError(#[from] chrono::ParseError),
     #[error("id parse error, {0}")]
@@ -23,6 +30,27 @@ pub enum ClipError {
     InvalidHits(#[from] std::num::TryFromIntError),
 }
 
+impl ClipError {
+    /// Whether a field exceeds its configured [`Limits`](field::Limits).
+    pub fn is_too_long(&self) -> bool {
+        matches!(self, Self::ContentTooLong(_) | Self::TitleTooLong(_))
+    }
+
+    /// The name of the clip field that failed validation, if the error is tied to one.
+    pub fn field(&self) -> Option<&'static str> {
+        match self {
+            Self::InvalidPassword(_) => Some("password"),
+            Self::InvalidTitle(_) | Self::TitleTooLong(_) => Some("title"),
+            Self::InvalidDate(_) | Self::DateParseError(_) => Some("expires_at"),
+            Self::EmptyContent | Self::ContentTooLong(_) | Self::InvalidContent(_) => {
+                Some("content")
+            }
+            Self::InvalidId(_) => Some("id"),
+            Self::InvalidHits(_) => Some("hits"),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Clip {
     pub id: field::ClipId,
@@ -33,4 +61,23 @@ pub struct Clip {
     pub expires_at: field::ExpiresAt,
     pub password: field::Password,
     pub hits: field::Hits,
+    pub version: field::Version,
+    /// Never serialized, so it does not leak into pages or API responses.
+    #[serde(skip)]
+    pub owner: field::Owner,
+}
+
+/// A deleted or expired [`Clip`], kept until the trash is purged.
+#[derive(Debug, Clone)]
+pub struct TrashedClip {
+    pub clip: Clip,
+    pub deleted_at: Time,
+}
+
+/// A [`Clip`] whose content is decompressed as it is read, rather than all at once.
+pub struct StreamedClip {
+    pub shortcode: field::ShortCode,
+    pub expires_at: field::ExpiresAt,
+    pub password: field::Password,
+    pub content: Box<dyn std::io::Read + Send + Sync>,
 }
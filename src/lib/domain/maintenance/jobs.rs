@@ -0,0 +1,229 @@
+//! The built-in maintenance [`Job`]s.
+
+use super::{Job, Schedule};
+use crate::data::DatabasePool;
+use crate::service::{action, ServiceError};
+use std::time::Duration;
+
+/// Settings of the built-in jobs.
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+    /// How long deleted and expired clips stay in the trash.
+    pub trash_grace: Duration,
+    /// How long expired API keys are kept, so admins can still see them.
+    pub stale_key_grace: Duration,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            trash_grace: Duration::from_secs(7 * 24 * 60 * 60),
+            stale_key_grace: Duration::from_secs(30 * 24 * 60 * 60),
+        }
+    }
+}
+
+/// The built-in jobs, configured by `settings`.
+pub fn builtin(settings: &Settings) -> Vec<Box<dyn Job>> {
+    vec![
+        Box::new(ExpiryPurge {
+            grace: settings.trash_grace,
+        }),
+        Box::new(Optimize),
+        Box::new(StaleKeyCleanup {
+            grace: settings.stale_key_grace,
+        }),
+        Box::new(AnalyticsRollup),
+        Box::new(ContentDedup),
+        Box::new(CompressionBackfill),
+    ]
+}
+
+fn chrono_duration(duration: Duration) -> chrono::Duration {
+    chrono::Duration::from_std(duration).unwrap_or(chrono::Duration::MAX)
+}
+
+/// Moves expired clips to the trash, purges those in it for longer than `grace`, and deletes
+/// the content no clip uses any more.
+pub struct ExpiryPurge {
+    pub grace: Duration,
+}
+
+#[rocket::async_trait]
+impl Job for ExpiryPurge {
+    fn name(&self) -> &'static str {
+        "expiry_purge"
+    }
+
+    fn schedule(&self) -> Schedule {
+        Schedule::Every(Duration::from_secs(60))
+    }
+
+    async fn run(&self, pool: &DatabasePool) -> Result<String, ServiceError> {
+        let trashed = action::delete_expired(pool).await?;
+        let purged = action::purge_trash(chrono_duration(self.grace), pool).await?;
+        let blobs = action::delete_orphaned_blobs(pool).await?;
+        Ok(format!(
+            "moved {} expired clips to the trash, purged {}, deleted {} unused blobs",
+            trashed, purged, blobs
+        ))
+    }
+}
+
+/// Runs SQLite's `ANALYZE` and `VACUUM` nightly.
+pub struct Optimize;
+
+#[rocket::async_trait]
+impl Job for Optimize {
+    fn name(&self) -> &'static str {
+        "optimize_database"
+    }
+
+    fn schedule(&self) -> Schedule {
+        Schedule::cron("0 30 3 * * *").expect("valid cron expression")
+    }
+
+    async fn run(&self, pool: &DatabasePool) -> Result<String, ServiceError> {
+        action::optimize_database(pool).await?;
+        Ok("analyzed and vacuumed".to_owned())
+    }
+}
+
+/// Deletes API keys that expired longer than `grace` ago.
+pub struct StaleKeyCleanup {
+    pub grace: Duration,
+}
+
+#[rocket::async_trait]
+impl Job for StaleKeyCleanup {
+    fn name(&self) -> &'static str {
+        "stale_key_cleanup"
+    }
+
+    fn schedule(&self) -> Schedule {
+        Schedule::Every(Duration::from_secs(60 * 60))
+    }
+
+    async fn run(&self, pool: &DatabasePool) -> Result<String, ServiceError> {
+        let deleted = action::delete_stale_api_keys(chrono_duration(self.grace), pool).await?;
+        Ok(format!("deleted {} expired API keys", deleted))
+    }
+}
+
+/// Rolls clip analytics up into [`DailyStats`](crate::domain::DailyStats).
+pub struct AnalyticsRollup;
+
+#[rocket::async_trait]
+impl Job for AnalyticsRollup {
+    fn name(&self) -> &'static str {
+        "analytics_rollup"
+    }
+
+    fn schedule(&self) -> Schedule {
+        Schedule::cron("0 55 * * * *").expect("valid cron expression")
+    }
+
+    async fn run(&self, pool: &DatabasePool) -> Result<String, ServiceError> {
+        let stats = action::rollup_daily_stats(pool).await?;
+        Ok(format!(
+            "{}: {} clips created, {} live using {} bytes, {} hits",
+            stats.day.into_inner().format("%Y-%m-%d"),
+            stats.clips_created,
+            stats.live_clips,
+            stats.live_bytes,
+            stats.hits
+        ))
+    }
+}
+
+/// Moves the content of clips saved before content was deduplicated to shared storage, a
+/// batch at a time.
+pub struct ContentDedup;
+
+/// Clips moved by each run of [`ContentDedup`].
+const DEDUP_BATCH: i64 = 500;
+
+#[rocket::async_trait]
+impl Job for ContentDedup {
+    fn name(&self) -> &'static str {
+        "content_dedup"
+    }
+
+    fn schedule(&self) -> Schedule {
+        Schedule::Every(Duration::from_secs(10 * 60))
+    }
+
+    async fn run(&self, pool: &DatabasePool) -> Result<String, ServiceError> {
+        let moved = action::dedupe_inline_content(DEDUP_BATCH, pool).await?;
+        Ok(format!(
+            "moved the content of {} clips to shared storage",
+            moved
+        ))
+    }
+}
+
+/// Compresses content stored before compression, a batch at a time, and reports the
+/// space saved.
+pub struct CompressionBackfill;
+
+/// Blobs looked at by each run of [`CompressionBackfill`].
+const COMPRESSION_BATCH: i64 = 200;
+
+#[rocket::async_trait]
+impl Job for CompressionBackfill {
+    fn name(&self) -> &'static str {
+        "compression_backfill"
+    }
+
+    fn schedule(&self) -> Schedule {
+        Schedule::Every(Duration::from_secs(10 * 60))
+    }
+
+    async fn run(&self, pool: &DatabasePool) -> Result<String, ServiceError> {
+        let (batch, total) = action::compress_stored_content(COMPRESSION_BATCH, pool).await?;
+        Ok(format!(
+            "compressed {} blobs from {} to {} bytes; all content takes {} of {} bytes ({:.1}% saved)",
+            batch.blobs,
+            batch.size,
+            batch.stored,
+            total.stored,
+            total.size,
+            total.saved_percent()
+        ))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::data::query::{new_clip, test_helpers::model_new_clip};
+    use crate::web::test_helpers::new_db;
+
+    #[test]
+    fn builtin_jobs_run() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        rt.block_on(async {
+            new_clip(model_new_clip("1"), pool).await.unwrap();
+            let jobs = builtin(&Settings::default());
+            let names: Vec<_> = jobs.iter().map(|job| job.name()).collect();
+            assert_eq!(
+                names,
+                [
+                    "expiry_purge",
+                    "optimize_database",
+                    "stale_key_cleanup",
+                    "analytics_rollup",
+                    "content_dedup",
+                    "compression_backfill"
+                ]
+            );
+            for job in &jobs {
+                job.run(pool).await.unwrap();
+            }
+            let summary = AnalyticsRollup.run(pool).await.unwrap();
+            assert!(summary.contains("1 clips created, 1 live"), "{}", summary);
+        });
+    }
+}
@@ -0,0 +1,315 @@
+//! Background maintenance: a small scheduler running [`Job`]s on their [`Schedule`].
+//!
+//! Every job runs on its own task, one run at a time, and records how its last run went
+//! in a [`JobStatus`]. [`Maintenance::shutdown`] lets running jobs finish and stops the rest.
+
+pub mod jobs;
+
+use crate::data::DatabasePool;
+use crate::service::ServiceError;
+use crate::Time;
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use rand::Rng;
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// When a [`Job`] runs.
+#[derive(Clone, Debug)]
+pub enum Schedule {
+    /// Repeatedly, this long after the previous run finished.
+    Every(Duration),
+    /// At the times matched by a cron expression in UTC, with a leading seconds field.
+    Cron(Box<cron::Schedule>),
+}
+
+impl Schedule {
+    /// A schedule from a cron expression such as `0 30 3 * * *` (daily at 03:30 UTC).
+    pub fn cron(expression: &str) -> Result<Self, cron::error::Error> {
+        Ok(Self::Cron(Box::new(cron::Schedule::from_str(expression)?)))
+    }
+
+    /// The next time to run after `now`, if there is one.
+    pub fn next_after(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Self::Every(interval) => chrono::Duration::from_std(*interval)
+                .ok()
+                .map(|interval| now + interval),
+            Self::Cron(schedule) => schedule.after(&now).next(),
+        }
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Every(interval) if interval.subsec_millis() > 0 => {
+                write!(f, "every {}ms", interval.as_millis())
+            }
+            Self::Every(interval) => write!(f, "every {}s", interval.as_secs()),
+            Self::Cron(schedule) => write!(f, "cron {}", schedule),
+        }
+    }
+}
+
+/// A unit of background work.
+#[rocket::async_trait]
+pub trait Job: Send + Sync {
+    /// A unique, stable name, e.g. `expiry_purge`.
+    fn name(&self) -> &'static str;
+
+    fn schedule(&self) -> Schedule;
+
+    /// Runs the job once, describing what it did.
+    async fn run(&self, pool: &DatabasePool) -> Result<String, ServiceError>;
+}
+
+/// How a [`Job`] has been doing.
+#[derive(Clone, Debug, Serialize)]
+pub struct JobStatus {
+    pub name: &'static str,
+    pub schedule: String,
+    pub runs: u64,
+    pub failures: u64,
+    pub running: bool,
+    pub last_started_at: Option<Time>,
+    pub last_duration_ms: Option<u64>,
+    /// What the last successful run did.
+    pub last_summary: Option<String>,
+    /// Why the last run failed; cleared by the next success.
+    pub last_error: Option<String>,
+    pub next_run_at: Option<Time>,
+}
+
+/// A [`Job`] registered with the scheduler, along with its status.
+struct Scheduled {
+    job: Box<dyn Job>,
+    schedule: Schedule,
+    status: Mutex<JobStatus>,
+}
+
+impl Scheduled {
+    fn new(job: Box<dyn Job>) -> Self {
+        let schedule = job.schedule();
+        let status = JobStatus {
+            name: job.name(),
+            schedule: schedule.to_string(),
+            runs: 0,
+            failures: 0,
+            running: false,
+            last_started_at: None,
+            last_duration_ms: None,
+            last_summary: None,
+            last_error: None,
+            next_run_at: None,
+        };
+        Self {
+            job,
+            schedule,
+            status: Mutex::new(status),
+        }
+    }
+
+    async fn run_once(&self, pool: &DatabasePool) {
+        let started = std::time::Instant::now();
+        {
+            let mut status = self.status.lock();
+            status.running = true;
+            status.next_run_at = None;
+            status.last_started_at = Some(Utc::now().into());
+        }
+        let result = self.job.run(pool).await;
+        let mut status = self.status.lock();
+        status.running = false;
+        status.runs += 1;
+        status.last_duration_ms = Some(started.elapsed().as_millis() as u64);
+        match result {
+            Ok(summary) => {
+                status.last_summary = Some(summary);
+                status.last_error = None;
+            }
+            Err(e) => {
+                eprintln!("maintenance job {} failed: {}", self.job.name(), e);
+                status.failures += 1;
+                status.last_error = Some(e.to_string());
+            }
+        }
+    }
+}
+
+/// Runs `scheduled` whenever it is due, until shut down.
+async fn run_scheduled(
+    scheduled: Arc<Scheduled>,
+    pool: DatabasePool,
+    jitter: Duration,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    loop {
+        let now = Utc::now();
+        let next = match scheduled.schedule.next_after(now) {
+            Some(next) => next,
+            None => break,
+        };
+        let jitter = match jitter.as_millis() as u64 {
+            0 => Duration::ZERO,
+            max => Duration::from_millis(rand::thread_rng().gen_range(0..=max)),
+        };
+        let delay = (next - now).to_std().unwrap_or_default() + jitter;
+        scheduled.status.lock().next_run_at = chrono::Duration::from_std(delay)
+            .ok()
+            .map(|delay| (now + delay).into());
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => scheduled.run_once(&pool).await,
+            _ = shutdown.changed() => break,
+        }
+    }
+    scheduled.status.lock().next_run_at = None;
+}
+
+/// The running maintenance [`Job`]s. Clones share the same jobs.
+#[derive(Clone)]
+pub struct Maintenance {
+    jobs: Arc<Vec<Arc<Scheduled>>>,
+    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    shutdown: Arc<watch::Sender<bool>>,
+}
+
+impl Maintenance {
+    /// Starts running `jobs` on their schedules. Each run is delayed by a random amount of
+    /// up to `jitter`, so that several instances don't all run a job at once.
+    pub fn spawn(
+        pool: DatabasePool,
+        handle: Handle,
+        jobs: Vec<Box<dyn Job>>,
+        jitter: Duration,
+    ) -> Self {
+        let (shutdown, receiver) = watch::channel(false);
+        let jobs: Vec<_> = jobs
+            .into_iter()
+            .map(|job| Arc::new(Scheduled::new(job)))
+            .collect();
+        let tasks = jobs
+            .iter()
+            .map(|scheduled| {
+                let task = run_scheduled(scheduled.clone(), pool.clone(), jitter, receiver.clone());
+                handle.spawn(task)
+            })
+            .collect();
+        Self {
+            jobs: Arc::new(jobs),
+            tasks: Arc::new(Mutex::new(tasks)),
+            shutdown: Arc::new(shutdown),
+        }
+    }
+
+    /// Whether every job is still scheduled.
+    pub fn is_alive(&self) -> bool {
+        let tasks = self.tasks.lock();
+        !tasks.is_empty() && tasks.iter().all(|task| !task.is_finished())
+    }
+
+    /// The status of every job, in the order they were registered.
+    pub fn statuses(&self) -> Vec<JobStatus> {
+        self.jobs
+            .iter()
+            .map(|scheduled| scheduled.status.lock().clone())
+            .collect()
+    }
+
+    /// Stops scheduling jobs, waiting for those running to finish.
+    pub async fn shutdown(&self) {
+        let _ = self.shutdown.send(true);
+        let tasks = std::mem::take(&mut *self.tasks.lock());
+        for task in tasks {
+            let _ = task.await;
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::web::test_helpers::new_db;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    struct Counter {
+        runs: Arc<AtomicU64>,
+        fail: bool,
+    }
+
+    #[rocket::async_trait]
+    impl Job for Counter {
+        fn name(&self) -> &'static str {
+            "counter"
+        }
+
+        fn schedule(&self) -> Schedule {
+            Schedule::Every(Duration::from_millis(10))
+        }
+
+        async fn run(&self, _pool: &DatabasePool) -> Result<String, ServiceError> {
+            let runs = self.runs.fetch_add(1, Ordering::SeqCst) + 1;
+            match self.fail {
+                true => Err(ServiceError::NotFound),
+                false => Ok(format!("run {}", runs)),
+            }
+        }
+    }
+
+    #[test]
+    fn runs_jobs_until_shut_down() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let db = new_db(rt.handle());
+        let runs = Arc::new(AtomicU64::new(0));
+        let jobs: Vec<Box<dyn Job>> = vec![
+            Box::new(Counter {
+                runs: runs.clone(),
+                fail: false,
+            }),
+            Box::new(Counter {
+                runs: Arc::new(AtomicU64::new(0)),
+                fail: true,
+            }),
+        ];
+        let maintenance = Maintenance::spawn(
+            db.get_pool().clone(),
+            rt.handle().clone(),
+            jobs,
+            Duration::from_millis(5),
+        );
+        assert!(maintenance.is_alive());
+        rt.block_on(async { tokio::time::sleep(Duration::from_millis(100)).await });
+        rt.block_on(maintenance.shutdown());
+        assert!(!maintenance.is_alive());
+
+        let statuses = maintenance.statuses();
+        assert_eq!(statuses[0].schedule, "every 10ms");
+        assert!(statuses[0].runs >= 2);
+        assert_eq!(statuses[0].runs, runs.load(Ordering::SeqCst));
+        assert_eq!(statuses[0].failures, 0);
+        assert!(statuses[0].last_summary.is_some());
+        assert!(statuses[1].runs >= 2);
+        assert_eq!(statuses[1].failures, statuses[1].runs);
+        assert_eq!(statuses[1].last_error.as_deref(), Some("not found"));
+
+        let stopped = runs.load(Ordering::SeqCst);
+        rt.block_on(async { tokio::time::sleep(Duration::from_millis(50)).await });
+        assert_eq!(runs.load(Ordering::SeqCst), stopped);
+    }
+
+    #[test]
+    fn cron_schedules_run_at_matching_times() {
+        let schedule = Schedule::cron("0 30 3 * * *").unwrap();
+        let now: DateTime<Utc> = "2030-05-01T12:00:00Z".parse().unwrap();
+        let next: DateTime<Utc> = "2030-05-02T03:30:00Z".parse().unwrap();
+        assert_eq!(schedule.next_after(now), Some(next));
+        assert_eq!(schedule.to_string(), "cron 0 30 3 * * *");
+        assert!(Schedule::cron("sometimes").is_err());
+    }
+}
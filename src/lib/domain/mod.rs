@@ -1,4 +1,12 @@
+pub mod api_key;
 pub mod clip;
+pub use api_key::ApiKeyInfo;
 pub use clip::Clip;
 pub mod maintenance;
+pub mod quota;
+pub mod scope;
+pub mod stats;
+pub use quota::{Quota, QuotaError, Usage};
+pub use scope::{Scope, Scopes};
+pub use stats::{ContentStorage, DailyStats};
 pub mod time;
@@ -0,0 +1,81 @@
+//! Limits on how much an [`ApiKey`](crate::web::ApiKey) may store.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+/// Limits on the clips owned by one API key. `None` means unlimited.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct Quota {
+    /// Clips that have not expired yet.
+    pub max_clips: Option<u64>,
+    /// Total size of the content of those clips, in bytes.
+    pub max_bytes: Option<u64>,
+    /// Clips created within the last 24 hours.
+    pub max_clips_per_day: Option<u64>,
+}
+
+impl Quota {
+    /// This quota, with the limits it leaves unset taken from `defaults`.
+    pub fn or(self, defaults: &Quota) -> Quota {
+        Quota {
+            max_clips: self.max_clips.or(defaults.max_clips),
+            max_bytes: self.max_bytes.or(defaults.max_bytes),
+            max_clips_per_day: self.max_clips_per_day.or(defaults.max_clips_per_day),
+        }
+    }
+
+    /// Fails if creating a clip of `bytes` bytes on top of `usage` exceeds a limit.
+    pub fn check_new(&self, usage: &Usage, bytes: u64) -> Result<(), QuotaError> {
+        if let Some(max) = self
+            .max_clips_per_day
+            .filter(|max| usage.clips_today >= *max)
+        {
+            return Err(QuotaError::ClipsPerDay(max));
+        }
+        if let Some(max) = self.max_clips.filter(|max| usage.clips >= *max) {
+            return Err(QuotaError::Clips(max));
+        }
+        self.check_bytes(usage.bytes.saturating_add(bytes))
+    }
+
+    /// Fails if replacing clip content of `old` bytes with `new` bytes exceeds the size limit.
+    ///
+    /// Shrinking a clip is always allowed, so owners over their limit can make room.
+    pub fn check_update(&self, usage: &Usage, old: u64, new: u64) -> Result<(), QuotaError> {
+        if new <= old {
+            return Ok(());
+        }
+        self.check_bytes(usage.bytes.saturating_sub(old).saturating_add(new))
+    }
+
+    fn check_bytes(&self, bytes: u64) -> Result<(), QuotaError> {
+        match self.max_bytes {
+            Some(max) if bytes > max => Err(QuotaError::Bytes(max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What the clips of one API key currently count against its [`Quota`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    /// Clips that have not expired yet.
+    pub clips: u64,
+    /// Total size of the content of those clips, in bytes.
+    pub bytes: u64,
+    /// Clips created within the last 24 hours, including expired ones.
+    pub clips_today: u64,
+}
+
+/// The [`Quota`] limit a change would exceed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+pub enum QuotaError {
+    #[error("quota of {0} live clips reached")]
+    Clips(u64),
+    #[error("quota of {0} bytes of clip content exceeded")]
+    Bytes(u64),
+    #[error("quota of {0} new clips per day reached")]
+    ClipsPerDay(u64),
+}
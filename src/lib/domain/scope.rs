@@ -0,0 +1,115 @@
+//! Permissions granted to an [`ApiKey`](crate::web::ApiKey).
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+use strum::{Display, EnumIter, EnumString, IntoEnumIterator};
+use utoipa::ToSchema;
+
+/// A single permission of an API key.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Display,
+    EnumString,
+    EnumIter,
+    Serialize,
+    Deserialize,
+    ToSchema,
+)]
+pub enum Scope {
+    /// Read clips.
+    #[strum(serialize = "clip:read")]
+    #[serde(rename = "clip:read")]
+    ClipRead,
+    /// Create and change clips.
+    #[strum(serialize = "clip:write")]
+    #[serde(rename = "clip:write")]
+    ClipWrite,
+    /// Delete clips.
+    #[strum(serialize = "clip:delete")]
+    #[serde(rename = "clip:delete")]
+    ClipDelete,
+    /// Manage API keys and the server.
+    #[strum(serialize = "admin")]
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+/// The set of [`Scope`]s granted to an API key, stored space separated.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scopes(BTreeSet<Scope>);
+
+impl Scopes {
+    /// The scopes of keys created without asking for specific ones: full access to clips.
+    pub fn clips() -> Self {
+        Self([Scope::ClipRead, Scope::ClipWrite, Scope::ClipDelete].into())
+    }
+
+    /// Every scope, including [`Scope::Admin`].
+    pub fn all() -> Self {
+        Self(Scope::iter().collect())
+    }
+
+    /// Whether `scope` is granted. [`Scope::Admin`] grants every scope.
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.0.contains(&scope) || self.0.contains(&Scope::Admin)
+    }
+
+    pub fn contains(&self, scope: Scope) -> bool {
+        self.0.contains(&scope)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Scope> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+impl FromIterator<Scope> for Scopes {
+    fn from_iter<I: IntoIterator<Item = Scope>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+/// Parses scopes separated by spaces or commas, e.g. `clip:read clip:write`.
+impl FromStr for Scopes {
+    type Err = strum::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|scope| !scope.is_empty())
+            .map(Scope::from_str)
+            .collect()
+    }
+}
+
+impl fmt::Display for Scopes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scopes: Vec<String> = self.iter().map(|scope| scope.to_string()).collect();
+        write!(f, "{}", scopes.join(" "))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn scopes_round_trip() {
+        let scopes: Scopes = "clip:write, clip:read".parse().unwrap();
+        assert_eq!(scopes.to_string(), "clip:read clip:write");
+        assert!(scopes.allows(Scope::ClipRead));
+        assert!(!scopes.allows(Scope::ClipDelete));
+        assert!(Scopes::all().allows(Scope::Admin));
+    }
+}
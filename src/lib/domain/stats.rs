@@ -0,0 +1,38 @@
+//! Analytics rolled up by the maintenance jobs.
+
+use crate::Time;
+use serde::Serialize;
+
+/// What the clips looked like on one UTC day.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DailyStats {
+    /// Midnight UTC at the start of the day.
+    pub day: Time,
+    pub clips_created: u64,
+    /// Clips neither expired nor in the trash, when last rolled up.
+    pub live_clips: u64,
+    /// Total size of the content of those clips, in bytes.
+    pub live_bytes: u64,
+    /// Hits of all clips so far, when last rolled up.
+    pub hits: u64,
+}
+
+/// How much space stored clip content takes, against the size of the content itself.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ContentStorage {
+    pub blobs: u64,
+    /// Size of the content, in bytes.
+    pub size: u64,
+    /// Bytes taken to store it, compressed or not.
+    pub stored: u64,
+}
+
+impl ContentStorage {
+    /// The share of the content size saved by compression, in percent.
+    pub fn saved_percent(&self) -> f64 {
+        match self.size {
+            0 => 0.0,
+            size => 100.0 * size.saturating_sub(self.stored) as f64 / size as f64,
+        }
+    }
+}
@@ -1,9 +1,10 @@
-use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc};
 use derive_more::From;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
+use utoipa::ToSchema;
 
-#[derive(Clone, Debug, Serialize, Deserialize, From)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, From, ToSchema)]
 pub struct Time(DateTime<Utc>);
 
 impl Time {
@@ -16,7 +17,21 @@ impl Time {
     }
 
     pub fn from_naive_utc(time: NaiveDateTime) -> Self {
-        Self(DateTime::<Utc>::from_utc(time, Utc))
+        Self(Utc.from_utc_datetime(&time))
+    }
+
+    /// Parses an RFC 3339 timestamp, or a wall clock time (`yyyy-mm-dd`, `yyyy-mm-ddThh:mm`
+    /// or `yyyy-mm-dd hh:mm`) read at `offset`.
+    pub fn parse_in(s: &str, offset: FixedOffset) -> Result<Self, chrono::ParseError> {
+        if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Self(time.with_timezone(&Utc)));
+        }
+        let local = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M")
+            .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M"))
+            .or_else(|_| {
+                NaiveDate::parse_from_str(s, "%Y-%m-%d").map(|date| date.and_time(NaiveTime::MIN))
+            })?;
+        Ok(Self::from_naive_utc(local - offset))
     }
 }
 
@@ -24,7 +39,45 @@ impl FromStr for Time {
     type Err = chrono::ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        // yyyy-mm-dd
-        Ok(format!("{}T00:00:00Z", s).parse::<DateTime<Utc>>()?.into())
+        Self::parse_in(s, Utc.fix())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn parses_wall_clock_times_at_an_offset() {
+        let plus_two = FixedOffset::east_opt(2 * 3600).unwrap();
+        let expected: DateTime<Utc> = "2030-05-01T22:00:00Z".parse().unwrap();
+        assert_eq!(
+            Time::parse_in("2030-05-02", plus_two).unwrap().into_inner(),
+            expected
+        );
+        assert_eq!(
+            Time::parse_in("2030-05-02T00:00", plus_two)
+                .unwrap()
+                .into_inner(),
+            expected
+        );
+        assert_eq!(
+            Time::parse_in("2030-05-02 00:00", plus_two)
+                .unwrap()
+                .into_inner(),
+            expected
+        );
+        // An explicit offset wins over the one supplied.
+        assert_eq!(
+            Time::parse_in("2030-05-01T17:00:00-05:00", plus_two)
+                .unwrap()
+                .into_inner(),
+            expected
+        );
+        assert_eq!(
+            "2030-05-02".parse::<Time>().unwrap().into_inner(),
+            "2030-05-02T00:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert!(Time::parse_in("tomorrow", plus_two).is_err());
     }
 }
@@ -8,6 +8,7 @@ pub use data::DataError;
 use domain::maintenance::Maintenance;
 pub use service::ServiceError;
 pub use web::hit_counter::HitCounter;
+pub use web::key_usage::KeyUsage;
 
 use data::AppDatabase;
 pub use domain::clip::field::ShortCode;
@@ -16,22 +17,53 @@ pub use domain::time::Time;
 use rocket::fs::FileServer;
 use rocket::{Build, Rocket};
 
+use domain::Quota;
+use web::key_generation::KeyGeneration;
+use web::password_attempts::PasswordAttempts;
+use web::rate_limit::RateLimiter;
 use web::renderer::Renderer;
 
 pub fn rocket(config: RocketConfig) -> Rocket<Build> {
-    rocket::build()
+    // Bodies may carry a clip as large as the field limits allow, but no larger.
+    let max_body = domain::clip::field::Limits::get().max_body_bytes();
+    let figment = rocket::Config::figment()
+        .merge(("limits.form", max_body))
+        .merge(("limits.json", max_body));
+    rocket::custom(figment)
         .manage::<AppDatabase>(config.database)
         .manage::<Renderer>(config.renderer)
         .manage::<HitCounter>(config.hit_counter)
+        .manage::<KeyUsage>(config.key_usage)
+        .manage::<KeyGeneration>(config.key_generation)
+        .manage::<RateLimiter>(config.rate_limiter)
+        .manage::<PasswordAttempts>(config.password_attempts)
+        .manage::<Quota>(config.quota)
+        .manage::<Maintenance>(config.maintenance)
         .mount("/", web::http::routes())
+        .mount("/", web::health::routes())
         .mount("/api", web::api::routes())
+        .mount("/api/v1", web::api::v1::routes())
+        .mount("/api/v1", web::api::keys::routes())
+        .mount("/api/v1", web::api::trash::routes())
+        .mount("/api/v1", web::api::jobs::routes())
+        .mount("/api/v1", web::api::metrics::routes())
+        .mount("/api", web::openapi::routes())
         .mount("/static", FileServer::from("static"))
         .register("/", web::http::catcher::catchers())
+        .register("/api", web::api::catcher::catchers())
+        .attach(web::request_id::RequestIdFairing)
+        .attach(web::rate_limit::RateLimitFairing)
 }
 
 pub struct RocketConfig {
     pub renderer: Renderer<'static>,
     pub database: AppDatabase,
     pub hit_counter: HitCounter,
+    pub key_usage: KeyUsage,
+    pub key_generation: KeyGeneration,
+    pub rate_limiter: RateLimiter,
+    pub password_attempts: PasswordAttempts,
+    /// Default limits on the clips of each API key.
+    pub quota: Quota,
     pub maintenance: Maintenance,
 }
@@ -1,7 +1,11 @@
-use crate::data::{query, DatabasePool, Transaction};
+use crate::data::{query, DatabasePool, Transaction, MIGRATOR};
+use crate::domain::clip::{field, StreamedClip, TrashedClip};
+use crate::domain::{ApiKeyInfo, ContentStorage, DailyStats, Quota, Scope, Scopes, Usage};
 use crate::service::ask;
 use crate::web::ApiKey;
 use crate::{Clip, ShortCode};
+use chrono::{Duration, NaiveTime, Utc};
+use sqlx::Sqlite;
 use std::convert::TryInto;
 
 use super::ServiceError;
@@ -14,44 +18,272 @@ pub async fn end_transaction(transaction: Transaction<'_>) -> Result<(), Service
     Ok(transaction.commit().await?)
 }
 
+/// Fails with [`ServiceError::Expired`] once a clip expired, even before maintenance deletes it.
+fn unexpired(expires_at: &field::ExpiresAt) -> Result<(), ServiceError> {
+    if expires_at.has_passed() {
+        Err(ServiceError::Expired)
+    } else {
+        Ok(())
+    }
+}
+
+/// Fails unless a clip that `expires_at` and is protected by `password` can be read
+/// with the `given` password.
+fn readable(
+    expires_at: &field::ExpiresAt,
+    password: &field::Password,
+    given: &field::Password,
+) -> Result<(), ServiceError> {
+    unexpired(expires_at)?;
+    if password.has_password() && password != given {
+        Err(ServiceError::PermissionError("invalid password".to_owned()))
+    } else {
+        Ok(())
+    }
+}
+
 pub async fn get_clip(req: ask::GetClip, pool: &DatabasePool) -> Result<Clip, ServiceError> {
     let password = req.password.clone();
     let clip: Clip = query::get_clip(req, pool).await?.try_into()?;
-    if clip.password.has_password() {
-        dbg!(&clip);
-        dbg!(&password);
-        if clip.password == password {
-            Ok(clip)
-        } else {
-            Err(ServiceError::PermissionError("invalid password".to_owned()))
-        }
-    } else {
+    readable(&clip.expires_at, &clip.password, &password)?;
+    Ok(clip)
+}
+
+/// Retrieves a clip like [`get_clip`], decompressing its content only as it is read.
+pub async fn get_clip_stream(
+    req: ask::GetClip,
+    pool: &DatabasePool,
+) -> Result<StreamedClip, ServiceError> {
+    let password = req.password.clone();
+    let clip: StreamedClip = query::get_clip(req, pool).await?.try_into()?;
+    readable(&clip.expires_at, &clip.password, &password)?;
+    Ok(clip)
+}
+
+/// Creates a clip, if it fits the [`Quota`] of the API key that owns it.
+///
+/// `quota` holds the server defaults for limits the key does not set itself. Clips
+/// without an owning API key are not limited. The usage is counted and the clip created
+/// under the database write lock, so concurrent requests of a key cannot together
+/// exceed its quota.
+pub async fn new_clip(
+    req: ask::NewClip,
+    quota: &Quota,
+    pool: &DatabasePool,
+) -> Result<Clip, ServiceError> {
+    let mut transaction = begin_transaction(pool).await?;
+    if let Some(api_key) = req.owner.key() {
+        query::lock_for_write(&mut transaction).await?;
+        let (quota, usage) = quota_and_usage(api_key, quota, &mut transaction).await?;
+        quota.check_new(&usage, req.content.as_str().len() as u64)?;
+    }
+    let clip: Clip = query::new_clip_in(req, &mut transaction).await?.try_into()?;
+    end_transaction(transaction).await?;
+    Ok(clip)
+}
+
+/// Retrieves a clip for changing it, if `credentials` show the owner or carry its password.
+pub async fn get_clip_for_update<'e, E>(
+    shortcode: &ShortCode,
+    credentials: &ask::Credentials,
+    executor: E,
+) -> Result<Clip, ServiceError>
+where
+    E: sqlx::Executor<'e, Database = Sqlite>,
+{
+    let clip: Clip = query::get_clip(shortcode.clone(), executor)
+        .await?
+        .try_into()?;
+    unexpired(&clip.expires_at)?;
+    let owns_key = match &credentials.api_key {
+        Some(api_key) => clip.owner.has_key(&api_key.hash()),
+        None => false,
+    };
+    let holds_token = match &credentials.edit_token {
+        Some(token) => clip.owner.accepts(token),
+        None => false,
+    };
+    let has_password = clip.password.has_password() && clip.password == credentials.password;
+    if owns_key || holds_token || has_password {
         Ok(clip)
+    } else {
+        Err(ServiceError::PermissionError(
+            "only the owner of the clip or its password may change it".to_owned(),
+        ))
+    }
+}
+
+/// Fails if replacing the content of `clip` with `content` exceeds the [`Quota`] of its owner.
+async fn check_update_quota(
+    clip: &Clip,
+    content: &field::Content,
+    quota: &Quota,
+    transaction: &mut Transaction<'_>,
+) -> Result<(), ServiceError> {
+    if let Some(api_key) = clip.owner.key() {
+        let (quota, usage) = quota_and_usage(api_key, quota, transaction).await?;
+        let old = clip.content.as_str().len() as u64;
+        quota.check_update(&usage, old, content.as_str().len() as u64)?;
     }
+    Ok(())
 }
 
-pub async fn new_clip(req: ask::NewClip, pool: &DatabasePool) -> Result<Clip, ServiceError> {
-    let clip: Clip = query::new_clip(req, pool).await?.try_into()?;
+/// Replaces a clip, if `credentials` allow it and the new content fits the [`Quota`] of
+/// its owner. Like [`new_clip`], the check and the update run under the write lock.
+pub async fn update_clip(
+    req: ask::UpdateClip,
+    credentials: &ask::Credentials,
+    quota: &Quota,
+    pool: &DatabasePool,
+) -> Result<Clip, ServiceError> {
+    let mut transaction = begin_transaction(pool).await?;
+    query::lock_for_write(&mut transaction).await?;
+    let current = get_clip_for_update(&req.shortcode, credentials, &mut transaction).await?;
+    check_update_quota(&current, &req.content, quota, &mut transaction).await?;
+    let clip: Clip = query::update_clip_in(req, &mut transaction)
+        .await?
+        .try_into()?;
+    end_transaction(transaction).await?;
     Ok(clip)
 }
 
-pub async fn update_clip(req: ask::UpdateClip, pool: &DatabasePool) -> Result<Clip, ServiceError> {
-    let clip: Clip = query::update_clip(req, pool).await?.try_into()?;
+/// Updates only the fields supplied in the [`PatchClip`](ask::PatchClip), checked like
+/// [`update_clip`].
+pub async fn patch_clip(
+    req: ask::PatchClip,
+    credentials: &ask::Credentials,
+    quota: &Quota,
+    pool: &DatabasePool,
+) -> Result<Clip, ServiceError> {
+    let mut transaction = begin_transaction(pool).await?;
+    query::lock_for_write(&mut transaction).await?;
+    let current = get_clip_for_update(&req.shortcode, credentials, &mut transaction).await?;
+    if let Some(content) = &req.content {
+        check_update_quota(&current, content, quota, &mut transaction).await?;
+    }
+    let clip: Clip = query::patch_clip_in(req, &mut transaction).await?.try_into()?;
+    end_transaction(transaction).await?;
     Ok(clip)
 }
 
+/// Deletes a clip, if `credentials` allow it. Like [`update_clip`], the check and the
+/// delete run under the write lock.
+pub async fn delete_clip(
+    req: ask::DeleteClip,
+    credentials: &ask::Credentials,
+    pool: &DatabasePool,
+) -> Result<(), ServiceError> {
+    let mut transaction = begin_transaction(pool).await?;
+    query::lock_for_write(&mut transaction).await?;
+    get_clip_for_update(&req.shortcode, credentials, &mut transaction).await?;
+    query::delete_clip(req, &mut transaction).await?;
+    end_transaction(transaction).await?;
+    Ok(())
+}
+
 pub async fn increase_hit_count(
     shortcode: &ShortCode,
     hits: i64,
     pool: &DatabasePool,
 ) -> Result<(), ServiceError> {
-    let _ = query::increment_hit(shortcode, hits, pool).await?;
-    Ok(())
+    Ok(query::increment_hit(shortcode, hits, pool).await?)
 }
 /// Creates a new [`ApiKey`].
-pub async fn generate_api_key(pool: &DatabasePool) -> Result<ApiKey, ServiceError> {
+pub async fn generate_api_key(
+    req: ask::NewApiKey,
+    pool: &DatabasePool,
+) -> Result<ApiKey, ServiceError> {
     let api_key = ApiKey::default();
-    Ok(query::save_api_key(api_key, pool).await?)
+    Ok(query::save_api_key(api_key, req, pool).await?)
+}
+
+/// Hashes the API keys saved before keys were stored hashed. Returns how many there were.
+pub async fn hash_legacy_api_keys(pool: &DatabasePool) -> Result<u64, ServiceError> {
+    Ok(query::hash_legacy_api_keys(pool).await?)
+}
+
+/// Makes sure an admin [`ApiKey`] exists, so that further keys can be created.
+///
+/// A `configured` key is saved (or restored) with every scope. Without one, a new
+/// admin key is generated if none exists yet. Returns the key if one was generated.
+pub async fn bootstrap_admin_key(
+    configured: Option<ApiKey>,
+    pool: &DatabasePool,
+) -> Result<Option<ApiKey>, ServiceError> {
+    let req = ask::NewApiKey {
+        name: "bootstrap admin".to_owned(),
+        scopes: Scopes::all(),
+        ..Default::default()
+    };
+    match configured {
+        Some(api_key) => {
+            query::upsert_api_key(api_key, req, pool).await?;
+            Ok(None)
+        }
+        None => {
+            let admin = Scope::Admin.to_string();
+            if query::count_api_keys_with_scope(&admin, pool).await? > 0 {
+                return Ok(None);
+            }
+            Ok(Some(generate_api_key(req, pool).await?))
+        }
+    }
+}
+
+/// The metadata of an [`ApiKey`], or `None` if the key does not exist.
+pub async fn get_api_key(
+    api_key: ApiKey,
+    pool: &DatabasePool,
+) -> Result<Option<ApiKeyInfo>, ServiceError> {
+    Ok(query::get_api_key(&api_key.hash(), pool)
+        .await?
+        .map(Into::into))
+}
+
+/// The [`Quota`] of the API key that hashes to `api_key`, and what its clips use of it.
+///
+/// Limits the key does not set itself are taken from `defaults`.
+async fn quota_and_usage(
+    api_key: &[u8],
+    defaults: &Quota,
+    transaction: &mut Transaction<'_>,
+) -> Result<(Quota, Usage), ServiceError> {
+    let quota = match query::get_api_key(api_key, &mut *transaction).await? {
+        Some(info) => ApiKeyInfo::from(info).quota.or(defaults),
+        None => *defaults,
+    };
+    let now = Utc::now();
+    let day_start = (now - Duration::days(1)).timestamp();
+    let usage = query::api_key_usage(api_key, now.timestamp(), day_start, transaction).await?;
+    Ok((quota, usage.into()))
+}
+
+/// The [`Quota`] of `api_key`, and what its clips use of it.
+pub async fn api_key_usage(
+    api_key: &ApiKey,
+    defaults: &Quota,
+    pool: &DatabasePool,
+) -> Result<(Quota, Usage), ServiceError> {
+    let mut transaction = begin_transaction(pool).await?;
+    quota_and_usage(&api_key.hash(), defaults, &mut transaction).await
+}
+
+/// The metadata of all API keys.
+pub async fn list_api_keys(pool: &DatabasePool) -> Result<Vec<ApiKeyInfo>, ServiceError> {
+    Ok(query::list_api_keys(pool)
+        .await?
+        .into_iter()
+        .map(Into::into)
+        .collect())
+}
+
+/// Records when the API key that hashes to `api_key` was last used.
+pub async fn touch_api_key(
+    api_key: &[u8],
+    used_at: i64,
+    transaction: &mut Transaction<'_>,
+) -> Result<(), ServiceError> {
+    Ok(query::touch_api_key(api_key, used_at, transaction).await?)
 }
 
 /// Revokes an existing [`ApiKey`].
@@ -62,11 +294,120 @@ pub async fn revoke_api_key(
     Ok(query::revoke_api_key(api_key, pool).await?)
 }
 
-/// Determines if an [`ApiKey`] is valid.
-pub async fn api_key_is_valid(api_key: ApiKey, pool: &DatabasePool) -> Result<bool, ServiceError> {
-    Ok(query::api_key_is_valid(api_key, pool).await?)
+/// Revokes the API key with the public `key_id`.
+pub async fn revoke_api_key_by_id(
+    key_id: &str,
+    pool: &DatabasePool,
+) -> Result<query::RevocationStatus, ServiceError> {
+    Ok(query::revoke_api_key_by_id(key_id, pool).await?)
 }
 
+/// Moves expired clips to the trash.
 pub async fn delete_expired(pool: &DatabasePool) -> Result<u64, ServiceError> {
     Ok(query::delete_expired(pool).await?)
 }
+
+/// Permanently deletes clips that have been in the trash for longer than `grace`.
+pub async fn purge_trash(grace: Duration, pool: &DatabasePool) -> Result<u64, ServiceError> {
+    let deleted_before = (Utc::now() - grace).timestamp();
+    Ok(query::purge_trash(deleted_before, pool).await?)
+}
+
+/// Deletes stored content that no clip, live or in the trash, uses any more.
+pub async fn delete_orphaned_blobs(pool: &DatabasePool) -> Result<u64, ServiceError> {
+    Ok(query::delete_orphaned_blobs(pool).await?)
+}
+
+/// Moves the content of up to `limit` clips saved before content was deduplicated to
+/// shared storage.
+pub async fn dedupe_inline_content(limit: i64, pool: &DatabasePool) -> Result<u64, ServiceError> {
+    Ok(query::dedupe_inline_content(limit, pool).await?)
+}
+
+/// Compresses up to `limit` blobs stored before compression. Returns what those blobs
+/// take now, and the space taken by all stored content.
+pub async fn compress_stored_content(
+    limit: i64,
+    pool: &DatabasePool,
+) -> Result<(ContentStorage, ContentStorage), ServiceError> {
+    let batch = query::compress_blobs(limit, pool).await?.into();
+    let total = query::content_storage(pool).await?.into();
+    Ok((batch, total))
+}
+
+/// A live clip with the same content and settings as `req`, created by the same owner.
+pub async fn find_identical_clip(
+    req: &ask::NewClip,
+    pool: &DatabasePool,
+) -> Result<Option<Clip>, ServiceError> {
+    match query::find_identical_clip(req.clone(), Utc::now().timestamp(), pool).await? {
+        Some(clip) => Ok(Some(clip.try_into()?)),
+        None => Ok(None),
+    }
+}
+
+/// The clips in the trash, most recently deleted first.
+pub async fn list_trash(pool: &DatabasePool) -> Result<Vec<TrashedClip>, ServiceError> {
+    query::list_trash(pool)
+        .await?
+        .into_iter()
+        .map(|clip| Ok(clip.try_into()?))
+        .collect()
+}
+
+/// Takes a clip out of the trash, clearing its expiry if that has passed.
+pub async fn restore_clip(
+    shortcode: &ShortCode,
+    pool: &DatabasePool,
+) -> Result<Clip, ServiceError> {
+    let now = Utc::now().timestamp();
+    Ok(query::restore_clip(shortcode, now, pool)
+        .await?
+        .try_into()?)
+}
+
+/// Permanently deletes API keys that expired longer than `grace` ago.
+pub async fn delete_stale_api_keys(
+    grace: Duration,
+    pool: &DatabasePool,
+) -> Result<u64, ServiceError> {
+    let expired_before = (Utc::now() - grace).timestamp();
+    Ok(query::delete_stale_api_keys(expired_before, pool).await?)
+}
+
+/// Refreshes the query planner statistics and reclaims unused space in the database.
+pub async fn optimize_database(pool: &DatabasePool) -> Result<(), ServiceError> {
+    Ok(query::optimize(pool).await?)
+}
+
+/// Rolls up the [`DailyStats`] of today, and finishes counting the clips created yesterday.
+pub async fn rollup_daily_stats(pool: &DatabasePool) -> Result<DailyStats, ServiceError> {
+    let now = Utc::now();
+    let today = now
+        .date_naive()
+        .and_time(NaiveTime::MIN)
+        .and_utc()
+        .timestamp();
+    let yesterday = today - Duration::days(1).num_seconds();
+    query::rollup_daily_stats(yesterday, now.timestamp(), false, pool).await?;
+    Ok(
+        query::rollup_daily_stats(today, now.timestamp(), true, pool)
+            .await?
+            .into(),
+    )
+}
+
+/// Checks that the database can be queried.
+pub async fn ping_database(pool: &DatabasePool) -> Result<(), ServiceError> {
+    Ok(query::ping(pool).await?)
+}
+
+/// Lists the versions of bundled migrations that have not been applied to the database.
+pub async fn pending_migrations(pool: &DatabasePool) -> Result<Vec<i64>, ServiceError> {
+    let applied = query::applied_migrations(pool).await?;
+    Ok(MIGRATOR
+        .iter()
+        .map(|migration| migration.version)
+        .filter(|version| !applied.contains(version))
+        .collect())
+}
@@ -1,8 +1,11 @@
 use derive_more::Constructor;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use crate::domain::clip::field;
-use crate::ShortCode;
+use crate::domain::{Quota, Scopes};
+use crate::web::ApiKey;
+use crate::{ShortCode, Time};
 
 #[derive(Debug, Deserialize, Serialize, Constructor)]
 pub struct GetClip {
@@ -10,15 +13,6 @@ pub struct GetClip {
     pub password: field::Password,
 }
 
-impl GetClip {
-    pub fn from_str(shortcode: &str) -> Self {
-        Self {
-            shortcode: ShortCode::from(shortcode),
-            password: field::Password::default(),
-        }
-    }
-}
-
 impl From<ShortCode> for GetClip {
     fn from(shortcode: ShortCode) -> Self {
         Self {
@@ -30,23 +24,81 @@ impl From<ShortCode> for GetClip {
 
 impl From<&str> for GetClip {
     fn from(s: &str) -> Self {
-        Self::from_str(s)
+        Self {
+            shortcode: ShortCode::from(s),
+            password: field::Password::default(),
+        }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
 pub struct NewClip {
     pub title: field::Title,
     pub content: field::Content,
     pub password: field::Password,
     pub expires_at: field::ExpiresAt,
+    /// Set from the API key or edit token of the request, never from the request body.
+    #[serde(skip)]
+    pub owner: field::Owner,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct UpdateClip {
     pub title: field::Title,
     pub content: field::Content,
     pub password: field::Password,
     pub expires_at: field::ExpiresAt,
     pub shortcode: field::ShortCode,
+    /// Only update if the clip is still at this version.
+    #[serde(skip)]
+    pub version: Option<field::Version>,
+}
+
+/// A partial update of a clip. `None` leaves a field unchanged.
+///
+/// Clearing an optional field is expressed with an empty value, e.g. `Some(Password::default())`.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct PatchClip {
+    pub shortcode: field::ShortCode,
+    pub title: Option<field::Title>,
+    pub content: Option<field::Content>,
+    pub password: Option<field::Password>,
+    pub expires_at: Option<field::ExpiresAt>,
+    /// Only update if the clip is still at this version.
+    #[serde(skip)]
+    pub version: Option<field::Version>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DeleteClip {
+    pub shortcode: field::ShortCode,
+}
+
+/// Proof of the right to change a clip: being its [`Owner`](field::Owner), or knowing its password.
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub api_key: Option<ApiKey>,
+    pub edit_token: Option<field::EditToken>,
+    pub password: field::Password,
+}
+
+/// A request to create an [`ApiKey`].
+#[derive(Debug, Default)]
+pub struct NewApiKey {
+    /// A label to tell keys apart, e.g. the client that uses it.
+    pub name: String,
+    pub scopes: Scopes,
+    pub expires_at: Option<Time>,
+    /// Limits that differ from the server defaults.
+    pub quota: Quota,
+}
+
+impl NewApiKey {
+    /// An unnamed key that never expires.
+    pub fn with_scopes(scopes: Scopes) -> Self {
+        Self {
+            scopes,
+            ..Self::default()
+        }
+    }
 }
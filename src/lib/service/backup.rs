@@ -0,0 +1,377 @@
+//! Backups of every clip and API key, as a versioned tar archive.
+//!
+//! The archive holds `manifest.json`, describing the backup, and one JSON record per line
+//! in `clips.jsonl` and `api_keys.jsonl`. API keys are stored by their SHA-256, as they
+//! are in the database, so an archive does not hold the keys themselves.
+
+use crate::data::model::{ApiKeyRecord, ClipRecord};
+use crate::data::{query, DataError, DatabasePool};
+use crate::service::{action, ServiceError};
+use crate::Time;
+use chrono::Utc;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// The `format` of every backup manifest.
+pub const FORMAT: &str = "clipstash-backup";
+/// The newest archive layout this build writes and reads.
+pub const FORMAT_VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const CLIPS: &str = "clips.jsonl";
+const API_KEYS: &str = "api_keys.jsonl";
+
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("archive error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid record: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0}")]
+    Service(#[from] ServiceError),
+    #[error("not a clipstash backup")]
+    NotABackup,
+    #[error("backup format version {0} is newer than this build supports")]
+    UnsupportedVersion(u32),
+    #[error("{0} is missing from the archive")]
+    Missing(&'static str),
+}
+
+impl From<DataError> for BackupError {
+    fn from(err: DataError) -> Self {
+        Self::Service(err.into())
+    }
+}
+
+/// Describes a backup archive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub version: u32,
+    pub created_at: Time,
+    /// The database migrations applied when the backup was taken.
+    pub migrations: Vec<i64>,
+    pub clips: usize,
+    pub api_keys: usize,
+}
+
+/// What to do with a backed up clip whose shortcode another clip already uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Restore the clip under a new shortcode.
+    Rename,
+    /// Leave the clip out.
+    Skip,
+}
+
+impl FromStr for OnConflict {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rename" => Ok(Self::Rename),
+            "skip" => Ok(Self::Skip),
+            _ => Err(format!("expected `rename` or `skip`, got `{}`", s)),
+        }
+    }
+}
+
+/// A clip restored under a new shortcode.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Renamed {
+    pub from: String,
+    pub to: String,
+}
+
+/// What a restore did. Restoring the same archive again only counts records as present.
+#[derive(Debug, Default, Serialize)]
+pub struct RestoreReport {
+    pub clips_restored: usize,
+    /// Clips whose id already existed, which are left as they are.
+    pub clips_present: usize,
+    /// Clips left out because their shortcode was taken.
+    pub clips_skipped: usize,
+    pub renamed: Vec<Renamed>,
+    pub api_keys_restored: usize,
+    /// API keys that already existed, which are left as they are.
+    pub api_keys_present: usize,
+}
+
+fn to_jsonl<T: Serialize>(records: &[T]) -> Result<Vec<u8>, BackupError> {
+    let mut lines = Vec::new();
+    for record in records {
+        serde_json::to_writer(&mut lines, record)?;
+        lines.push(b'\n');
+    }
+    Ok(lines)
+}
+
+fn from_jsonl<T: DeserializeOwned>(lines: &[u8]) -> Result<Vec<T>, BackupError> {
+    lines
+        .split(|byte| *byte == b'\n')
+        .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+        .map(|line| Ok(serde_json::from_slice(line)?))
+        .collect()
+}
+
+fn append<W: Write>(
+    archive: &mut tar::Builder<W>,
+    path: &str,
+    data: &[u8],
+    mtime: u64,
+) -> Result<(), BackupError> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o600);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    archive.append_data(&mut header, path, data)?;
+    Ok(())
+}
+
+/// Writes every clip, including those in the trash, and every API key to `writer`.
+///
+/// Take the backup from a [snapshot](crate::data::snapshot) for a consistent view of a
+/// database in use.
+pub async fn write_archive<W: Write>(
+    pool: &DatabasePool,
+    writer: W,
+) -> Result<Manifest, BackupError> {
+    let clips = query::export_clips(pool).await?;
+    let api_keys = query::export_api_keys(pool).await?;
+    let now = Utc::now();
+    let manifest = Manifest {
+        format: FORMAT.to_owned(),
+        version: FORMAT_VERSION,
+        created_at: now.into(),
+        // Databases set up by hand have no migration table, and so no applied migrations.
+        migrations: query::applied_migrations(pool).await.unwrap_or_default(),
+        clips: clips.len(),
+        api_keys: api_keys.len(),
+    };
+
+    let mtime = now.timestamp().max(0) as u64;
+    let mut archive = tar::Builder::new(writer);
+    append(
+        &mut archive,
+        MANIFEST,
+        &serde_json::to_vec_pretty(&manifest)?,
+        mtime,
+    )?;
+    append(&mut archive, CLIPS, &to_jsonl(&clips)?, mtime)?;
+    append(&mut archive, API_KEYS, &to_jsonl(&api_keys)?, mtime)?;
+    archive.into_inner()?.flush()?;
+    Ok(manifest)
+}
+
+/// The contents of a backup archive.
+struct Archive {
+    manifest: Manifest,
+    clips: Vec<ClipRecord>,
+    api_keys: Vec<ApiKeyRecord>,
+}
+
+fn read_archive<R: Read>(reader: R) -> Result<Archive, BackupError> {
+    let (mut manifest, mut clips, mut api_keys) = (None, None, None);
+    for entry in tar::Archive::new(reader).entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().into_owned();
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data)?;
+        match path.as_str() {
+            MANIFEST => manifest = Some(data),
+            CLIPS => clips = Some(data),
+            API_KEYS => api_keys = Some(data),
+            _ => (),
+        }
+    }
+    let manifest: Manifest = match manifest {
+        Some(data) => serde_json::from_slice(&data).map_err(|_| BackupError::NotABackup)?,
+        None => return Err(BackupError::NotABackup),
+    };
+    if manifest.format != FORMAT {
+        return Err(BackupError::NotABackup);
+    }
+    if manifest.version > FORMAT_VERSION {
+        return Err(BackupError::UnsupportedVersion(manifest.version));
+    }
+    Ok(Archive {
+        manifest,
+        clips: from_jsonl(&clips.ok_or(BackupError::Missing(CLIPS))?)?,
+        api_keys: from_jsonl(&api_keys.ok_or(BackupError::Missing(API_KEYS))?)?,
+    })
+}
+
+/// Restores the clips and API keys in the archive read from `reader`.
+///
+/// The restore runs in a single transaction, so it either completes or changes nothing.
+/// Records that already exist are left alone, so restoring again is harmless. API keys
+/// are restored before the clips they own. Clips whose shortcode is taken by a
+/// different clip are handled according to `on_conflict`. API keys whose public id is
+/// taken get a new one.
+pub async fn restore_archive<R: Read>(
+    pool: &DatabasePool,
+    reader: R,
+    on_conflict: OnConflict,
+) -> Result<(Manifest, RestoreReport), BackupError> {
+    let archive = read_archive(reader)?;
+    let mut report = RestoreReport::default();
+    let mut transaction = action::begin_transaction(pool).await?;
+
+    for mut api_key in archive.api_keys {
+        if query::api_key_record_exists(&api_key, &mut transaction).await? {
+            report.api_keys_present += 1;
+            continue;
+        }
+        while query::key_id_taken(&api_key.key_id, &mut transaction).await? {
+            api_key.key_id = format!("{:016x}", rand::random::<u64>());
+        }
+        query::insert_api_key_record(&api_key, &mut transaction).await?;
+        report.api_keys_restored += 1;
+    }
+
+    for mut clip in archive.clips {
+        if query::clip_record_exists(&clip, &mut transaction).await? {
+            report.clips_present += 1;
+            continue;
+        }
+        if query::shortcode_taken(&clip.shortcode, &mut transaction).await? {
+            match on_conflict {
+                OnConflict::Skip => {
+                    report.clips_skipped += 1;
+                    continue;
+                }
+                OnConflict::Rename => {
+                    let to = query::free_shortcode(&mut transaction).await?;
+                    let from = std::mem::replace(&mut clip.shortcode, to.clone());
+                    report.renamed.push(Renamed { from, to });
+                }
+            }
+        }
+        query::insert_clip_record(&clip, &mut transaction).await?;
+        report.clips_restored += 1;
+    }
+
+    action::end_transaction(transaction).await?;
+    Ok((archive.manifest, report))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::data::query::{new_clip, test_helpers::model_new_clip};
+    use crate::domain::clip::field;
+    use crate::service::{action, ask};
+    use crate::web::test_helpers::new_db;
+
+    #[test]
+    fn restores_idempotently_and_renames_conflicts() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let source = new_db(rt.handle());
+        let target = new_db(rt.handle());
+        let (source, target) = (source.get_pool(), target.get_pool());
+
+        let archive = rt.block_on(async {
+            new_clip(model_new_clip("same"), source).await.unwrap();
+            new_clip(model_new_clip("other"), source).await.unwrap();
+            action::generate_api_key(ask::NewApiKey::default(), source)
+                .await
+                .unwrap();
+            let mut archive = Vec::new();
+            let manifest = write_archive(source, &mut archive).await.unwrap();
+            assert_eq!((manifest.clips, manifest.api_keys), (2, 1));
+            archive
+        });
+
+        rt.block_on(async {
+            // A different clip already uses one of the shortcodes.
+            new_clip(model_new_clip("same"), target).await.unwrap();
+
+            let (_, report) = restore_archive(target, archive.as_slice(), OnConflict::Rename)
+                .await
+                .unwrap();
+            assert_eq!(report.clips_restored, 2);
+            assert_eq!(report.api_keys_restored, 1);
+            assert_eq!(report.renamed.len(), 1);
+            assert_eq!(report.renamed[0].from, "same");
+
+            let (_, again) = restore_archive(target, archive.as_slice(), OnConflict::Rename)
+                .await
+                .unwrap();
+            assert_eq!((again.clips_restored, again.clips_present), (0, 2));
+            assert_eq!((again.api_keys_restored, again.api_keys_present), (0, 1));
+
+            assert_eq!(query::export_clips(target).await.unwrap().len(), 3);
+            let renamed = &report.renamed[0].to;
+            let clip = query::get_clip(renamed.clone(), target).await.unwrap();
+            assert_eq!(clip.content, b"content for clip 'same'");
+        });
+    }
+
+    #[test]
+    fn restores_owned_clips_into_an_empty_database() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let source = new_db(rt.handle());
+        let target = new_db(rt.handle());
+        let (source, target) = (source.get_pool(), target.get_pool());
+
+        let api_key = rt.block_on(async {
+            action::generate_api_key(ask::NewApiKey::default(), source)
+                .await
+                .unwrap()
+        });
+        let archive = rt.block_on(async {
+            let owned = ask::NewClip {
+                title: field::Title::default(),
+                content: field::Content::new("owned content").unwrap(),
+                password: field::Password::default(),
+                expires_at: field::ExpiresAt::default(),
+                owner: api_key.clone().into(),
+            };
+            action::new_clip(owned, &Default::default(), source)
+                .await
+                .unwrap();
+            let mut archive = Vec::new();
+            write_archive(source, &mut archive).await.unwrap();
+            archive
+        });
+        let text = String::from_utf8_lossy(&archive);
+        assert!(!text.contains(&api_key.to_base64()));
+
+        rt.block_on(async {
+            let (_, report) = restore_archive(target, archive.as_slice(), OnConflict::Skip)
+                .await
+                .unwrap();
+            assert_eq!((report.api_keys_restored, report.clips_restored), (1, 1));
+            let clips = query::export_clips(target).await.unwrap();
+            assert_eq!(clips[0].owner_key, Some(api_key.hash()));
+            let restored = action::get_api_key(api_key, target).await.unwrap();
+            assert!(restored.is_some());
+        });
+    }
+
+    #[test]
+    fn rejects_other_archives() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let db = new_db(rt.handle());
+        let mut archive = tar::Builder::new(Vec::new());
+        let manifest = br#"{"format": "clipstash-backup", "version": 99, "created_at": "2030-01-01T00:00:00Z", "migrations": [], "clips": 0, "api_keys": 0}"#;
+        append(&mut archive, MANIFEST, manifest, 0).unwrap();
+        let archive = archive.into_inner().unwrap();
+
+        let result = rt.block_on(restore_archive(
+            db.get_pool(),
+            archive.as_slice(),
+            OnConflict::Skip,
+        ));
+        assert!(matches!(result, Err(BackupError::UnsupportedVersion(99))));
+        let result = rt.block_on(restore_archive(
+            db.get_pool(),
+            &b"not a tar"[..],
+            OnConflict::Skip,
+        ));
+        assert!(result.is_err());
+    }
+}
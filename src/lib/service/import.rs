@@ -0,0 +1,531 @@
+//! Imports clips exported from other paste services.
+//!
+//! Clips can come from a directory with one file per clip, named after its shortcode, or
+//! from a JSON or CSV export with one record per clip. Records are checked like any new
+//! clip, and keep their shortcode unless it is taken or unusable.
+
+use crate::data::model;
+use crate::data::{query, DataError, DatabasePool, Transaction};
+use crate::domain::clip::{field, ClipError};
+use crate::service::{action, ask, ServiceError};
+use crate::{ShortCode, Time};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// The longest shortcode kept from an import.
+const MAX_SHORTCODE_CHARS: usize = 64;
+
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error("read error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid JSON export: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("cannot tell the format of {0}, expected a directory or a .json, .jsonl or .csv file")]
+    UnknownFormat(String),
+    #[error("{0}")]
+    Service(#[from] ServiceError),
+}
+
+impl From<DataError> for ImportError {
+    fn from(err: DataError) -> Self {
+        Self::Service(err.into())
+    }
+}
+
+/// Why a record of an export cannot be imported.
+#[derive(Debug, Error)]
+pub enum RecordError {
+    #[error("unreadable record: {0}")]
+    Malformed(String),
+    #[error("{0}")]
+    Clip(#[from] ClipError),
+}
+
+impl RecordError {
+    /// The name of the clip field that failed validation, if the error is tied to one.
+    pub fn field(&self) -> Option<&'static str> {
+        match self {
+            Self::Malformed(_) => None,
+            Self::Clip(e) => e.field(),
+        }
+    }
+}
+
+/// The layout of an export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One file per clip, named after its shortcode.
+    Directory,
+    /// An array of records, or one record per line.
+    Json,
+    /// One record per row, under a header row naming the fields.
+    Csv,
+}
+
+impl Format {
+    /// Tells the format from the path: a directory, or a file's extension.
+    pub fn detect(path: &Path) -> Result<Self, ImportError> {
+        if path.is_dir() {
+            return Ok(Self::Directory);
+        }
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") | Some("jsonl") => Ok(Self::Json),
+            Some("csv") => Ok(Self::Csv),
+            _ => Err(ImportError::UnknownFormat(path.display().to_string())),
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dir" | "directory" => Ok(Self::Directory),
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            _ => Err(format!("expected `dir`, `json` or `csv`, got `{}`", s)),
+        }
+    }
+}
+
+/// A time given as RFC 3339 text, a date, or seconds since the Unix epoch.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Stamp {
+    Seconds(i64),
+    Text(String),
+}
+
+impl Stamp {
+    fn into_text(self) -> String {
+        match self {
+            Self::Seconds(seconds) => seconds.to_string(),
+            Self::Text(text) => text,
+        }
+    }
+}
+
+fn parse_time(s: &str) -> Result<Option<Time>, ClipError> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Ok(None);
+    }
+    if let Ok(seconds) = s.parse::<i64>() {
+        return match chrono::DateTime::from_timestamp(seconds, 0) {
+            Some(time) => Ok(Some(time.into())),
+            None => Err(ClipError::InvalidDate(format!(
+                "{} is out of range",
+                seconds
+            ))),
+        };
+    }
+    Ok(Some(Time::from_str(s)?))
+}
+
+/// A clip as another service exported it. Common alternative field names are accepted.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ImportRecord {
+    #[serde(default, alias = "key", alias = "slug", alias = "id")]
+    pub shortcode: Option<String>,
+    #[serde(default, alias = "name")]
+    pub title: Option<String>,
+    #[serde(default, alias = "body", alias = "text", alias = "paste")]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default, alias = "created", deserialize_with = "stamp")]
+    pub created_at: Option<String>,
+    #[serde(
+        default,
+        alias = "expires",
+        alias = "expiry",
+        deserialize_with = "stamp"
+    )]
+    pub expires_at: Option<String>,
+}
+
+fn stamp<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(Option::<Stamp>::deserialize(deserializer)?.map(Stamp::into_text))
+}
+
+/// A clip checked for import, with the shortcode it asks for.
+struct Checked {
+    shortcode: Option<String>,
+    created_at: Option<Time>,
+    clip: ask::NewClip,
+}
+
+impl ImportRecord {
+    /// Maps the record onto a new clip, checking each field like the web and API do.
+    fn check(self) -> Result<Checked, ClipError> {
+        let content = self.content.unwrap_or_default();
+        let expires_at =
+            field::ExpiresAt::new(parse_time(self.expires_at.as_deref().unwrap_or(""))?);
+        if expires_at.has_passed() {
+            return Err(ClipError::InvalidDate(
+                "the clip has already expired".to_owned(),
+            ));
+        }
+        Ok(Checked {
+            shortcode: self
+                .shortcode
+                .map(|shortcode| shortcode.trim().to_owned())
+                .filter(|shortcode| !shortcode.is_empty()),
+            created_at: parse_time(self.created_at.as_deref().unwrap_or(""))?,
+            clip: ask::NewClip {
+                title: field::Title::new(self.title)?,
+                content: field::Content::new(&content)?,
+                password: field::Password::new(self.password)?,
+                expires_at,
+                owner: field::Owner::default(),
+            },
+        })
+    }
+}
+
+/// A record of an export, with where it came from, or why it could not be read.
+#[derive(Debug)]
+pub struct Entry {
+    /// The file, or the position of the record in the export.
+    pub source: String,
+    pub record: Result<ImportRecord, RecordError>,
+}
+
+fn read_directory(path: &Path) -> Result<Vec<Entry>, ImportError> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_file() && !name.starts_with('.') {
+            files.push((name, entry.path()));
+        }
+    }
+    files.sort();
+    let mut entries = Vec::with_capacity(files.len());
+    for (name, path) in files {
+        let record = match String::from_utf8(std::fs::read(&path)?) {
+            Ok(content) => Ok(ImportRecord {
+                shortcode: path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned()),
+                content: Some(content),
+                ..Default::default()
+            }),
+            Err(_) => Err(ClipError::InvalidContent("not UTF-8 text".to_owned()).into()),
+        };
+        entries.push(Entry {
+            source: name,
+            record,
+        });
+    }
+    Ok(entries)
+}
+
+/// Reads a JSON array of records, or one record per line.
+///
+/// A record that does not fit [`ImportRecord`], or a line that is not JSON, is reported
+/// with its entry rather than failing the whole export.
+fn read_json(data: &[u8]) -> Result<Vec<Entry>, ImportError> {
+    let malformed = |e: serde_json::Error| RecordError::Malformed(e.to_string());
+    if data.trim_ascii_start().starts_with(b"[") {
+        let values: Vec<serde_json::Value> = serde_json::from_slice(data)?;
+        return Ok(values
+            .into_iter()
+            .enumerate()
+            .map(|(n, value)| Entry {
+                source: format!("record {}", n + 1),
+                record: serde_json::from_value(value).map_err(malformed),
+            })
+            .collect());
+    }
+    Ok(data
+        .split(|&byte| byte == b'\n')
+        .enumerate()
+        .filter(|(_, line)| !line.trim_ascii().is_empty())
+        .map(|(n, line)| Entry {
+            source: format!("line {}", n + 1),
+            record: serde_json::from_slice(line).map_err(malformed),
+        })
+        .collect())
+}
+
+/// Reads one record per row. A row that cannot be read is reported with its entry.
+fn read_csv(data: &[u8]) -> Result<Vec<Entry>, ImportError> {
+    let mut reader = csv::Reader::from_reader(data);
+    Ok(reader
+        .deserialize::<ImportRecord>()
+        .enumerate()
+        .map(|(n, record)| Entry {
+            source: format!("row {}", n + 1),
+            record: record.map_err(|e| RecordError::Malformed(e.to_string())),
+        })
+        .collect())
+}
+
+/// Reads the records of the export at `path`.
+pub fn read_export(path: &Path, format: Format) -> Result<Vec<Entry>, ImportError> {
+    match format {
+        Format::Directory => read_directory(path),
+        Format::Json => read_json(&std::fs::read(path)?),
+        Format::Csv => read_csv(&std::fs::read(path)?),
+    }
+}
+
+/// A record whose shortcode could not be kept.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Conflict {
+    pub source: String,
+    pub shortcode: String,
+    /// Why the shortcode could not be kept.
+    pub reason: &'static str,
+    /// The shortcode the clip was, or would be, imported under.
+    pub imported_as: String,
+}
+
+/// A record that is not a valid clip.
+#[derive(Debug, Clone, Serialize)]
+pub struct Failure {
+    pub source: String,
+    /// The clip field that failed validation, if the error is tied to one.
+    pub field: Option<&'static str>,
+    pub error: String,
+}
+
+/// What an import did, or would do in a dry run.
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub imported: usize,
+    /// Imported clips whose shortcode could not be kept.
+    pub conflicts: Vec<Conflict>,
+    /// Records left out because they are not valid clips.
+    pub failed: Vec<Failure>,
+}
+
+/// Why `shortcode` cannot be kept, if it cannot.
+async fn conflict(
+    shortcode: &str,
+    claimed: &HashSet<String>,
+    transaction: &mut Transaction<'_>,
+) -> Result<Option<&'static str>, DataError> {
+    let usable = shortcode.chars().count() <= MAX_SHORTCODE_CHARS
+        && shortcode
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    Ok(if !usable {
+        Some("not usable in a URL")
+    } else if claimed.contains(shortcode) {
+        Some("used by an earlier record")
+    } else if query::shortcode_taken(shortcode, transaction).await? {
+        Some("taken by an existing clip")
+    } else {
+        None
+    })
+}
+
+/// Imports the clips in `entries`, or with `dry_run` only reports what would happen.
+///
+/// Records that are not valid clips are left out and reported. A clip keeps its original
+/// shortcode unless another clip, or an earlier record, has it or it is unusable in a
+/// URL; it then gets a new one, which is reported as a conflict.
+///
+/// The clips are created in a single transaction, so an import either completes or
+/// changes nothing.
+pub async fn import(
+    entries: Vec<Entry>,
+    dry_run: bool,
+    pool: &DatabasePool,
+) -> Result<ImportReport, ImportError> {
+    let mut report = ImportReport {
+        dry_run,
+        ..Default::default()
+    };
+    let mut claimed = HashSet::new();
+    let mut transaction = action::begin_transaction(pool).await?;
+
+    for Entry { source, record } in entries {
+        let checked = match record.and_then(|record| Ok(record.check()?)) {
+            Ok(checked) => checked,
+            Err(e) => {
+                report.failed.push(Failure {
+                    source,
+                    field: e.field(),
+                    error: e.to_string(),
+                });
+                continue;
+            }
+        };
+
+        let conflict = match &checked.shortcode {
+            Some(shortcode) => conflict(shortcode, &claimed, &mut transaction).await?,
+            None => None,
+        };
+        let shortcode = match (checked.shortcode, conflict) {
+            (Some(shortcode), None) => shortcode,
+            (original, reason) => {
+                let mut shortcode = query::free_shortcode(&mut transaction).await?;
+                while claimed.contains(&shortcode) {
+                    shortcode = query::free_shortcode(&mut transaction).await?;
+                }
+                if let (Some(original), Some(reason)) = (original, reason) {
+                    report.conflicts.push(Conflict {
+                        source,
+                        shortcode: original,
+                        reason,
+                        imported_as: shortcode.clone(),
+                    });
+                }
+                shortcode
+            }
+        };
+        claimed.insert(shortcode.clone());
+
+        if !dry_run {
+            let mut clip =
+                model::NewClip::from(checked.clip).with_shortcode(ShortCode::from(shortcode));
+            if let Some(created_at) = checked.created_at {
+                clip = clip.with_created_at(created_at);
+            }
+            query::new_clip_in(clip, &mut transaction).await?;
+        }
+        report.imported += 1;
+    }
+    if !dry_run {
+        action::end_transaction(transaction).await?;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::data::query::{new_clip, test_helpers::model_new_clip};
+    use crate::web::test_helpers::new_db;
+
+    const CSV: &[u8] = b"key,title,body,expires\n\
+        first,First,hello,\n\
+        taken,,clashes,\n\
+        first,,again,\n\
+        ../up,,odd shortcode,\n\
+        ,,,\n\
+        late,,too late,2001-01-01\n";
+
+    #[test]
+    fn reads_json_arrays_and_lines() {
+        let array = br#"[{"slug": "a", "text": "one", "created": 1600000000}, {"content": "two"}]"#;
+        let entries = read_json(array).unwrap();
+        assert_eq!(entries.len(), 2);
+        let first = entries[0].record.as_ref().unwrap();
+        assert_eq!(first.shortcode.as_deref(), Some("a"));
+        assert_eq!(first.created_at.as_deref(), Some("1600000000"));
+
+        let lines = b"{\"content\": \"one\"}\n{\"content\": \"two\"}\n";
+        assert_eq!(read_json(lines).unwrap().len(), 2);
+
+        let broken = b"{\"content\": \"one\"}\n{\"content\": \n\n{\"content\": 3}\n";
+        let entries = read_json(broken).unwrap();
+        let sources: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.source.as_str(), entry.record.is_ok()))
+            .collect();
+        assert_eq!(
+            sources,
+            vec![("line 1", true), ("line 2", false), ("line 4", false)]
+        );
+    }
+
+    #[test]
+    fn reports_malformed_rows() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let db = new_db(rt.handle());
+        let csv = b"key,body\nfine,hello\nshort\nalso,fine\n";
+        let report = rt
+            .block_on(import(read_csv(csv).unwrap(), true, db.get_pool()))
+            .unwrap();
+        assert_eq!(report.imported, 2);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].source, "row 2");
+        assert_eq!(report.failed[0].field, None);
+    }
+
+    #[test]
+    fn dry_run_reports_conflicts_and_failures() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        rt.block_on(async {
+            new_clip(model_new_clip("taken"), pool).await.unwrap();
+
+            let report = import(read_csv(CSV).unwrap(), true, pool).await.unwrap();
+            assert_eq!(report.imported, 4);
+            let reasons: Vec<_> = report
+                .conflicts
+                .iter()
+                .map(|c| (c.source.as_str(), c.reason))
+                .collect();
+            assert_eq!(
+                reasons,
+                vec![
+                    ("row 2", "taken by an existing clip"),
+                    ("row 3", "used by an earlier record"),
+                    ("row 4", "not usable in a URL"),
+                ]
+            );
+            let failed: Vec<_> = report
+                .failed
+                .iter()
+                .map(|f| (f.source.as_str(), f.field))
+                .collect();
+            assert_eq!(
+                failed,
+                vec![("row 5", Some("content")), ("row 6", Some("expires_at"))]
+            );
+            assert_eq!(query::export_clips(pool).await.unwrap().len(), 1);
+        });
+    }
+
+    #[test]
+    fn imports_under_original_shortcodes() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        rt.block_on(async {
+            new_clip(model_new_clip("taken"), pool).await.unwrap();
+
+            let report = import(read_csv(CSV).unwrap(), false, pool).await.unwrap();
+            assert_eq!(report.imported, 4);
+            let first = query::get_clip("first".to_owned(), pool).await.unwrap();
+            assert_eq!(first.content, b"hello");
+            let renamed = &report.conflicts[0].imported_as;
+            let clip = query::get_clip(renamed.clone(), pool).await.unwrap();
+            assert_eq!(clip.content, b"clashes");
+            assert_eq!(query::export_clips(pool).await.unwrap().len(), 5);
+        });
+    }
+
+    #[test]
+    fn failed_import_changes_nothing() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let db = new_db(rt.handle());
+        let pool = db.get_pool();
+        rt.block_on(async {
+            sqlx::query(
+                "CREATE TRIGGER fail_second BEFORE INSERT ON clips WHEN NEW.shortcode = 'second' \
+                 BEGIN SELECT RAISE(ABORT, 'disk full'); END",
+            )
+            .execute(pool)
+            .await
+            .unwrap();
+
+            let csv = b"key,body\nfirst,one\nsecond,two\n";
+            let result = import(read_csv(csv).unwrap(), false, pool).await;
+            assert!(result.is_err());
+            assert!(query::export_clips(pool).await.unwrap().is_empty());
+        });
+    }
+}
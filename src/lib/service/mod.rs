@@ -1,6 +1,9 @@
 pub mod action;
 pub mod ask;
+pub mod backup;
+pub mod import;
 
+use crate::domain::QuotaError;
 use crate::{ClipError, DataError};
 use sqlx;
 use thiserror::Error;
@@ -13,17 +16,26 @@ pub enum ServiceError {
     Clip(#[from] ClipError),
     #[error("not found")]
     NotFound,
+    #[error("expired")]
+    Expired,
+    #[error("conflict: {0}")]
+    Conflict(String),
     #[error("permission error: {0}")]
     PermissionError(String),
+    #[error("precondition failed: {0}")]
+    PreconditionFailed(String),
+    #[error("{0}")]
+    Quota(#[from] QuotaError),
 }
 
+/// SQLite extended result code for a violated `UNIQUE` constraint.
+const SQLITE_CONSTRAINT_UNIQUE: &str = "2067";
+
 impl From<DataError> for ServiceError {
     fn from(err: DataError) -> Self {
         match err {
-            DataError::DatabaseError(e) => match e {
-                sqlx::Error::RowNotFound => Self::NotFound,
-                _ => Self::Data(DataError::DatabaseError(e)),
-            },
+            DataError::DatabaseError(e) => e.into(),
+            DataError::VersionMismatch(_) => Self::PreconditionFailed(err.to_string()),
         }
     }
 }
@@ -32,6 +44,11 @@ impl From<sqlx::Error> for ServiceError {
     fn from(err: sqlx::Error) -> Self {
         match err {
             sqlx::Error::RowNotFound => Self::NotFound,
+            sqlx::Error::Database(ref e)
+                if e.code().as_deref() == Some(SQLITE_CONSTRAINT_UNIQUE) =>
+            {
+                Self::Conflict(e.message().to_owned())
+            }
             _ => Self::Data(DataError::DatabaseError(err)),
         }
     }
@@ -0,0 +1,104 @@
+//! Status of the maintenance [`Job`](crate::domain::maintenance::Job)s, mounted under `/api/v1`.
+//!
+//! Requires the [`Admin`] scope.
+
+use super::scope::{Admin, Scoped};
+use super::v1::rfc3339;
+use crate::domain::maintenance::{JobStatus, Maintenance};
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// How a maintenance job has been doing, as returned by the API.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct JobStatusResponse {
+    pub name: String,
+    /// e.g. `every 60s` or `cron 0 30 3 * * *`.
+    pub schedule: String,
+    pub runs: u64,
+    pub failures: u64,
+    pub running: bool,
+    /// RFC 3339 timestamp, absent if the job has not run yet.
+    #[schema(format = DateTime)]
+    pub last_started_at: Option<String>,
+    pub last_duration_ms: Option<u64>,
+    /// What the last successful run did.
+    pub last_summary: Option<String>,
+    /// Why the last run failed; absent if it succeeded.
+    pub last_error: Option<String>,
+    /// RFC 3339 timestamp, absent while running or after shutdown.
+    #[schema(format = DateTime)]
+    pub next_run_at: Option<String>,
+}
+
+impl From<JobStatus> for JobStatusResponse {
+    fn from(status: JobStatus) -> Self {
+        Self {
+            name: status.name.to_owned(),
+            schedule: status.schedule,
+            runs: status.runs,
+            failures: status.failures,
+            running: status.running,
+            last_started_at: status.last_started_at.map(rfc3339),
+            last_duration_ms: status.last_duration_ms,
+            last_summary: status.last_summary,
+            last_error: status.last_error,
+            next_run_at: status.next_run_at.map(rfc3339),
+        }
+    }
+}
+
+/// Route to show the status of every maintenance job.
+#[utoipa::path(
+    get,
+    path = "/jobs",
+    context_path = "/api/v1",
+    tag = "jobs",
+    responses(
+        (status = 200, description = "Status of every maintenance job", body = [JobStatusResponse]),
+        (status = 401, description = "API key missing or invalid", body = ErrorBody),
+        (status = 403, description = "API key lacks `admin`", body = ErrorBody),
+    ),
+    security(("api_key" = []))
+)]
+#[rocket::get("/jobs")]
+pub async fn list_jobs(
+    maintenance: &State<Maintenance>,
+    _api_key: Scoped<Admin>,
+) -> Json<Vec<JobStatusResponse>> {
+    Json(maintenance.statuses().into_iter().map(Into::into).collect())
+}
+
+/// The URI [`routes`](rocket::Route) which can be mounted by [`rocket`].
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![list_jobs]
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::JobStatusResponse;
+    use crate::domain::Scopes;
+    use crate::web::api::test::{api_key, runtime, scoped_api_key};
+    use crate::web::test::client;
+    use rocket::http::Status;
+
+    #[test]
+    fn admin_sees_job_status() {
+        let rt = runtime();
+        let client = client();
+        let response = client
+            .get("/api/v1/jobs")
+            .header(api_key(&client, &rt))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let admin = scoped_api_key(&client, &rt, Scopes::all());
+        let response = client.get("/api/v1/jobs").header(admin).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let jobs: Vec<JobStatusResponse> = response.into_json().unwrap();
+        let purge = jobs.iter().find(|job| job.name == "expiry_purge").unwrap();
+        assert_eq!(purge.schedule, "every 60s");
+        assert!(jobs.iter().any(|job| job.name == "analytics_rollup"));
+    }
+}
@@ -0,0 +1,466 @@
+//! Management of [`ApiKey`]s, mounted under `/api/v1`.
+//!
+//! Listing, creating and revoking keys requires the [`Admin`] scope. Any key may
+//! revoke itself and see how much of its [`Quota`] it uses.
+
+use super::responses::AdminErrors;
+use super::scope::{Admin, Scoped};
+use super::v1::rfc3339;
+use super::{ApiError, ApiKey};
+use crate::data::query::RevocationStatus;
+use crate::data::AppDatabase;
+use crate::domain::{ApiKeyInfo, Quota, Scope, Scopes, Usage};
+use crate::service::{action, ask};
+use crate::Time;
+use chrono::{DateTime, Utc};
+use rocket::response::status::NoContent;
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// The metadata of an [`ApiKey`] as returned by the API. The key itself is never listed.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ApiKeyResponse {
+    /// Public identifier used to revoke the key.
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+    /// RFC 3339 timestamp.
+    #[schema(format = DateTime)]
+    pub created_at: String,
+    /// RFC 3339 timestamp, absent if the key was never used.
+    #[schema(format = DateTime)]
+    pub last_used_at: Option<String>,
+    /// RFC 3339 timestamp, absent if the key never expires.
+    #[schema(format = DateTime)]
+    pub expires_at: Option<String>,
+    /// Limits of this key; absent limits use the server defaults.
+    pub quota: Quota,
+}
+
+impl From<ApiKeyInfo> for ApiKeyResponse {
+    fn from(key: ApiKeyInfo) -> Self {
+        Self {
+            id: key.id,
+            name: key.name,
+            scopes: key.scopes.iter().collect(),
+            created_at: rfc3339(key.created_at),
+            last_used_at: key.last_used_at.map(rfc3339),
+            expires_at: key.expires_at.map(rfc3339),
+            quota: key.quota,
+        }
+    }
+}
+
+/// Request body to create an [`ApiKey`].
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct NewApiKeyRequest {
+    /// A label to tell keys apart, e.g. the client that uses it.
+    #[serde(default)]
+    pub name: String,
+    /// Defaults to all clip scopes.
+    #[serde(default)]
+    pub scopes: Option<Vec<Scope>>,
+    /// RFC 3339 timestamp with any offset.
+    #[serde(default)]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Limits of this key; absent limits use the server defaults.
+    #[serde(default)]
+    pub quota: Quota,
+}
+
+impl From<NewApiKeyRequest> for ask::NewApiKey {
+    fn from(req: NewApiKeyRequest) -> Self {
+        Self {
+            name: req.name,
+            scopes: req
+                .scopes
+                .map(|scopes| scopes.into_iter().collect())
+                .unwrap_or_else(Scopes::clips),
+            expires_at: req.expires_at.map(Time::from),
+            quota: req.quota,
+        }
+    }
+}
+
+/// A newly created [`ApiKey`]. This is the only time the key is shown.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct NewApiKeyResponse {
+    /// The key to send in the `x-api-key` header.
+    pub api_key: String,
+    pub key: ApiKeyResponse,
+}
+
+/// Route to list all [`ApiKey`]s.
+#[utoipa::path(
+    get,
+    path = "/keys",
+    context_path = "/api/v1",
+    tag = "keys",
+    responses(
+        (status = 200, description = "All API keys", body = [ApiKeyResponse]),
+        AdminErrors,
+    ),
+    security(("api_key" = []))
+)]
+#[rocket::get("/keys")]
+pub async fn list_keys(
+    database: &State<AppDatabase>,
+    _api_key: Scoped<Admin>,
+) -> Result<Json<Vec<ApiKeyResponse>>, ApiError> {
+    let keys = action::list_api_keys(database.get_pool()).await?;
+    Ok(Json(keys.into_iter().map(Into::into).collect()))
+}
+
+/// Route to create an [`ApiKey`].
+#[utoipa::path(
+    post,
+    path = "/keys",
+    context_path = "/api/v1",
+    tag = "keys",
+    request_body = NewApiKeyRequest,
+    responses(
+        (status = 200, description = "The created key", body = NewApiKeyResponse),
+        (status = 422, description = "Malformed request body", body = ErrorBody),
+        AdminErrors,
+    ),
+    security(("api_key" = []))
+)]
+#[rocket::post("/keys", data = "<req>")]
+pub async fn new_key(
+    req: Json<NewApiKeyRequest>,
+    database: &State<AppDatabase>,
+    _api_key: Scoped<Admin>,
+) -> Result<Json<NewApiKeyResponse>, ApiError> {
+    let pool = database.get_pool();
+    let api_key = action::generate_api_key(req.into_inner().into(), pool).await?;
+    let key = action::get_api_key(api_key.clone(), pool)
+        .await?
+        .ok_or_else(|| ApiError::Server("created key not found".to_owned()))?;
+    Ok(Json(NewApiKeyResponse {
+        api_key: api_key.to_base64(),
+        key: key.into(),
+    }))
+}
+
+/// Route to revoke an [`ApiKey`] by its public id.
+#[utoipa::path(
+    delete,
+    path = "/keys/{id}",
+    context_path = "/api/v1",
+    tag = "keys",
+    params(("id" = String, Path, description = "Public identifier of the key")),
+    responses(
+        (status = 204, description = "The key was revoked"),
+        (status = 404, description = "Key not found", body = ErrorBody),
+        AdminErrors,
+    ),
+    security(("api_key" = []))
+)]
+#[rocket::delete("/keys/<id>")]
+pub async fn revoke_key(
+    id: &str,
+    database: &State<AppDatabase>,
+    _api_key: Scoped<Admin>,
+) -> Result<NoContent, ApiError> {
+    match action::revoke_api_key_by_id(id, database.get_pool()).await? {
+        RevocationStatus::Revoked => Ok(NoContent),
+        RevocationStatus::NotFound => Err(ApiError::NotFound("API key not found".to_owned())),
+    }
+}
+
+/// Route to revoke the [`ApiKey`] the request is made with.
+#[utoipa::path(
+    delete,
+    path = "/key",
+    context_path = "/api/v1",
+    tag = "keys",
+    responses(
+        (status = 204, description = "The key was revoked"),
+        (status = 401, description = "API key missing or invalid", body = ErrorBody),
+    ),
+    security(("api_key" = []))
+)]
+#[rocket::delete("/key")]
+pub async fn revoke_own_key(
+    database: &State<AppDatabase>,
+    api_key: ApiKey,
+) -> Result<NoContent, ApiError> {
+    match action::revoke_api_key(api_key, database.get_pool()).await? {
+        RevocationStatus::Revoked => Ok(NoContent),
+        RevocationStatus::NotFound => Err(ApiError::NotFound("API key not found".to_owned())),
+    }
+}
+
+/// How much of one [`Quota`] limit is used.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct QuotaUsage {
+    pub used: u64,
+    /// Absent if there is no limit.
+    pub limit: Option<u64>,
+}
+
+/// What the clips of an [`ApiKey`] use of its [`Quota`].
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UsageResponse {
+    /// Clips that have not expired yet.
+    pub clips: QuotaUsage,
+    /// Total size of the content of those clips, in bytes.
+    pub bytes: QuotaUsage,
+    /// Clips created within the last 24 hours.
+    pub clips_per_day: QuotaUsage,
+}
+
+impl UsageResponse {
+    fn new(quota: Quota, usage: Usage) -> Self {
+        let of = |used, limit| QuotaUsage { used, limit };
+        Self {
+            clips: of(usage.clips, quota.max_clips),
+            bytes: of(usage.bytes, quota.max_bytes),
+            clips_per_day: of(usage.clips_today, quota.max_clips_per_day),
+        }
+    }
+}
+
+/// Route to show how much of its [`Quota`] the [`ApiKey`] of the request uses.
+#[utoipa::path(
+    get,
+    path = "/usage",
+    context_path = "/api/v1",
+    tag = "keys",
+    responses(
+        (status = 200, description = "Usage of the key's quota", body = UsageResponse),
+        (status = 401, description = "API key missing or invalid", body = ErrorBody),
+    ),
+    security(("api_key" = []))
+)]
+#[rocket::get("/usage")]
+pub async fn usage(
+    database: &State<AppDatabase>,
+    quota: &State<Quota>,
+    api_key: ApiKey,
+) -> Result<Json<UsageResponse>, ApiError> {
+    let (quota, usage) = action::api_key_usage(&api_key, quota, database.get_pool()).await?;
+    Ok(Json(UsageResponse::new(quota, usage)))
+}
+
+/// Route to show quota usage under `/api/usage`, answering the same as [`usage`].
+#[utoipa::path(
+    get,
+    path = "/usage",
+    context_path = "/api",
+    tag = "keys",
+    responses(
+        (status = 200, description = "Usage of the key's quota", body = UsageResponse),
+        (status = 401, description = "API key missing or invalid", body = ErrorBody),
+    ),
+    security(("api_key" = []))
+)]
+#[rocket::get("/usage")]
+pub async fn unversioned_usage(
+    database: &State<AppDatabase>,
+    quota: &State<Quota>,
+    api_key: ApiKey,
+) -> Result<Json<UsageResponse>, ApiError> {
+    usage(database, quota, api_key).await
+}
+
+/// The URI [`routes`](rocket::Route) which can be mounted by [`rocket`].
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![list_keys, new_key, revoke_key, revoke_own_key, usage]
+}
+
+/// The routes mounted directly under `/api`, outside the versioned namespace.
+pub fn unversioned_routes() -> Vec<rocket::Route> {
+    rocket::routes![unversioned_usage]
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{ApiKeyResponse, NewApiKeyResponse, UsageResponse};
+    use crate::domain::Scopes;
+    use crate::web::api::test::{api_key, runtime, scoped_api_key};
+    use crate::web::api::v1::ClipResponse;
+    use crate::web::api::ErrorBody;
+    use crate::web::api::API_KEY_HEADER;
+    use crate::web::test::client;
+    use rocket::http::{ContentType, Header, Status};
+
+    #[test]
+    fn admin_manages_keys() {
+        let rt = runtime();
+        let client = client();
+        let admin = scoped_api_key(&client, &rt, Scopes::all());
+
+        let response = client
+            .post("/api/v1/keys")
+            .header(admin.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"name": "ci", "scopes": ["clip:read"]}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let created: NewApiKeyResponse = response.into_json().unwrap();
+        assert_eq!(created.key.name, "ci");
+        assert!(created.key.last_used_at.is_none());
+        let new_key = Header::new(API_KEY_HEADER, created.api_key);
+
+        let response = client.get("/api/v1/keys").header(admin.clone()).dispatch();
+        let keys: Vec<ApiKeyResponse> = response.into_json().unwrap();
+        assert!(keys.iter().any(|key| key.id == created.key.id));
+
+        let response = client
+            .get("/api/v1/keys")
+            .header(new_key.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let uri = format!("/api/v1/keys/{}", created.key.id);
+        let response = client.delete(uri.as_str()).header(admin.clone()).dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+        let response = client.delete(uri.as_str()).header(admin).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let response = client.get("/api/v1/clip/any").header(new_key).dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn expired_keys_are_unauthorized() {
+        let rt = runtime();
+        let client = client();
+        let admin = scoped_api_key(&client, &rt, Scopes::all());
+        let response = client
+            .post("/api/v1/keys")
+            .header(admin)
+            .header(ContentType::JSON)
+            .body(r#"{"expires_at": "2000-01-01T00:00:00Z"}"#)
+            .dispatch();
+        let created: NewApiKeyResponse = response.into_json().unwrap();
+
+        let response = client
+            .get("/api/v1/clip/any")
+            .header(Header::new(API_KEY_HEADER, created.api_key))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let body: crate::web::api::ErrorBody = response.into_json().unwrap();
+        assert_eq!(body.message, "API key expired");
+    }
+
+    #[test]
+    fn keys_can_revoke_themselves() {
+        let rt = runtime();
+        let client = client();
+        let key = api_key(&client, &rt);
+        let response = client.delete("/api/v1/key").header(key.clone()).dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+        let response = client.delete("/api/v1/key").header(key).dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+
+    #[test]
+    fn enforces_key_quotas() {
+        let rt = runtime();
+        let client = client();
+        let admin = scoped_api_key(&client, &rt, Scopes::all());
+        let response = client
+            .post("/api/v1/keys")
+            .header(admin)
+            .header(ContentType::JSON)
+            .body(r#"{"quota": {"max_clips": 1, "max_bytes": 10}}"#)
+            .dispatch();
+        let created: NewApiKeyResponse = response.into_json().unwrap();
+        assert_eq!(created.key.quota.max_clips, Some(1));
+        assert_eq!(created.key.quota.max_clips_per_day, None);
+        let key = Header::new(API_KEY_HEADER, created.api_key);
+        let create = |content: &str| {
+            client
+                .post("/api/v1/clip")
+                .header(key.clone())
+                .header(ContentType::JSON)
+                .body(format!(r#"{{"content": "{}"}}"#, content))
+                .dispatch()
+        };
+
+        let response = create("12345");
+        assert_eq!(response.status(), Status::Ok);
+        let clip: ClipResponse = response.into_json().unwrap();
+        let response = create("6");
+        assert_eq!(response.status(), Status::TooManyRequests);
+        let body: ErrorBody = response.into_json().unwrap();
+        assert_eq!(body.code, "quota_exceeded");
+
+        let response = client
+            .put(format!("/api/v1/clip/{}", clip.shortcode))
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"content": "more than ten bytes"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::PayloadTooLarge);
+
+        let response = client.get("/api/v1/usage").header(key.clone()).dispatch();
+        let usage: UsageResponse = response.into_json().unwrap();
+        assert_eq!((usage.clips.used, usage.clips.limit), (1, Some(1)));
+        assert_eq!((usage.bytes.used, usage.bytes.limit), (5, Some(10)));
+        assert_eq!(
+            (usage.clips_per_day.used, usage.clips_per_day.limit),
+            (1, None)
+        );
+
+        let response = client.get("/api/usage").header(key).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Deprecation"), None);
+        let unversioned: UsageResponse = response.into_json().unwrap();
+        assert_eq!(unversioned.bytes.used, usage.bytes.used);
+    }
+
+    #[test]
+    fn quota_holds_for_concurrent_clips() {
+        use crate::domain::clip::field;
+        use crate::domain::Quota;
+        use crate::service::{action, ask};
+        use crate::web::test_helpers::new_file_db;
+        use crate::ServiceError;
+
+        // A file lets the pool hand out several connections, which race for the quota
+        let rt = runtime();
+        let dir = tempfile::tempdir().unwrap();
+        let db = new_file_db(rt.handle(), dir.path());
+        let pool = db.get_pool().clone();
+        let created = rt.block_on(async move {
+            let api_key = action::generate_api_key(ask::NewApiKey::default(), &pool)
+                .await
+                .unwrap();
+            let quota = Quota {
+                max_clips: Some(2),
+                ..Default::default()
+            };
+            let tasks: Vec<_> = (0..8)
+                .map(|i| {
+                    let (pool, owner) = (pool.clone(), api_key.clone());
+                    tokio::spawn(async move {
+                        let req = ask::NewClip {
+                            title: field::Title::default(),
+                            content: field::Content::new(&format!("clip {}", i)).unwrap(),
+                            password: field::Password::default(),
+                            expires_at: field::ExpiresAt::default(),
+                            owner: owner.into(),
+                        };
+                        action::new_clip(req, &quota, &pool).await
+                    })
+                })
+                .collect();
+            let mut created = 0;
+            for task in tasks {
+                // Clips beyond the quota fail on it, not on a busy database
+                match task.await.unwrap() {
+                    Ok(_) => created += 1,
+                    Err(e) => assert!(matches!(e, ServiceError::Quota(_)), "{}", e),
+                }
+            }
+            created
+        });
+        assert_eq!(created, 2);
+    }
+}
@@ -0,0 +1,260 @@
+//! Deprecated aliases of the [`v1`](super::v1) and [`keys`](super::keys) routes, mounted under `/api`.
+//!
+//! Responses carry a `Deprecation` header and a `Link` to the successor route.
+#![allow(deprecated)]
+
+use super::keys::{self, NewApiKeyRequest, NewApiKeyResponse};
+use super::responses::{AdminErrors, ClipCreateErrors, ClipReadErrors, ClipUpdateErrors};
+use super::scope::{Admin, ClipRead, ClipWrite, Scoped};
+use super::v1::{self, ClipResponse, PatchClipRequest};
+use super::{credentials, ApiError, ClipPassword, Deprecated};
+use crate::data::AppDatabase;
+use crate::domain::clip::field::Password;
+use crate::domain::{Quota, Scopes};
+use crate::service::action;
+use crate::service::ask::{NewClip, UpdateClip};
+use crate::web::etag::{IfMatch, IfNoneMatch, Tagged};
+use crate::web::password_attempts::PasswordAttempt;
+use crate::web::rate_limit::{Create, RateLimited, Read};
+use crate::web::HitCounter;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::json::Json;
+use rocket::State;
+use std::str::FromStr;
+
+/// Cookie that older clients use to send the clip password.
+///
+/// Only honored by the deprecated routes, and only when no [`ClipPassword`] header is sent.
+pub const LEGACY_PASSWORD_COOKIE: &str = "password";
+
+/// The [`ClipPassword`] header, or else the [`LEGACY_PASSWORD_COOKIE`].
+pub struct LegacyClipPassword(ClipPassword);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LegacyClipPassword {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let password = match req.guard::<ClipPassword>().await {
+            Outcome::Success(password) => password,
+            _ => ClipPassword::default(),
+        };
+        let password = match req.cookies().get(LEGACY_PASSWORD_COOKIE) {
+            Some(cookie) if !password.is_present() => Password::new(cookie.value().to_owned())
+                .map(ClipPassword::from)
+                .unwrap_or_default(),
+            _ => password,
+        };
+        Outcome::Success(Self(password))
+    }
+}
+
+/// Deprecated alias of [`v1::get_clip`].
+#[utoipa::path(
+    get,
+    path = "/clip/{shortcode}",
+    context_path = "/api",
+    tag = "deprecated",
+    params(
+        ("shortcode" = String, Path, description = "Shortcode of the clip"),
+        ("x-clip-password" = Option<String>, Header, description = "Password of a protected clip"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a cached copy"),
+    ),
+    responses(
+        (status = 200, description = "The clip", body = ClipResponse,
+            headers(("ETag" = String, description = "Current version of the clip"))),
+        (status = 304, description = "The cached copy is still current"),
+        ClipReadErrors,
+    ),
+    security(("api_key" = []))
+)]
+#[deprecated = "use `/api/v1/clip/<shortcode>`"]
+#[rocket::get("/clip/<shortcode>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn get_clip(
+    rate_limit: RateLimited<Read>,
+    shortcode: &str,
+    database: &State<AppDatabase>,
+    password: LegacyClipPassword,
+    attempt: PasswordAttempt<'_>,
+    if_none_match: IfNoneMatch,
+    hit_counter: &State<HitCounter>,
+    api_key: Scoped<ClipRead>,
+) -> Deprecated<Result<Tagged<Json<ClipResponse>>, ApiError>> {
+    let response = v1::get_clip(
+        rate_limit,
+        shortcode,
+        database,
+        password.0,
+        attempt,
+        if_none_match,
+        hit_counter,
+        api_key,
+    )
+    .await;
+    Deprecated::new(response, format!("/api/v1/clip/{}", shortcode))
+}
+
+/// Deprecated alias of [`v1::new_clip`].
+#[utoipa::path(
+    post,
+    path = "/clip",
+    context_path = "/api",
+    tag = "deprecated",
+    request_body = NewClip,
+    responses(
+        (status = 200, description = "The created clip", body = ClipResponse),
+        ClipCreateErrors,
+    ),
+    security(("api_key" = []))
+)]
+#[deprecated = "use `/api/v1/clip`"]
+#[rocket::post("/clip", data = "<req>")]
+pub async fn new_clip(
+    _rate_limit: RateLimited<Create>,
+    req: Json<NewClip>,
+    database: &State<AppDatabase>,
+    quota: &State<Quota>,
+    api_key: Scoped<ClipWrite>,
+) -> Deprecated<Result<Json<ClipResponse>, ApiError>> {
+    let mut req = req.into_inner();
+    req.owner = api_key.into_inner().into();
+    let response = action::new_clip(req, quota, database.get_pool())
+        .await
+        .map(|clip| Json(clip.into()))
+        .map_err(ApiError::from);
+    Deprecated::new(response, "/api/v1/clip")
+}
+
+/// Deprecated alias of [`v1::update_clip`].
+#[utoipa::path(
+    put,
+    path = "/clip",
+    context_path = "/api",
+    tag = "deprecated",
+    params(
+        ("If-Match" = Option<String>, Header, description = "Only update if the clip still has this ETag"),
+        ("x-clip-password" = Option<String>, Header, description = "Current password of the clip, unless the API key owns it"),
+    ),
+    request_body = UpdateClip,
+    responses(
+        (status = 200, description = "The updated clip", body = ClipResponse,
+            headers(("ETag" = String, description = "New version of the clip"))),
+        ClipUpdateErrors,
+    ),
+    security(("api_key" = []))
+)]
+#[deprecated = "use `/api/v1/clip/<shortcode>`"]
+#[rocket::put("/clip", data = "<req>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn update_clip(
+    req: Json<UpdateClip>,
+    if_match: IfMatch,
+    password: ClipPassword,
+    attempt: PasswordAttempt<'_>,
+    database: &State<AppDatabase>,
+    quota: &State<Quota>,
+    api_key: Scoped<ClipWrite>,
+) -> Deprecated<Result<Tagged<Json<ClipResponse>>, ApiError>> {
+    let mut req = req.into_inner();
+    req.version = if_match.version();
+    let shortcode = req.shortcode.clone();
+    let successor = format!("/api/v1/clip/{}", shortcode.as_str());
+    let tried = password.is_present();
+    let credentials = credentials(api_key.into_inner(), password);
+    let update = action::update_clip(req, &credentials, quota, database.get_pool());
+    let response = match attempt.guard(&shortcode, tried, update).await {
+        Ok(result) => result
+            .map(|clip| v1::tagged(clip, &IfNoneMatch::default()))
+            .map_err(ApiError::from),
+        Err(lockout) => Err(lockout.into()),
+    };
+    Deprecated::new(response, successor)
+}
+
+/// Deprecated alias of [`v1::patch_clip`].
+#[utoipa::path(
+    patch,
+    path = "/clip/{shortcode}",
+    context_path = "/api",
+    tag = "deprecated",
+    params(
+        ("shortcode" = String, Path, description = "Shortcode of the clip"),
+        ("If-Match" = Option<String>, Header, description = "Only update if the clip still has this ETag"),
+        ("x-clip-password" = Option<String>, Header, description = "Current password of the clip, unless the API key owns it"),
+    ),
+    request_body(content = PatchClipRequest, content_type = "application/merge-patch+json"),
+    responses(
+        (status = 200, description = "The updated clip", body = ClipResponse,
+            headers(("ETag" = String, description = "New version of the clip"))),
+        ClipUpdateErrors,
+    ),
+    security(("api_key" = []))
+)]
+#[deprecated = "use `/api/v1/clip/<shortcode>`"]
+#[rocket::patch("/clip/<shortcode>", data = "<req>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn patch_clip(
+    shortcode: &str,
+    req: Json<PatchClipRequest>,
+    if_match: IfMatch,
+    password: ClipPassword,
+    attempt: PasswordAttempt<'_>,
+    database: &State<AppDatabase>,
+    quota: &State<Quota>,
+    api_key: Scoped<ClipWrite>,
+) -> Deprecated<Result<Tagged<Json<ClipResponse>>, ApiError>> {
+    let response = v1::patch_clip(
+        shortcode, req, if_match, password, attempt, database, quota, api_key,
+    )
+    .await;
+    Deprecated::new(response, format!("/api/v1/clip/{}", shortcode))
+}
+
+/// Deprecated alias of [`keys::new_key`].
+#[utoipa::path(
+    get,
+    path = "/key/new",
+    context_path = "/api",
+    tag = "deprecated",
+    params(
+        ("scopes" = Option<String>, Query, description = "Space or comma separated scopes to grant, e.g. `clip:read clip:write`. Defaults to all clip scopes."),
+    ),
+    responses(
+        (status = 200, description = "The created key", body = NewApiKeyResponse),
+        (status = 400, description = "Unknown scope", body = ErrorBody),
+        AdminErrors,
+    ),
+    security(("api_key" = []))
+)]
+#[deprecated = "use `/api/v1/keys`"]
+#[rocket::get("/key/new?<scopes>")]
+pub async fn new_api_key(
+    scopes: Option<&str>,
+    database: &State<AppDatabase>,
+    api_key: Scoped<Admin>,
+) -> Deprecated<Result<Json<NewApiKeyResponse>, ApiError>> {
+    let scopes = match scopes.map(Scopes::from_str).transpose() {
+        Ok(scopes) => scopes,
+        Err(_) => {
+            let e = ApiError::Validation {
+                message: "unknown scope".to_owned(),
+                field: Some("scopes".to_owned()),
+            };
+            return Deprecated::new(Err(e), "/api/v1/keys");
+        }
+    };
+    let req = NewApiKeyRequest {
+        name: String::new(),
+        scopes: scopes.map(|scopes| scopes.iter().collect()),
+        expires_at: None,
+        quota: Quota::default(),
+    };
+    let response = keys::new_key(Json(req), database, api_key).await;
+    Deprecated::new(response, "/api/v1/keys")
+}
+
+/// The URI [`routes`](rocket::Route) which can be mounted by [`rocket`].
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![get_clip, new_clip, update_clip, patch_clip, new_api_key]
+}
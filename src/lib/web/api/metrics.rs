@@ -0,0 +1,73 @@
+//! Counters of security relevant events, mounted under `/api/v1`.
+//!
+//! Requires the [`Admin`] scope, since the counters show how a server is being probed.
+
+use super::scope::{Admin, Scoped};
+use crate::web::password_attempts::{PasswordAttempts, PasswordMetrics};
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Counters since startup, as returned by the API.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MetricsResponse {
+    pub passwords: PasswordMetrics,
+}
+
+/// Route to report counters of security relevant events since startup.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    context_path = "/api/v1",
+    tag = "metrics",
+    responses(
+        (status = 200, description = "Counters since startup", body = MetricsResponse),
+        (status = 401, description = "API key missing or invalid", body = ErrorBody),
+        (status = 403, description = "API key lacks `admin`", body = ErrorBody),
+    ),
+    security(("api_key" = []))
+)]
+#[rocket::get("/metrics")]
+pub fn metrics(
+    password_attempts: &State<PasswordAttempts>,
+    _api_key: Scoped<Admin>,
+) -> Json<MetricsResponse> {
+    Json(MetricsResponse {
+        passwords: password_attempts.metrics(),
+    })
+}
+
+/// The URI [`routes`](rocket::Route) which can be mounted by [`rocket`].
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![metrics]
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::MetricsResponse;
+    use crate::domain::Scopes;
+    use crate::web::api::test::{api_key, runtime, scoped_api_key};
+    use crate::web::test::client;
+    use rocket::http::Status;
+
+    #[test]
+    fn admin_sees_metrics() {
+        let rt = runtime();
+        let client = client();
+        let response = client.get("/api/v1/metrics").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response = client
+            .get("/api/v1/metrics")
+            .header(api_key(&client, &rt))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let admin = scoped_api_key(&client, &rt, Scopes::all());
+        let response = client.get("/api/v1/metrics").header(admin).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let metrics: MetricsResponse = response.into_json().unwrap();
+        assert_eq!(metrics.passwords.failures, 0);
+        assert_eq!(metrics.passwords.lockouts, 0);
+    }
+}
@@ -0,0 +1,816 @@
+//! API routing, errors, and data structures.
+use crate::data::AppDatabase;
+use crate::domain::clip::field::{Owner, Password};
+use crate::domain::QuotaError;
+use crate::service::action;
+use crate::service::ask::Credentials;
+use crate::web::password_attempts::Lockout;
+use crate::web::{KeyUsage, RequestId};
+use crate::{ClipError, ServiceError};
+use rocket::http::Status;
+use rocket::request::{FromParam, FromRequest, Outcome, Request};
+use rocket::response::{self, status, Responder};
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+pub mod jobs;
+pub mod keys;
+pub mod legacy;
+pub mod responses;
+pub mod metrics;
+pub mod scope;
+pub mod trash;
+pub mod v1;
+
+/// HTTP request header name to include an API key.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// HTTP request header name to include the password of a protected clip.
+pub const CLIP_PASSWORD_HEADER: &str = "x-clip-password";
+
+/// The possible errors that can occur when accessing an `ApiKey`.
+#[derive(Debug, Clone, thiserror::Error, Serialize)]
+pub enum ApiKeyError {
+    /// API key not found.
+    #[error("API key not found")]
+    NotFound(String),
+    /// Invalid API key format.
+    #[error("invalid API key format")]
+    DecodeError(String),
+    /// API key past its expiry time.
+    #[error("API key expired")]
+    Expired,
+}
+
+/// An API key that is used to access the API endpoints.
+#[derive(Debug, Clone)]
+pub struct ApiKey(Vec<u8>);
+
+impl ApiKey {
+    /// Convert raw bytes of the [`ApiKey`]into a [`String`]
+    pub fn to_base64(&self) -> String {
+        base64::encode(self.0.as_slice())
+    }
+    /// The raw bytes of the [`ApiKey`].
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+    /// Extract the underlying [`Vector`](std::vec::Vec).
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+    /// The SHA-256 of the [`ApiKey`], which is stored in place of the key itself.
+    pub fn hash(&self) -> Vec<u8> {
+        Sha256::digest(self.0.as_slice()).to_vec()
+    }
+}
+
+/// The [`Owner`] of clips created with this [`ApiKey`].
+impl From<ApiKey> for Owner {
+    fn from(api_key: ApiKey) -> Self {
+        Owner::new(Some(api_key.hash()), None)
+    }
+}
+
+/// The default implementation produces a new 128-bit [`ApiKey`].
+impl Default for ApiKey {
+    fn default() -> Self {
+        let key = (0..16).map(|_| rand::random::<u8>()).collect();
+        Self(key)
+    }
+}
+
+impl FromStr for ApiKey {
+    type Err = ApiKeyError;
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        base64::decode(key)
+            .map(ApiKey)
+            .map_err(|e| Self::Err::DecodeError(e.to_string()))
+    }
+}
+
+impl<'r> FromParam<'r> for ApiKey {
+    type Error = ApiKeyError;
+
+    fn from_param(param: &'r str) -> Result<Self, Self::Error> {
+        ApiKey::from_str(param)
+    }
+}
+
+/// The JSON body of every API error response.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorBody {
+    /// Machine-readable error code, such as `not_found`.
+    pub code: String,
+    /// Human-readable description of the problem.
+    pub message: String,
+    /// The request field that caused the error, if any.
+    pub field: Option<String>,
+    /// The [`RequestId`] of the failed request.
+    pub request_id: String,
+}
+
+/// The possible errors that can occur when attempting to respond to a request.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum ApiError {
+    /// Invalid submission by client.
+    #[error("{message}")]
+    Validation {
+        message: String,
+        field: Option<String>,
+    },
+
+    /// Missing or invalid [`ApiKey`].
+    #[error("{0}")]
+    Unauthorized(String),
+
+    /// The request is authenticated but not allowed, e.g. a wrong clip password.
+    #[error("{0}")]
+    Forbidden(String),
+
+    /// Data not found.
+    #[error("{0}")]
+    NotFound(String),
+
+    /// The data existed but is no longer available, e.g. an expired clip.
+    #[error("{0}")]
+    Gone(String),
+
+    /// The request conflicts with existing data, e.g. a taken shortcode.
+    #[error("{0}")]
+    Conflict(String),
+
+    /// A conditional request did not match, e.g. a stale `If-Match` version.
+    #[error("{0}")]
+    PreconditionFailed(String),
+
+    /// The request body is well-formed but could not be understood.
+    #[error("{message}")]
+    Unprocessable {
+        message: String,
+        field: Option<String>,
+    },
+
+    /// The request body or one of its fields is too large.
+    #[error("{message}")]
+    PayloadTooLarge {
+        message: String,
+        field: Option<String>,
+    },
+
+    /// The client sent too many requests.
+    #[error("{message}")]
+    TooManyRequests {
+        message: String,
+        /// Seconds until the client may retry, sent as `Retry-After`.
+        retry_after: Option<u64>,
+    },
+
+    /// The change would exceed the [`Quota`](crate::domain::Quota) of the API key.
+    #[error("{0}")]
+    QuotaExceeded(QuotaError),
+
+    /// Server error.
+    #[error("{0}")]
+    Server(String),
+}
+
+impl ApiError {
+    /// The HTTP status this error is reported with.
+    pub fn status(&self) -> Status {
+        match self {
+            Self::Validation { .. } => Status::BadRequest,
+            Self::Unauthorized(_) => Status::Unauthorized,
+            Self::Forbidden(_) => Status::Forbidden,
+            Self::NotFound(_) => Status::NotFound,
+            Self::Gone(_) => Status::Gone,
+            Self::Conflict(_) => Status::Conflict,
+            Self::PreconditionFailed(_) => Status::PreconditionFailed,
+            Self::Unprocessable { .. } => Status::UnprocessableEntity,
+            Self::PayloadTooLarge { .. } => Status::PayloadTooLarge,
+            Self::TooManyRequests { .. } => Status::TooManyRequests,
+            Self::QuotaExceeded(QuotaError::Bytes(_)) => Status::PayloadTooLarge,
+            Self::QuotaExceeded(_) => Status::TooManyRequests,
+            Self::Server(_) => Status::InternalServerError,
+        }
+    }
+
+    /// The machine-readable `code` of the [`ErrorBody`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Validation { .. } => "validation_error",
+            Self::Unauthorized(_) => "unauthorized",
+            Self::Forbidden(_) => "forbidden",
+            Self::NotFound(_) => "not_found",
+            Self::Gone(_) => "gone",
+            Self::Conflict(_) => "conflict",
+            Self::PreconditionFailed(_) => "precondition_failed",
+            Self::Unprocessable { .. } => "unprocessable_entity",
+            Self::PayloadTooLarge { .. } => "payload_too_large",
+            Self::TooManyRequests { .. } => "too_many_requests",
+            Self::QuotaExceeded(_) => "quota_exceeded",
+            Self::Server(_) => "internal_error",
+        }
+    }
+
+    /// The request field that caused the error, if any.
+    pub fn field(&self) -> Option<&str> {
+        match self {
+            Self::Validation { field, .. }
+            | Self::Unprocessable { field, .. }
+            | Self::PayloadTooLarge { field, .. } => field.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Builds the [`ErrorBody`] reported for the request `req`.
+    pub fn to_body(&self, req: &Request<'_>) -> ErrorBody {
+        ErrorBody {
+            code: self.code().to_owned(),
+            message: self.to_string(),
+            field: self.field().map(ToOwned::to_owned),
+            request_id: RequestId::of(req).to_owned(),
+        }
+    }
+
+    /// Remembers this error so the catcher for its status can report it.
+    ///
+    /// Rocket does not hand request guard errors to catchers, so guards store them
+    /// in the request-local cache instead.
+    pub(crate) fn cache(self, req: &Request<'_>) -> (Status, Self) {
+        let status = self.status();
+        req.local_cache(|| GuardError(Some(self.clone())));
+        (status, self)
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let mut response = status::Custom(self.status(), Json(self.to_body(req))).respond_to(req)?;
+        if let Self::TooManyRequests {
+            retry_after: Some(retry_after),
+            ..
+        } = self
+        {
+            response.set_raw_header("Retry-After", retry_after.to_string());
+        }
+        Ok(response)
+    }
+}
+
+impl From<ApiKeyError> for ApiError {
+    fn from(err: ApiKeyError) -> Self {
+        Self::Unauthorized(err.to_string())
+    }
+}
+
+impl From<Lockout> for ApiError {
+    fn from(err: Lockout) -> Self {
+        Self::TooManyRequests {
+            message: err.to_string(),
+            retry_after: Some(err.retry_after()),
+        }
+    }
+}
+
+impl From<ClipError> for ApiError {
+    fn from(err: ClipError) -> Self {
+        let field = err.field().map(ToOwned::to_owned);
+        let message = err.to_string();
+        if err.is_too_long() {
+            Self::PayloadTooLarge { message, field }
+        } else {
+            Self::Validation { message, field }
+        }
+    }
+}
+
+impl From<ServiceError> for ApiError {
+    fn from(err: ServiceError) -> Self {
+        match err {
+            ServiceError::Clip(c) => c.into(),
+            ServiceError::NotFound => Self::NotFound("entity not found".to_owned()),
+            ServiceError::Expired => Self::Gone("clip expired".to_owned()),
+            ServiceError::Conflict(_) => Self::Conflict("shortcode already in use".to_owned()),
+            ServiceError::Data(_) => Self::Server("a server error occurred".to_owned()),
+            ServiceError::PermissionError(msg) => Self::Forbidden(msg),
+            ServiceError::PreconditionFailed(msg) => Self::PreconditionFailed(msg),
+            ServiceError::Quota(e) => Self::QuotaExceeded(e),
+        }
+    }
+}
+
+/// An [`ApiError`] raised by a request guard, stored for the catchers.
+struct GuardError(Option<ApiError>);
+
+/// Allows an [`ApiKey`] to be used as a [request guard](https://rocket.rs/v0.5-rc/guide/requests/#request-guards) in a route.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ApiKey {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        // Several guards may ask for the key, so it is only looked up once per request.
+        let authenticated = req
+            .local_cache_async(async { Authenticated(authenticate(req).await) })
+            .await;
+        match &authenticated.0 {
+            Ok(api_key) => Outcome::Success(api_key.clone()),
+            Err(e) => Outcome::Failure(e.clone().cache(req)),
+        }
+    }
+}
+
+/// The outcome of looking up the [`ApiKey`] of a request.
+struct Authenticated(Result<ApiKey, ApiError>);
+
+/// Looks up the [`ApiKey`] sent with `req`, caching its [`Scopes`](crate::domain::Scopes).
+async fn authenticate(req: &Request<'_>) -> Result<ApiKey, ApiError> {
+    let key = req
+        .headers()
+        .get_one(API_KEY_HEADER)
+        .ok_or_else(|| ApiError::Unauthorized("API key missing".to_owned()))?;
+    let db = match req.guard::<&State<AppDatabase>>().await {
+        Outcome::Success(db) => db,
+        _ => return Err(ApiError::Server("server error".to_owned())),
+    };
+    let api_key = ApiKey::from_str(key)?;
+    match action::get_api_key(api_key.clone(), db.get_pool()).await {
+        Ok(Some(info)) if info.is_expired() => Err(ApiKeyError::Expired.into()),
+        Ok(Some(info)) => {
+            if let Outcome::Success(key_usage) = req.guard::<&State<KeyUsage>>().await {
+                key_usage.used(&api_key);
+            }
+            req.local_cache(|| info.scopes);
+            Ok(api_key)
+        }
+        Ok(None) => Err(ApiKeyError::NotFound("API key not found".to_owned()).into()),
+        Err(_) => Err(ApiError::Server("server error".to_owned())),
+    }
+}
+
+/// Wraps the response of a deprecated route.
+///
+/// Adds a `Deprecation` header and a `Link` to the route that replaces it.
+pub struct Deprecated<R> {
+    inner: R,
+    successor: String,
+}
+
+impl<R> Deprecated<R> {
+    pub fn new<S: Into<String>>(inner: R, successor: S) -> Self {
+        Self {
+            inner,
+            successor: successor.into(),
+        }
+    }
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Deprecated<R> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'o> {
+        let mut response = self.inner.respond_to(req)?;
+        response.set_raw_header("Deprecation", "true");
+        response.set_raw_header(
+            "Link",
+            format!("<{}>; rel=\"successor-version\"", self.successor),
+        );
+        Ok(response)
+    }
+}
+
+/// The password of a protected clip, sent in the [`CLIP_PASSWORD_HEADER`].
+#[derive(Debug, Clone, Default)]
+pub struct ClipPassword(Option<Password>);
+
+impl ClipPassword {
+    /// Whether the request carried a password.
+    pub fn is_present(&self) -> bool {
+        self.0.is_some()
+    }
+    /// Extract the underlying [`Password`], which is empty if none was sent.
+    pub fn into_inner(self) -> Password {
+        self.0.unwrap_or_default()
+    }
+}
+
+/// The [`Credentials`] of a request made with `api_key` and `password`.
+pub(crate) fn credentials(api_key: ApiKey, password: ClipPassword) -> Credentials {
+    Credentials {
+        api_key: Some(api_key),
+        edit_token: None,
+        password: password.into_inner(),
+    }
+}
+
+impl From<Password> for ClipPassword {
+    fn from(password: Password) -> Self {
+        Self(Some(password))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClipPassword {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let password = req
+            .headers()
+            .get_one(CLIP_PASSWORD_HEADER)
+            .and_then(|raw_password| Password::new(raw_password.to_owned()).ok());
+        Outcome::Success(Self(password))
+    }
+}
+
+/// The URI [`routes`](rocket::Route) which can be mounted by [`rocket`].
+pub fn routes() -> Vec<rocket::Route> {
+    let mut routes = legacy::routes();
+    routes.extend(keys::unversioned_routes());
+    routes
+}
+
+pub mod catcher {
+    //! Contains all the API catchers.
+    use super::{ApiError, GuardError};
+    use crate::web::request_id;
+    use rocket::http::Status;
+    use rocket::Request;
+    use rocket::{catch, catchers, Catcher};
+
+    /// Reports the error stored by a request guard, or `fallback` if there is none.
+    fn caught(req: &Request, fallback: ApiError) -> ApiError {
+        match &req.local_cache(|| GuardError(None)).0 {
+            Some(e) if e.status() == fallback.status() => e.clone(),
+            _ => fallback,
+        }
+    }
+
+    /// Catch unhandled errors.
+    #[catch(default)]
+    fn default(status: Status, req: &Request) -> ApiError {
+        eprintln!("General error: {}", request_id::describe(status, req));
+        let message = status.reason().unwrap_or("something went wrong").to_owned();
+        let fallback = match status.code {
+            400 => ApiError::Validation {
+                message,
+                field: None,
+            },
+            401 => ApiError::Unauthorized(message),
+            403 => ApiError::Forbidden(message),
+            404 => ApiError::NotFound(message),
+            410 => ApiError::Gone(message),
+            409 => ApiError::Conflict(message),
+            413 => ApiError::PayloadTooLarge {
+                message,
+                field: None,
+            },
+            422 => ApiError::Unprocessable {
+                message,
+                field: None,
+            },
+            429 => ApiError::TooManyRequests {
+                message,
+                retry_after: None,
+            },
+            _ => ApiError::Server(message),
+        };
+        caught(req, fallback)
+    }
+
+    /// Catch server errors.
+    #[catch(500)]
+    fn internal_error(req: &Request) -> ApiError {
+        eprintln!(
+            "Internal error: {}",
+            request_id::describe(Status::InternalServerError, req)
+        );
+        caught(req, ApiError::Server("internal server error".to_owned()))
+    }
+
+    /// Catch missing data errors.
+    #[catch(404)]
+    fn not_found(req: &Request) -> ApiError {
+        caught(req, ApiError::NotFound("resource not found".to_owned()))
+    }
+
+    /// Catch malformed requests.
+    #[catch(400)]
+    fn bad_request(req: &Request) -> ApiError {
+        let fallback = ApiError::Validation {
+            message: "malformed request".to_owned(),
+            field: None,
+        };
+        caught(req, fallback)
+    }
+
+    /// Catch API key errors.
+    #[catch(401)]
+    fn unauthorized(req: &Request) -> ApiError {
+        caught(
+            req,
+            ApiError::Unauthorized("API key missing or invalid".to_owned()),
+        )
+    }
+
+    /// Catch request bodies that could not be deserialized.
+    #[catch(422)]
+    fn unprocessable(req: &Request) -> ApiError {
+        let fallback = ApiError::Unprocessable {
+            message: "request body could not be parsed".to_owned(),
+            field: None,
+        };
+        caught(req, fallback)
+    }
+
+    /// The [`catchers`](rocket::Catcher) which can be registered by [`rocket`].
+    pub fn catchers() -> Vec<Catcher> {
+        catchers![
+            not_found,
+            default,
+            internal_error,
+            bad_request,
+            unauthorized,
+            unprocessable
+        ]
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{ErrorBody, API_KEY_HEADER, CLIP_PASSWORD_HEADER};
+    use crate::data::AppDatabase;
+    use crate::domain::{Scope, Scopes};
+    use crate::web::request_id::REQUEST_ID_HEADER;
+    use crate::web::test::client;
+    use rocket::http::{ContentType, Cookie, Header, Status};
+    use rocket::local::blocking::Client;
+    use tokio::runtime::Runtime;
+
+    pub fn runtime() -> Runtime {
+        Runtime::new().expect("failed to spawn tokio runtime")
+    }
+
+    pub fn api_key(client: &Client, rt: &Runtime) -> Header<'static> {
+        scoped_api_key(client, rt, Scopes::clips())
+    }
+
+    pub fn scoped_api_key(client: &Client, rt: &Runtime, scopes: Scopes) -> Header<'static> {
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+        let api_key = rt
+            .block_on(async move {
+                let req = crate::service::ask::NewApiKey::with_scopes(scopes);
+                crate::service::action::generate_api_key(req, db.get_pool()).await
+            })
+            .unwrap();
+        Header::new(API_KEY_HEADER, api_key.to_base64())
+    }
+
+    #[test]
+    fn missing_api_key_is_unauthorized() {
+        let client = client();
+        let response = client
+            .get("/api/clip/notexist")
+            .header(Header::new(REQUEST_ID_HEADER, "test-request"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let body: ErrorBody = response.into_json().unwrap();
+        assert_eq!(body.code, "unauthorized");
+        assert_eq!(body.request_id, "test-request");
+    }
+
+    #[test]
+    fn unknown_api_key_is_unauthorized() {
+        let client = client();
+        let response = client
+            .get("/api/clip/notexist")
+            .header(Header::new(API_KEY_HEADER, "8mRnWXn97EqWRT6bLr9NZg=="))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+        let body: ErrorBody = response.into_json().unwrap();
+        assert_eq!(body.message, "API key not found");
+    }
+
+    #[test]
+    fn missing_clip_is_not_found() {
+        let rt = runtime();
+        let client = client();
+        let response = client
+            .get("/api/clip/notexist")
+            .header(api_key(&client, &rt))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let body: ErrorBody = response.into_json().unwrap();
+        assert_eq!(body.code, "not_found");
+        assert!(!body.request_id.is_empty());
+    }
+
+    #[test]
+    fn wrong_password_is_forbidden() {
+        let rt = runtime();
+        let client = client();
+        let key = api_key(&client, &rt);
+        let response = client
+            .post("/api/clip")
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"title": null, "content": "secret", "password": "123", "expires_at": null}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let clip: serde_json::Value = response.into_json().unwrap();
+        let shortcode = clip["shortcode"].as_str().unwrap();
+
+        let response = client
+            .get(format!("/api/clip/{}", shortcode))
+            .header(key)
+            .cookie(Cookie::new("password", "abc"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let body: ErrorBody = response.into_json().unwrap();
+        assert_eq!(body.code, "forbidden");
+    }
+
+    #[test]
+    fn malformed_body_is_unprocessable() {
+        let rt = runtime();
+        let client = client();
+        let response = client
+            .post("/api/clip")
+            .header(api_key(&client, &rt))
+            .header(ContentType::JSON)
+            .body(r#"{"content": 1}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let body: ErrorBody = response.into_json().unwrap();
+        assert_eq!(body.code, "unprocessable_entity");
+    }
+
+    /// Creates a clip through the API and returns its shortcode.
+    fn new_clip(client: &Client, key: &Header<'static>, body: &str) -> String {
+        let response = client
+            .post("/api/v1/clip")
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .body(body)
+            .dispatch();
+        let clip: serde_json::Value = response.into_json().unwrap();
+        clip["shortcode"].as_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn only_owner_or_password_may_update() {
+        let rt = runtime();
+        let client = client();
+        let owner = api_key(&client, &rt);
+        let other = api_key(&client, &rt);
+        let shortcode = new_clip(&client, &owner, r#"{"content": "mine"}"#);
+        let uri = format!("/api/v1/clip/{}", shortcode);
+
+        let response = client
+            .put(uri.as_str())
+            .header(other.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"content": "theirs"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let body: ErrorBody = response.into_json().unwrap();
+        assert_eq!(body.code, "forbidden");
+        let response = client
+            .patch(uri.as_str())
+            .header(other)
+            .header(ContentType::JSON)
+            .body(r#"{"content": "theirs"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client
+            .patch(uri.as_str())
+            .header(owner)
+            .header(ContentType::JSON)
+            .body(r#"{"content": "still mine"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn clip_password_authorizes_other_keys() {
+        let rt = runtime();
+        let client = client();
+        let owner = api_key(&client, &rt);
+        let other = api_key(&client, &rt);
+        let shortcode = new_clip(
+            &client,
+            &owner,
+            r#"{"content": "shared", "password": "123"}"#,
+        );
+        let uri = format!("/api/v1/clip/{}", shortcode);
+
+        let response = client
+            .patch(uri.as_str())
+            .header(other.clone())
+            .header(Header::new(CLIP_PASSWORD_HEADER, "abc"))
+            .header(ContentType::JSON)
+            .body(r#"{"title": "changed"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = client
+            .patch(uri.as_str())
+            .header(other)
+            .header(Header::new(CLIP_PASSWORD_HEADER, "123"))
+            .header(ContentType::JSON)
+            .body(r#"{"title": "changed"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn reuses_identical_clips_when_asked() {
+        let rt = runtime();
+        let client = client();
+        let owner = api_key(&client, &rt);
+        let other = api_key(&client, &rt);
+        let body = r#"{"content": "build log", "title": "ci", "reuse_existing": true}"#;
+        let shortcode = new_clip(&client, &owner, body);
+
+        assert_eq!(new_clip(&client, &owner, body), shortcode);
+        assert_ne!(new_clip(&client, &other, body), shortcode);
+        let titled = r#"{"content": "build log", "title": "other", "reuse_existing": true}"#;
+        assert_ne!(new_clip(&client, &owner, titled), shortcode);
+        assert_ne!(
+            new_clip(
+                &client,
+                &owner,
+                r#"{"content": "build log", "title": "ci"}"#
+            ),
+            shortcode
+        );
+    }
+
+    #[test]
+    fn only_owner_or_password_may_delete() {
+        let rt = runtime();
+        let client = client();
+        let owner = api_key(&client, &rt);
+        let other = api_key(&client, &rt);
+        let shortcode = new_clip(&client, &owner, r#"{"content": "mine"}"#);
+        let uri = format!("/api/v1/clip/{}", shortcode);
+
+        let response = client.delete(uri.as_str()).header(other).dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = client.delete(uri.as_str()).header(owner.clone()).dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+        let response = client.get(uri.as_str()).header(owner).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn routes_require_scopes() {
+        let rt = runtime();
+        let client = client();
+        let writer = api_key(&client, &rt);
+        let reader = scoped_api_key(&client, &rt, [Scope::ClipRead].into_iter().collect());
+        let shortcode = new_clip(&client, &writer, r#"{"content": "scoped"}"#);
+        let uri = format!("/api/v1/clip/{}", shortcode);
+
+        let response = client.get(uri.as_str()).header(reader.clone()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client
+            .post("/api/v1/clip")
+            .header(reader.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"content": "denied"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let body: ErrorBody = response.into_json().unwrap();
+        assert_eq!(body.message, "API key lacks the `clip:write` scope");
+
+        let response = client.delete(uri.as_str()).header(reader).dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn legacy_key_route_requires_admin() {
+        let rt = runtime();
+        let client = client();
+        let response = client.get("/api/key/new").dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let admin = scoped_api_key(&client, &rt, Scopes::all());
+        let response = client
+            .get("/api/key/new?scopes=clip:read")
+            .header(admin.clone())
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Deprecation"), Some("true"));
+        let created: super::keys::NewApiKeyResponse = response.into_json().unwrap();
+        assert_eq!(created.key.scopes, vec![Scope::ClipRead]);
+
+        let response = client
+            .get("/api/key/new?scopes=clip:everything")
+            .header(admin)
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+}
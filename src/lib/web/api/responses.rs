@@ -0,0 +1,126 @@
+//! Error responses shared by the OpenAPI descriptions of the [`v1`](super::v1) routes and
+//! their [`legacy`](super::legacy) aliases, so that both document the same failures.
+
+use super::ErrorBody;
+use utoipa::IntoResponses;
+
+/// Errors of reading a clip.
+#[derive(IntoResponses)]
+pub enum ClipReadErrors {
+    /// API key missing or invalid
+    #[response(status = 401)]
+    Unauthorized(ErrorBody),
+    /// Wrong clip password, or API key lacks `clip:read`
+    #[response(status = 403)]
+    Forbidden(ErrorBody),
+    /// Clip not found
+    #[response(status = 404)]
+    NotFound(ErrorBody),
+    /// Clip expired
+    #[response(status = 410)]
+    Gone(ErrorBody),
+    /// Rate limit exceeded, or too many wrong clip passwords
+    #[response(
+        status = 429,
+        headers(("Retry-After" = u64, description = "Seconds until the request may be retried"))
+    )]
+    TooManyRequests(ErrorBody),
+}
+
+/// Errors of creating a clip.
+#[derive(IntoResponses)]
+pub enum ClipCreateErrors {
+    /// Invalid clip
+    #[response(status = 400)]
+    BadRequest(ErrorBody),
+    /// API key missing or invalid
+    #[response(status = 401)]
+    Unauthorized(ErrorBody),
+    /// API key lacks `clip:write`
+    #[response(status = 403)]
+    Forbidden(ErrorBody),
+    /// Shortcode already in use
+    #[response(status = 409)]
+    Conflict(ErrorBody),
+    /// The clip is too large, or the API key's storage quota would be exceeded
+    #[response(status = 413)]
+    PayloadTooLarge(ErrorBody),
+    /// Malformed request body
+    #[response(status = 422)]
+    Unprocessable(ErrorBody),
+    /// Rate limit exceeded, or the API key's clip quota reached
+    #[response(
+        status = 429,
+        headers(("Retry-After" = u64, description = "Seconds until the request may be retried, unless the quota was reached"))
+    )]
+    TooManyRequests(ErrorBody),
+}
+
+/// Errors of replacing or patching a clip.
+#[derive(IntoResponses)]
+pub enum ClipUpdateErrors {
+    /// Invalid clip
+    #[response(status = 400)]
+    BadRequest(ErrorBody),
+    /// API key missing or invalid
+    #[response(status = 401)]
+    Unauthorized(ErrorBody),
+    /// API key lacks `clip:write`, or is neither the owner nor sent the clip password
+    #[response(status = 403)]
+    Forbidden(ErrorBody),
+    /// Clip not found
+    #[response(status = 404)]
+    NotFound(ErrorBody),
+    /// Clip expired
+    #[response(status = 410)]
+    Gone(ErrorBody),
+    /// The clip changed since `If-Match` was read
+    #[response(status = 412)]
+    PreconditionFailed(ErrorBody),
+    /// The clip is too large, or the owner's storage quota would be exceeded
+    #[response(status = 413)]
+    PayloadTooLarge(ErrorBody),
+    /// Malformed request body
+    #[response(status = 422)]
+    Unprocessable(ErrorBody),
+    /// Too many wrong clip passwords
+    #[response(
+        status = 429,
+        headers(("Retry-After" = u64, description = "Seconds until the request may be retried"))
+    )]
+    TooManyRequests(ErrorBody),
+}
+
+/// Errors of deleting a clip.
+#[derive(IntoResponses)]
+pub enum ClipDeleteErrors {
+    /// API key missing or invalid
+    #[response(status = 401)]
+    Unauthorized(ErrorBody),
+    /// API key lacks `clip:delete`, or is neither the owner nor sent the clip password
+    #[response(status = 403)]
+    Forbidden(ErrorBody),
+    /// Clip not found
+    #[response(status = 404)]
+    NotFound(ErrorBody),
+    /// Clip expired
+    #[response(status = 410)]
+    Gone(ErrorBody),
+    /// Too many wrong clip passwords
+    #[response(
+        status = 429,
+        headers(("Retry-After" = u64, description = "Seconds until the request may be retried"))
+    )]
+    TooManyRequests(ErrorBody),
+}
+
+/// Errors of the routes that need an admin key.
+#[derive(IntoResponses)]
+pub enum AdminErrors {
+    /// API key missing or invalid
+    #[response(status = 401)]
+    Unauthorized(ErrorBody),
+    /// API key lacks `admin`
+    #[response(status = 403)]
+    Forbidden(ErrorBody),
+}
@@ -0,0 +1,73 @@
+//! Scope checks for API routes.
+//!
+//! Routes name the [`Scope`] they need with a [`Scoped`] request guard, which answers
+//! `403 Forbidden` when the key was not granted it.
+
+use super::{ApiError, ApiKey};
+use crate::domain::{Scope, Scopes};
+use rocket::request::{FromRequest, Outcome, Request};
+use std::marker::PhantomData;
+
+/// A [`Scope`] that can be required by a [`Scoped`] guard.
+pub trait RequiredScope {
+    const SCOPE: Scope;
+}
+
+/// Marker for [`Scope::ClipRead`].
+pub struct ClipRead;
+/// Marker for [`Scope::ClipWrite`].
+pub struct ClipWrite;
+/// Marker for [`Scope::ClipDelete`].
+pub struct ClipDelete;
+/// Marker for [`Scope::Admin`].
+pub struct Admin;
+
+impl RequiredScope for ClipRead {
+    const SCOPE: Scope = Scope::ClipRead;
+}
+impl RequiredScope for ClipWrite {
+    const SCOPE: Scope = Scope::ClipWrite;
+}
+impl RequiredScope for ClipDelete {
+    const SCOPE: Scope = Scope::ClipDelete;
+}
+impl RequiredScope for Admin {
+    const SCOPE: Scope = Scope::Admin;
+}
+
+/// A valid [`ApiKey`] that was granted the scope `S`.
+pub struct Scoped<S> {
+    api_key: ApiKey,
+    scope: PhantomData<S>,
+}
+
+impl<S> Scoped<S> {
+    /// Extract the underlying [`ApiKey`].
+    pub fn into_inner(self) -> ApiKey {
+        self.api_key
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, S: RequiredScope> FromRequest<'r> for Scoped<S> {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let api_key = match req.guard::<ApiKey>().await {
+            Outcome::Success(api_key) => api_key,
+            Outcome::Failure(e) => return Outcome::Failure(e),
+            Outcome::Forward(f) => return Outcome::Forward(f),
+        };
+        // The ApiKey guard caches the scopes it looked up alongside the key.
+        let scopes = req.local_cache(Scopes::default);
+        if scopes.allows(S::SCOPE) {
+            Outcome::Success(Self {
+                api_key,
+                scope: PhantomData,
+            })
+        } else {
+            let e = ApiError::Forbidden(format!("API key lacks the `{}` scope", S::SCOPE));
+            Outcome::Failure(e.cache(req))
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! The trash of deleted and expired clips, mounted under `/api/v1`.
+//!
+//! Clips stay in the trash until maintenance purges them after the grace period.
+//! Listing and restoring them requires the [`Admin`] scope.
+
+use super::scope::{Admin, Scoped};
+use super::v1::{rfc3339, ClipResponse};
+use super::ApiError;
+use crate::data::AppDatabase;
+use crate::domain::clip::TrashedClip;
+use crate::service::action;
+use crate::ShortCode;
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// A clip in the trash as returned by the API.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TrashedClipResponse {
+    pub clip: ClipResponse,
+    /// RFC 3339 timestamp of when the clip was deleted or found expired.
+    #[schema(format = DateTime)]
+    pub deleted_at: String,
+}
+
+impl From<TrashedClip> for TrashedClipResponse {
+    fn from(trashed: TrashedClip) -> Self {
+        Self {
+            clip: trashed.clip.into(),
+            deleted_at: rfc3339(trashed.deleted_at),
+        }
+    }
+}
+
+/// Route to list the clips in the trash, most recently deleted first.
+#[utoipa::path(
+    get,
+    path = "/trash",
+    context_path = "/api/v1",
+    tag = "trash",
+    responses(
+        (status = 200, description = "All clips in the trash", body = [TrashedClipResponse]),
+        (status = 401, description = "API key missing or invalid", body = ErrorBody),
+        (status = 403, description = "API key lacks `admin`", body = ErrorBody),
+    ),
+    security(("api_key" = []))
+)]
+#[rocket::get("/trash")]
+pub async fn list_trash(
+    database: &State<AppDatabase>,
+    _api_key: Scoped<Admin>,
+) -> Result<Json<Vec<TrashedClipResponse>>, ApiError> {
+    let trash = action::list_trash(database.get_pool()).await?;
+    Ok(Json(trash.into_iter().map(Into::into).collect()))
+}
+
+/// Route to take a clip out of the trash. An expiry that has passed is cleared.
+#[utoipa::path(
+    post,
+    path = "/trash/{shortcode}/restore",
+    context_path = "/api/v1",
+    tag = "trash",
+    params(("shortcode" = String, Path, description = "Shortcode of the trashed clip")),
+    responses(
+        (status = 200, description = "The restored clip", body = ClipResponse),
+        (status = 401, description = "API key missing or invalid", body = ErrorBody),
+        (status = 403, description = "API key lacks `admin`", body = ErrorBody),
+        (status = 404, description = "No such clip in the trash", body = ErrorBody),
+    ),
+    security(("api_key" = []))
+)]
+#[rocket::post("/trash/<shortcode>/restore")]
+pub async fn restore_clip(
+    shortcode: &str,
+    database: &State<AppDatabase>,
+    _api_key: Scoped<Admin>,
+) -> Result<Json<ClipResponse>, ApiError> {
+    let clip = action::restore_clip(&ShortCode::from(shortcode), database.get_pool()).await?;
+    Ok(Json(clip.into()))
+}
+
+/// The URI [`routes`](rocket::Route) which can be mounted by [`rocket`].
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![list_trash, restore_clip]
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::TrashedClipResponse;
+    use crate::domain::Scopes;
+    use crate::web::api::test::{api_key, runtime, scoped_api_key};
+    use crate::web::api::v1::ClipResponse;
+    use crate::web::test::client;
+    use rocket::http::{ContentType, Status};
+
+    #[test]
+    fn admin_restores_deleted_and_expired_clips() {
+        let rt = runtime();
+        let client = client();
+        let key = api_key(&client, &rt);
+        let admin = scoped_api_key(&client, &rt, Scopes::all());
+        let new_clip = |body: &'static str| -> ClipResponse {
+            client
+                .post("/api/v1/clip")
+                .header(key.clone())
+                .header(ContentType::JSON)
+                .body(body)
+                .dispatch()
+                .into_json()
+                .unwrap()
+        };
+        let deleted = new_clip(r#"{"content": "deleted"}"#);
+        let expired = new_clip(r#"{"content": "expired", "expires_at": "2000-01-01T00:00:00Z"}"#);
+
+        let uri = format!("/api/v1/clip/{}", deleted.shortcode);
+        let response = client.delete(uri.as_str()).header(key.clone()).dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+        let response = client.get(uri.as_str()).header(key.clone()).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        let pool = client
+            .rocket()
+            .state::<crate::data::AppDatabase>()
+            .unwrap()
+            .get_pool();
+        rt.block_on(crate::service::action::delete_expired(pool))
+            .unwrap();
+
+        let response = client.get("/api/v1/trash").header(key.clone()).dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = client.get("/api/v1/trash").header(admin.clone()).dispatch();
+        let trash: Vec<TrashedClipResponse> = response.into_json().unwrap();
+        for shortcode in [&deleted.shortcode, &expired.shortcode] {
+            assert!(trash.iter().any(|t| &t.clip.shortcode == shortcode));
+        }
+
+        for clip in [&deleted, &expired] {
+            let uri = format!("/api/v1/trash/{}/restore", clip.shortcode);
+            let response = client.post(uri.as_str()).header(admin.clone()).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+            let restored: ClipResponse = response.into_json().unwrap();
+            assert!(restored.expires_at.is_none());
+            let response = client.post(uri.as_str()).header(admin.clone()).dispatch();
+            assert_eq!(response.status(), Status::NotFound);
+
+            let uri = format!("/api/v1/clip/{}", clip.shortcode);
+            let response = client.get(uri.as_str()).header(key.clone()).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
+
+        // Purging with no grace period empties the trash for good.
+        let response = client.delete(uri.as_str()).header(key.clone()).dispatch();
+        assert_eq!(response.status(), Status::NoContent);
+        let purged = rt
+            .block_on(crate::service::action::purge_trash(
+                chrono::Duration::seconds(-1),
+                pool,
+            ))
+            .unwrap();
+        assert_eq!(purged, 1);
+        let uri = format!("/api/v1/trash/{}/restore", deleted.shortcode);
+        let response = client.post(uri.as_str()).header(admin).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+}
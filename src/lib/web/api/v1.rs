@@ -0,0 +1,788 @@
+//! Version 1 of the JSON API, mounted under `/api/v1`.
+//!
+//! Requests and responses use dedicated DTOs so that refactoring the domain types
+//! does not change the wire format.
+
+use super::responses::{ClipCreateErrors, ClipDeleteErrors, ClipReadErrors, ClipUpdateErrors};
+use super::scope::{ClipDelete, ClipRead, ClipWrite, Scoped};
+use super::{credentials, ApiError, ClipPassword};
+use crate::data::AppDatabase;
+use crate::domain::clip::field;
+use crate::domain::Quota;
+use crate::service::{action, ask};
+use crate::web::etag::{IfMatch, IfNoneMatch, Tagged};
+use crate::web::password_attempts::PasswordAttempt;
+use crate::web::rate_limit::{Create, RateLimited, Read};
+use crate::web::HitCounter;
+use crate::{Clip, ClipError, ShortCode, Time};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rocket::response::status::NoContent;
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::convert::TryFrom;
+use utoipa::ToSchema;
+
+/// Formats a [`Time`] as an RFC 3339 timestamp.
+pub(crate) fn rfc3339(time: Time) -> String {
+    time.into_inner().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// A [`Clip`] as returned by the API.
+///
+/// The password is never included; `has_password` reports whether one is set.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ClipResponse {
+    pub shortcode: String,
+    pub title: Option<String>,
+    pub content: String,
+    pub has_password: bool,
+    /// RFC 3339 timestamp.
+    #[schema(format = DateTime)]
+    pub created_at: String,
+    /// RFC 3339 timestamp, absent if the clip never expires.
+    #[schema(format = DateTime)]
+    pub expires_at: Option<String>,
+    pub hits: i64,
+    /// Incremented on every change; also sent as the `ETag`.
+    pub version: i64,
+}
+
+impl From<Clip> for ClipResponse {
+    fn from(clip: Clip) -> Self {
+        Self {
+            has_password: clip.password.has_password(),
+            shortcode: clip.shortcode.into_inner(),
+            title: clip.title.into_inner(),
+            content: clip.content.into_inner(),
+            created_at: rfc3339(clip.created_at.into_inner()),
+            expires_at: clip.expires_at.into_inner().map(rfc3339),
+            hits: clip.hits.into_inner(),
+            version: clip.version.into_inner(),
+        }
+    }
+}
+
+/// Request body to create a clip.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct NewClipRequest {
+    pub content: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// RFC 3339 timestamp with any offset.
+    #[serde(default)]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Return a live clip this API key already created with the same content, title,
+    /// password and expiry instead of creating another.
+    #[serde(default)]
+    pub reuse_existing: bool,
+}
+
+impl TryFrom<NewClipRequest> for ask::NewClip {
+    type Error = ClipError;
+
+    fn try_from(req: NewClipRequest) -> Result<Self, Self::Error> {
+        Ok(Self {
+            title: field::Title::new(req.title)?,
+            content: field::Content::new(req.content.as_str())?,
+            password: field::Password::new(req.password)?,
+            expires_at: field::ExpiresAt::new(req.expires_at.map(Time::from)),
+            owner: field::Owner::default(),
+        })
+    }
+}
+
+/// Request body to replace the contents of a clip.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UpdateClipRequest {
+    pub content: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// RFC 3339 timestamp with any offset.
+    #[serde(default)]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl UpdateClipRequest {
+    fn into_ask(self, shortcode: &str) -> Result<ask::UpdateClip, ClipError> {
+        Ok(ask::UpdateClip {
+            title: field::Title::new(self.title)?,
+            content: field::Content::new(self.content.as_str())?,
+            password: field::Password::new(self.password)?,
+            expires_at: field::ExpiresAt::new(self.expires_at.map(Time::from)),
+            shortcode: shortcode.into(),
+            version: None,
+        })
+    }
+}
+
+/// Responds with `clip` tagged by its version.
+pub(crate) fn tagged(clip: Clip, if_none_match: &IfNoneMatch) -> Tagged<Json<ClipResponse>> {
+    Tagged::new(clip.version.into(), Json(clip.into()), if_none_match)
+}
+
+/// Deserializes a field that may be absent, `null` or set, as in JSON Merge Patch.
+///
+/// Used with `#[serde(default)]`: an absent field stays `None`, `null` becomes `Some(None)`.
+fn nullable<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Request body to partially update a clip, following JSON Merge Patch (RFC 7396).
+///
+/// Absent fields are left unchanged and `null` clears a field.
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct PatchClipRequest {
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(value_type = Option<String>, nullable)]
+    pub content: Option<Option<String>>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(value_type = Option<String>, nullable)]
+    pub title: Option<Option<String>>,
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(value_type = Option<String>, nullable)]
+    pub password: Option<Option<String>>,
+    /// RFC 3339 timestamp with any offset.
+    #[serde(
+        default,
+        deserialize_with = "nullable",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(value_type = Option<String>, format = DateTime, nullable)]
+    pub expires_at: Option<Option<DateTime<Utc>>>,
+}
+
+impl PatchClipRequest {
+    fn into_ask(self, shortcode: &str) -> Result<ask::PatchClip, ClipError> {
+        Ok(ask::PatchClip {
+            shortcode: shortcode.into(),
+            title: self.title.map(field::Title::new).transpose()?,
+            content: self
+                .content
+                .map(|content| field::Content::new(content.unwrap_or_default().as_str()))
+                .transpose()?,
+            password: self.password.map(field::Password::new).transpose()?,
+            expires_at: self
+                .expires_at
+                .map(|expires_at| field::ExpiresAt::new(expires_at.map(Time::from))),
+            version: None,
+        })
+    }
+}
+
+/// Route to retrieve an existing [`Clip`], based on its [`ShortCode`](crate::ShortCode).
+#[utoipa::path(
+    get,
+    path = "/clip/{shortcode}",
+    context_path = "/api/v1",
+    tag = "clips",
+    params(
+        ("shortcode" = String, Path, description = "Shortcode of the clip"),
+        ("x-clip-password" = Option<String>, Header, description = "Password of a protected clip"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a cached copy"),
+    ),
+    responses(
+        (status = 200, description = "The clip", body = ClipResponse,
+            headers(("ETag" = String, description = "Current version of the clip"))),
+        (status = 304, description = "The cached copy is still current"),
+        ClipReadErrors,
+    ),
+    security(("api_key" = []))
+)]
+#[rocket::get("/clip/<shortcode>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn get_clip(
+    _rate_limit: RateLimited<Read>,
+    shortcode: &str,
+    database: &State<AppDatabase>,
+    password: ClipPassword,
+    attempt: PasswordAttempt<'_>,
+    if_none_match: IfNoneMatch,
+    hit_counter: &State<HitCounter>,
+    _api_key: Scoped<ClipRead>,
+) -> Result<Tagged<Json<ClipResponse>>, ApiError> {
+    let tried = password.is_present();
+    let req = ask::GetClip {
+        shortcode: shortcode.into(),
+        password: password.into_inner(),
+    };
+    let clip = attempt
+        .guard(
+            &req.shortcode.clone(),
+            tried,
+            action::get_clip(req, database.get_pool()),
+        )
+        .await??;
+    hit_counter.hit(shortcode.into(), 1);
+    Ok(tagged(clip, &if_none_match))
+}
+
+/// Route to add a new [`Clip`].
+#[utoipa::path(
+    post,
+    path = "/clip",
+    context_path = "/api/v1",
+    tag = "clips",
+    request_body = NewClipRequest,
+    responses(
+        (status = 200, description = "The created clip, or the reused one with `reuse_existing`", body = ClipResponse),
+        ClipCreateErrors,
+    ),
+    security(("api_key" = []))
+)]
+#[rocket::post("/clip", data = "<req>")]
+pub async fn new_clip(
+    _rate_limit: RateLimited<Create>,
+    req: Json<NewClipRequest>,
+    database: &State<AppDatabase>,
+    quota: &State<Quota>,
+    api_key: Scoped<ClipWrite>,
+) -> Result<Json<ClipResponse>, ApiError> {
+    let req = req.into_inner();
+    let reuse_existing = req.reuse_existing;
+    let mut req = ask::NewClip::try_from(req)?;
+    req.owner = api_key.into_inner().into();
+    if reuse_existing {
+        if let Some(clip) = action::find_identical_clip(&req, database.get_pool()).await? {
+            return Ok(Json(clip.into()));
+        }
+    }
+    let clip = action::new_clip(req, quota, database.get_pool()).await?;
+    Ok(Json(clip.into()))
+}
+
+/// Route to replace an existing [`Clip`].
+#[utoipa::path(
+    put,
+    path = "/clip/{shortcode}",
+    context_path = "/api/v1",
+    tag = "clips",
+    params(
+        ("shortcode" = String, Path, description = "Shortcode of the clip"),
+        ("If-Match" = Option<String>, Header, description = "Only update if the clip still has this ETag"),
+        ("x-clip-password" = Option<String>, Header, description = "Current password of the clip, unless the API key owns it"),
+    ),
+    request_body = UpdateClipRequest,
+    responses(
+        (status = 200, description = "The updated clip", body = ClipResponse,
+            headers(("ETag" = String, description = "New version of the clip"))),
+        ClipUpdateErrors,
+    ),
+    security(("api_key" = []))
+)]
+#[rocket::put("/clip/<shortcode>", data = "<req>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn update_clip(
+    shortcode: &str,
+    req: Json<UpdateClipRequest>,
+    if_match: IfMatch,
+    password: ClipPassword,
+    attempt: PasswordAttempt<'_>,
+    database: &State<AppDatabase>,
+    quota: &State<Quota>,
+    api_key: Scoped<ClipWrite>,
+) -> Result<Tagged<Json<ClipResponse>>, ApiError> {
+    let mut req = req.into_inner().into_ask(shortcode)?;
+    req.version = if_match.version();
+    let tried = password.is_present();
+    let credentials = credentials(api_key.into_inner(), password);
+    let clip = attempt
+        .guard(
+            &req.shortcode.clone(),
+            tried,
+            action::update_clip(req, &credentials, quota, database.get_pool()),
+        )
+        .await??;
+    Ok(tagged(clip, &IfNoneMatch::default()))
+}
+
+/// Route to change some fields of an existing [`Clip`].
+#[utoipa::path(
+    patch,
+    path = "/clip/{shortcode}",
+    context_path = "/api/v1",
+    tag = "clips",
+    params(
+        ("shortcode" = String, Path, description = "Shortcode of the clip"),
+        ("If-Match" = Option<String>, Header, description = "Only update if the clip still has this ETag"),
+        ("x-clip-password" = Option<String>, Header, description = "Current password of the clip, unless the API key owns it"),
+    ),
+    request_body(content = PatchClipRequest, content_type = "application/merge-patch+json"),
+    responses(
+        (status = 200, description = "The updated clip", body = ClipResponse,
+            headers(("ETag" = String, description = "New version of the clip"))),
+        ClipUpdateErrors,
+    ),
+    security(("api_key" = []))
+)]
+#[rocket::patch("/clip/<shortcode>", data = "<req>")]
+#[allow(clippy::too_many_arguments)]
+pub async fn patch_clip(
+    shortcode: &str,
+    req: Json<PatchClipRequest>,
+    if_match: IfMatch,
+    password: ClipPassword,
+    attempt: PasswordAttempt<'_>,
+    database: &State<AppDatabase>,
+    quota: &State<Quota>,
+    api_key: Scoped<ClipWrite>,
+) -> Result<Tagged<Json<ClipResponse>>, ApiError> {
+    let mut req = req.into_inner().into_ask(shortcode)?;
+    req.version = if_match.version();
+    let tried = password.is_present();
+    let credentials = credentials(api_key.into_inner(), password);
+    let clip = attempt
+        .guard(
+            &req.shortcode.clone(),
+            tried,
+            action::patch_clip(req, &credentials, quota, database.get_pool()),
+        )
+        .await??;
+    Ok(tagged(clip, &IfNoneMatch::default()))
+}
+
+/// Route to delete an existing [`Clip`].
+#[utoipa::path(
+    delete,
+    path = "/clip/{shortcode}",
+    context_path = "/api/v1",
+    tag = "clips",
+    params(
+        ("shortcode" = String, Path, description = "Shortcode of the clip"),
+        ("x-clip-password" = Option<String>, Header, description = "Current password of the clip, unless the API key owns it"),
+    ),
+    responses(
+        (status = 204, description = "The clip was deleted"),
+        ClipDeleteErrors,
+    ),
+    security(("api_key" = []))
+)]
+#[rocket::delete("/clip/<shortcode>")]
+pub async fn delete_clip(
+    shortcode: &str,
+    password: ClipPassword,
+    attempt: PasswordAttempt<'_>,
+    database: &State<AppDatabase>,
+    api_key: Scoped<ClipDelete>,
+) -> Result<NoContent, ApiError> {
+    let shortcode = ShortCode::from(shortcode);
+    let req = ask::DeleteClip {
+        shortcode: shortcode.clone(),
+    };
+    let tried = password.is_present();
+    let credentials = credentials(api_key.into_inner(), password);
+    let delete = action::delete_clip(req, &credentials, database.get_pool());
+    attempt.guard(&shortcode, tried, delete).await??;
+    Ok(NoContent)
+}
+
+/// The URI [`routes`](rocket::Route) which can be mounted by [`rocket`].
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![get_clip, new_clip, update_clip, patch_clip, delete_clip]
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::ClipResponse;
+    use crate::domain::clip::field::Limits;
+    use crate::web::api::test::{api_key, runtime};
+    use crate::web::api::{ErrorBody, CLIP_PASSWORD_HEADER};
+    use crate::web::test::client;
+    use rocket::http::{ContentType, Header, Status};
+
+    #[test]
+    fn redacts_password_and_formats_timestamps() {
+        let rt = runtime();
+        let client = client();
+        let key = api_key(&client, &rt);
+        let response = client
+            .post("/api/v1/clip")
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"content": "secret", "password": "123", "expires_at": "2100-01-01T02:00:00+02:00"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.into_string().unwrap();
+        assert!(!body.contains("123"));
+        let clip: ClipResponse = serde_json::from_str(&body).unwrap();
+        assert!(clip.has_password);
+        assert_eq!(clip.expires_at.as_deref(), Some("2100-01-01T00:00:00Z"));
+        assert!(chrono::DateTime::parse_from_rfc3339(&clip.created_at).is_ok());
+    }
+
+    #[test]
+    fn updates_clip_by_shortcode() {
+        let rt = runtime();
+        let client = client();
+        let key = api_key(&client, &rt);
+        let response = client
+            .post("/api/v1/clip")
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"content": "first"}"#)
+            .dispatch();
+        let clip: ClipResponse = response.into_json().unwrap();
+        assert!(!clip.has_password);
+
+        let response = client
+            .put(format!("/api/v1/clip/{}", clip.shortcode))
+            .header(key)
+            .header(ContentType::JSON)
+            .body(r#"{"content": "second"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let clip: ClipResponse = response.into_json().unwrap();
+        assert_eq!(clip.content, "second");
+    }
+
+    #[test]
+    fn rejects_empty_content() {
+        let rt = runtime();
+        let client = client();
+        let response = client
+            .post("/api/v1/clip")
+            .header(api_key(&client, &rt))
+            .header(ContentType::JSON)
+            .body(r#"{"content": ""}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn legacy_route_is_deprecated_alias() {
+        let rt = runtime();
+        let client = client();
+        let key = api_key(&client, &rt);
+        let response = client
+            .post("/api/clip")
+            .header(key)
+            .header(ContentType::JSON)
+            .body(r#"{"title": null, "content": "old", "password": "123", "expires_at": null}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Deprecation"), Some("true"));
+        assert_eq!(
+            response.headers().get_one("Link"),
+            Some(r#"</api/v1/clip>; rel="successor-version""#)
+        );
+        let clip: ClipResponse = response.into_json().unwrap();
+        assert!(clip.has_password);
+    }
+
+    #[test]
+    fn reads_clip_password_from_header() {
+        let rt = runtime();
+        let client = client();
+        let key = api_key(&client, &rt);
+        let response = client
+            .post("/api/v1/clip")
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"content": "secret", "password": "123"}"#)
+            .dispatch();
+        let clip: ClipResponse = response.into_json().unwrap();
+        let uri = format!("/api/v1/clip/{}", clip.shortcode);
+
+        let response = client.get(uri.as_str()).header(key.clone()).dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = client
+            .get(uri.as_str())
+            .header(key.clone())
+            .header(Header::new(CLIP_PASSWORD_HEADER, "abc"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = client
+            .get(uri.as_str())
+            .header(key)
+            .header(Header::new(CLIP_PASSWORD_HEADER, "123"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn patch_changes_only_supplied_fields() {
+        let rt = runtime();
+        let client = client();
+        let key = api_key(&client, &rt);
+        let response = client
+            .post("/api/v1/clip")
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .body(
+                r#"{"content": "first", "password": "123", "expires_at": "2100-01-01T00:00:00Z"}"#,
+            )
+            .dispatch();
+        let clip: ClipResponse = response.into_json().unwrap();
+        let uri = format!("/api/v1/clip/{}", clip.shortcode);
+
+        let response = client
+            .patch(uri.as_str())
+            .header(key.clone())
+            .header(ContentType::new("application", "merge-patch+json"))
+            .body(r#"{"content": "second"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let patched: ClipResponse = response.into_json().unwrap();
+        assert_eq!(patched.content, "second");
+        assert!(patched.has_password);
+        assert_eq!(patched.expires_at, clip.expires_at);
+
+        // `null` clears optional fields
+        let response = client
+            .patch(uri.as_str())
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"password": null, "expires_at": null}"#)
+            .dispatch();
+        let patched: ClipResponse = response.into_json().unwrap();
+        assert_eq!(patched.content, "second");
+        assert!(!patched.has_password);
+        assert_eq!(patched.expires_at, None);
+
+        // content is required, so it cannot be cleared
+        let response = client
+            .patch(uri.as_str())
+            .header(key)
+            .header(ContentType::JSON)
+            .body(r#"{"content": null}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn patch_missing_clip_is_not_found() {
+        let rt = runtime();
+        let client = client();
+        let response = client
+            .patch("/api/v1/clip/notexist")
+            .header(api_key(&client, &rt))
+            .header(ContentType::JSON)
+            .body(r#"{"title": "new"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[test]
+    fn expired_clip_is_gone_before_maintenance_runs() {
+        let rt = runtime();
+        let client = client();
+        let key = api_key(&client, &rt);
+        let response = client
+            .post("/api/v1/clip")
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"content": "stale", "expires_at": "2000-01-01T00:00:00Z"}"#)
+            .dispatch();
+        let clip: ClipResponse = response.into_json().unwrap();
+
+        let uri = format!("/api/v1/clip/{}", clip.shortcode);
+        let response = client.get(uri.as_str()).header(key.clone()).dispatch();
+        assert_eq!(response.status(), Status::Gone);
+        let body: ErrorBody = response.into_json().unwrap();
+        assert_eq!(body.code, "gone");
+
+        let response = client
+            .put(uri.as_str())
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"content": "revived"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Gone);
+        let response = client.get(format!("/clip/{}", clip.shortcode)).dispatch();
+        assert_eq!(response.status(), Status::Gone);
+        let response = client
+            .get(format!("/clip/raw/{}", clip.shortcode))
+            .dispatch();
+        assert_eq!(response.status(), Status::Gone);
+    }
+
+    #[test]
+    fn conditional_requests_use_etags() {
+        let rt = runtime();
+        let client = client();
+        let key = api_key(&client, &rt);
+        let response = client
+            .post("/api/v1/clip")
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"content": "first"}"#)
+            .dispatch();
+        let clip: ClipResponse = response.into_json().unwrap();
+        let uri = format!("/api/v1/clip/{}", clip.shortcode);
+
+        let response = client.get(uri.as_str()).header(key.clone()).dispatch();
+        let etag = response.headers().get_one("ETag").unwrap().to_owned();
+        assert_eq!(etag, "W/\"1\"");
+
+        let response = client
+            .get(uri.as_str())
+            .header(key.clone())
+            .header(Header::new("If-None-Match", etag.clone()))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotModified);
+        assert!(response.into_string().is_none());
+
+        // The first writer wins, the second one gets 412
+        let response = client
+            .patch(uri.as_str())
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .header(Header::new("If-Match", etag.clone()))
+            .body(r#"{"content": "second"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("ETag"), Some("W/\"2\""));
+        let response = client
+            .put(uri.as_str())
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .header(Header::new("If-Match", etag.clone()))
+            .body(r#"{"content": "third"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::PreconditionFailed);
+
+        // A stale cached copy is refetched
+        let response = client
+            .get(uri.as_str())
+            .header(key)
+            .header(Header::new("If-None-Match", etag))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let clip: ClipResponse = response.into_json().unwrap();
+        assert_eq!(clip.content, "second");
+        assert_eq!(clip.version, 2);
+    }
+
+    #[test]
+    fn legacy_put_honors_if_match() {
+        let rt = runtime();
+        let client = client();
+        let key = api_key(&client, &rt);
+        let response = client
+            .post("/api/v1/clip")
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"content": "first"}"#)
+            .dispatch();
+        let clip: ClipResponse = response.into_json().unwrap();
+        let body = |content: &str| {
+            format!(
+                r#"{{"shortcode": "{}", "title": null, "content": "{}", "password": null, "expires_at": null}}"#,
+                clip.shortcode, content
+            )
+        };
+
+        let response = client
+            .put("/api/clip")
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .header(Header::new("If-Match", "\"1\""))
+            .body(body("second"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("ETag"), Some("W/\"2\""));
+        let response = client
+            .put("/api/clip")
+            .header(key)
+            .header(ContentType::JSON)
+            .header(Header::new("If-Match", "\"1\""))
+            .body(body("third"))
+            .dispatch();
+        assert_eq!(response.status(), Status::PreconditionFailed);
+        assert_eq!(response.headers().get_one("Deprecation"), Some("true"));
+    }
+
+    #[test]
+    fn legacy_patch_is_deprecated_alias() {
+        let rt = runtime();
+        let client = client();
+        let key = api_key(&client, &rt);
+        let response = client
+            .post("/api/v1/clip")
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"content": "first"}"#)
+            .dispatch();
+        let clip: ClipResponse = response.into_json().unwrap();
+
+        let response = client
+            .patch(format!("/api/clip/{}", clip.shortcode))
+            .header(key)
+            .header(ContentType::new("application", "merge-patch+json"))
+            .body(r#"{"title": "renamed"}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("Deprecation"), Some("true"));
+        let link = format!(
+            "</api/v1/clip/{}>; rel=\"successor-version\"",
+            clip.shortcode
+        );
+        assert_eq!(response.headers().get_one("Link"), Some(link.as_str()));
+        let patched: ClipResponse = response.into_json().unwrap();
+        assert_eq!(patched.title.as_deref(), Some("renamed"));
+        assert_eq!(patched.content, "first");
+    }
+
+    #[test]
+    fn rejects_oversized_and_invalid_clips() {
+        let rt = runtime();
+        let client = client();
+        let key = api_key(&client, &rt);
+        let create = |body: String| {
+            client
+                .post("/api/v1/clip")
+                .header(key.clone())
+                .header(ContentType::JSON)
+                .body(body)
+                .dispatch()
+        };
+        let limits = Limits::get();
+
+        let content = "a".repeat(limits.max_content_bytes + 1);
+        let response = create(format!(r#"{{"content": "{}"}}"#, content));
+        assert_eq!(response.status(), Status::PayloadTooLarge);
+        let body: ErrorBody = response.into_json().unwrap();
+        assert_eq!(body.code, "payload_too_large");
+        assert_eq!(body.field.as_deref(), Some("content"));
+
+        let title = "t".repeat(limits.max_title_chars + 1);
+        let response = create(format!(r#"{{"content": "a", "title": "{}"}}"#, title));
+        assert_eq!(response.status(), Status::PayloadTooLarge);
+        let body: ErrorBody = response.into_json().unwrap();
+        assert_eq!(body.field.as_deref(), Some("title"));
+
+        // Bodies over the data limit are refused before they are parsed.
+        let content = "a".repeat(limits.max_body_bytes() + 1);
+        let response = create(format!(r#"{{"content": "{}"}}"#, content));
+        assert_eq!(response.status(), Status::PayloadTooLarge);
+        let body: ErrorBody = response.into_json().unwrap();
+        assert_eq!(body.code, "payload_too_large");
+
+        let response = create(r#"{"content": "null\u0000byte"}"#.to_owned());
+        assert_eq!(response.status(), Status::BadRequest);
+        let body: ErrorBody = response.into_json().unwrap();
+        assert_eq!(body.field.as_deref(), Some("content"));
+    }
+}
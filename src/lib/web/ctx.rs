@@ -1,3 +1,5 @@
+use crate::web::key_generation::KeyGeneration;
+use crate::web::password_attempts::Lockout;
 use derive_more::Constructor;
 use serde::Serialize;
 
@@ -7,15 +9,9 @@ pub trait PageContext {
     fn parent(&self) -> &str;
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Default, Serialize)]
 pub struct Home {}
 
-impl Default for Home {
-    fn default() -> Self {
-        Self {}
-    }
-}
-
 impl PageContext for Home {
     fn title(&self) -> &str {
         "Stash your clipboard"
@@ -33,6 +29,8 @@ impl PageContext for Home {
 #[derive(Debug, Serialize, Constructor)]
 pub struct ClipView {
     pub clip: crate::Clip,
+    /// Link to the edit page, shown only to the creator of the clip.
+    pub edit_url: Option<String>,
 }
 
 impl PageContext for ClipView {
@@ -49,9 +47,80 @@ impl PageContext for ClipView {
     }
 }
 
+#[derive(Debug, Serialize)]
+pub struct EditClip {
+    form_action: String,
+    edit_token: String,
+}
+
+impl EditClip {
+    pub fn new(
+        shortcode: &crate::ShortCode,
+        token: &crate::domain::clip::field::EditToken,
+    ) -> Self {
+        Self {
+            form_action: crate::web::edit_cookie::edit_url(shortcode),
+            edit_token: token.as_str().to_owned(),
+        }
+    }
+}
+
+impl PageContext for EditClip {
+    fn title(&self) -> &str {
+        "Edit clip"
+    }
+
+    fn template_path(&self) -> &str {
+        "edit_clip"
+    }
+
+    fn parent(&self) -> &str {
+        "base"
+    }
+}
+
+/// The page asking for the edit token of a clip, for browsers without its edit cookie.
 #[derive(Debug, Serialize, Constructor)]
+pub struct EditRequireToken {
+    shortcode: crate::ShortCode,
+}
+
+impl PageContext for EditRequireToken {
+    fn title(&self) -> &str {
+        "Edit token required"
+    }
+
+    fn template_path(&self) -> &str {
+        "edit_need_token"
+    }
+
+    fn parent(&self) -> &str {
+        "base"
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct ClipRequirePassword {
     shortcode: crate::ShortCode,
+    /// Seconds until another password may be tried, if too many were wrong.
+    lockout: Option<u64>,
+}
+
+impl ClipRequirePassword {
+    pub fn new(shortcode: crate::ShortCode) -> Self {
+        Self {
+            shortcode,
+            lockout: None,
+        }
+    }
+
+    /// The page shown while wrong passwords have locked the clip or client out.
+    pub fn locked_out(shortcode: crate::ShortCode, lockout: &Lockout) -> Self {
+        Self {
+            shortcode,
+            lockout: Some(lockout.retry_after()),
+        }
+    }
 }
 
 impl PageContext for ClipRequirePassword {
@@ -67,12 +136,23 @@ impl PageContext for ClipRequirePassword {
         "base"
     }
 }
-#[derive(Debug, Serialize)]
-pub struct ApiKeyGenerate {}
+#[derive(Debug, Default, Serialize)]
+pub struct ApiKeyGenerate {
+    /// Whether the page may generate keys at all.
+    pub enabled: bool,
+    /// How many keys each client may generate per hour.
+    pub per_hour: u32,
+    /// The scopes granted to the generated key, space separated.
+    pub scopes: Option<String>,
+}
 
-impl Default for ApiKeyGenerate {
-    fn default() -> Self {
-        Self {}
+impl ApiKeyGenerate {
+    pub fn new(key_generation: &KeyGeneration) -> Self {
+        Self {
+            enabled: key_generation.is_enabled(),
+            per_hour: key_generation.per_hour(),
+            scopes: None,
+        }
     }
 }
 
@@ -90,15 +170,9 @@ impl PageContext for ApiKeyGenerate {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Default, Serialize)]
 pub struct ApiKey {}
 
-impl Default for ApiKey {
-    fn default() -> Self {
-        Self {}
-    }
-}
-
 impl PageContext for ApiKey {
     fn title(&self) -> &str {
         "Api Key"
@@ -112,3 +186,20 @@ impl PageContext for ApiKey {
         "base"
     }
 }
+
+#[derive(Debug, Default, Serialize)]
+pub struct ApiDocs {}
+
+impl PageContext for ApiDocs {
+    fn title(&self) -> &str {
+        "API Documentation"
+    }
+
+    fn template_path(&self) -> &str {
+        "api_docs"
+    }
+
+    fn parent(&self) -> &str {
+        "base"
+    }
+}
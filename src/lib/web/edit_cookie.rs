@@ -0,0 +1,44 @@
+//! Cookies remembering the edit tokens of clips created in this browser.
+//!
+//! The [`EditToken`] is issued when a clip is created on the web. Keeping it in a
+//! private cookie lets the creator find the edit link on the clip page later. The token
+//! never goes in a URL, where it would end up in logs, history and `Referer` headers;
+//! other browsers submit it in a form body instead.
+
+use crate::domain::clip::field::EditToken;
+use crate::ShortCode;
+use rocket::http::{Cookie, CookieJar, SameSite};
+
+/// Prefix of the edit cookie name; the clip [`ShortCode`] is appended.
+pub const EDIT_COOKIE_PREFIX: &str = "edit-";
+
+/// How long the browser remembers the edit token of a clip.
+pub const EDIT_TTL_DAYS: i64 = 30;
+
+/// Name of the edit cookie for `shortcode`.
+pub fn cookie_name(shortcode: &ShortCode) -> String {
+    format!("{}{}", EDIT_COOKIE_PREFIX, shortcode.as_str())
+}
+
+/// Remembers the `token` to edit the clip at `shortcode`.
+pub fn remember(cookies: &CookieJar<'_>, shortcode: &ShortCode, token: &EditToken) {
+    let cookie = Cookie::build(cookie_name(shortcode), token.as_str().to_owned())
+        .path("/clip")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .max_age(rocket::time::Duration::days(EDIT_TTL_DAYS))
+        .finish();
+    cookies.add_private(cookie);
+}
+
+/// The edit token remembered for `shortcode`, if any.
+pub fn token(cookies: &CookieJar<'_>, shortcode: &ShortCode) -> Option<EditToken> {
+    cookies
+        .get_private(&cookie_name(shortcode))
+        .map(|cookie| EditToken::new(cookie.value().to_owned()))
+}
+
+/// The link to edit the clip at `shortcode`, authorized by its edit cookie.
+pub fn edit_url(shortcode: &ShortCode) -> String {
+    format!("/clip/{}/edit", shortcode.as_str())
+}
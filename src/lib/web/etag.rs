@@ -0,0 +1,142 @@
+//! Entity tags and conditional requests for clips.
+//!
+//! A clip's [`ETag`] is derived from its [`Version`], which changes whenever the clip is
+//! updated. Hit counts are not part of the version, so counting a view does not
+//! invalidate caches. Responses that carry the hit count then differ between views of
+//! the same version, so the tag is weak: it marks the clip as semantically unchanged,
+//! not the response as byte for byte the same.
+
+use crate::domain::clip::field::Version;
+use rocket::http::{Header, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, Responder, Response};
+
+/// The weak entity tag of a clip at some [`Version`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ETag(i64);
+
+impl ETag {
+    /// Parses a single entity tag such as `"3"` or `W/"3"`, ignoring the weak marker.
+    fn parse(tag: &str) -> Option<Self> {
+        let tag = tag.trim();
+        let tag = tag.strip_prefix("W/").unwrap_or(tag);
+        tag.strip_prefix('"')?
+            .strip_suffix('"')?
+            .parse()
+            .ok()
+            .map(Self)
+    }
+
+    /// Whether a comma separated list of entity tags contains this tag, or is `*`.
+    fn matches_any(&self, tags: &str) -> bool {
+        tags.trim() == "*"
+            || tags
+                .split(',')
+                .filter_map(Self::parse)
+                .any(|tag| tag == *self)
+    }
+}
+
+impl From<Version> for ETag {
+    fn from(version: Version) -> Self {
+        Self(version.into_inner())
+    }
+}
+
+impl From<ETag> for Header<'static> {
+    fn from(etag: ETag) -> Self {
+        Header::new("ETag", format!("W/\"{}\"", etag.0))
+    }
+}
+
+/// The `If-Match` request header, used to make updates conditional.
+#[derive(Debug, Clone, Default)]
+pub struct IfMatch(Option<String>);
+
+impl IfMatch {
+    /// The [`Version`] an update must find the clip at, or `None` to update unconditionally.
+    ///
+    /// Clips are only tagged weakly, yet the [`Version`] a tag names is exactly what an
+    /// update must not overwrite, so weak tags are accepted along with strong ones.
+    /// Versions start at 1, so a header without any usable tag expects version 0 and
+    /// never matches.
+    pub fn version(&self) -> Option<Version> {
+        let tags = self.0.as_deref()?;
+        if tags.trim() == "*" {
+            return None;
+        }
+        let version = tags
+            .split(',')
+            .find_map(ETag::parse)
+            .map_or(0, |tag| tag.0);
+        Some(Version::new(version))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfMatch {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Self(
+            req.headers().get_one("If-Match").map(ToOwned::to_owned),
+        ))
+    }
+}
+
+/// The `If-None-Match` request header, used to skip resending unchanged clips.
+#[derive(Debug, Clone, Default)]
+pub struct IfNoneMatch(Option<String>);
+
+impl IfNoneMatch {
+    /// Whether the client already holds the representation tagged with `etag`.
+    pub fn matches(&self, etag: ETag) -> bool {
+        self.0.as_deref().is_some_and(|tags| etag.matches_any(tags))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IfNoneMatch {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(Self(
+            req.headers()
+                .get_one("If-None-Match")
+                .map(ToOwned::to_owned),
+        ))
+    }
+}
+
+/// A response tagged with an [`ETag`], or `304 Not Modified` if the client has it already.
+pub enum Tagged<R> {
+    /// Send `R` with its [`ETag`].
+    Modified(ETag, R),
+    /// Send only the [`ETag`] with `304 Not Modified`.
+    NotModified(ETag),
+}
+
+impl<R> Tagged<R> {
+    /// Tags `inner`, unless the `If-None-Match` header shows the client already has it.
+    pub fn new(etag: ETag, inner: R, if_none_match: &IfNoneMatch) -> Self {
+        if if_none_match.matches(etag) {
+            Self::NotModified(etag)
+        } else {
+            Self::Modified(etag, inner)
+        }
+    }
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Tagged<R> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'o> {
+        match self {
+            Self::Modified(etag, inner) => Response::build_from(inner.respond_to(req)?)
+                .header(etag)
+                .ok(),
+            Self::NotModified(etag) => Response::build()
+                .status(Status::NotModified)
+                .header(etag)
+                .ok(),
+        }
+    }
+}
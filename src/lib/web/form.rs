@@ -1,16 +1,113 @@
+// The `FromForm` derive still emits `allow(private_in_public)`, which newer compilers removed.
+#![allow(renamed_and_removed_lints)]
+
 use crate::domain::clip::field;
-use rocket::form::FromForm;
+use crate::domain::time::Time;
+use chrono::{FixedOffset, Offset, Utc};
+use rocket::form::{self, FromForm, FromFormField, ValueField};
 use serde::Serialize;
 
+/// The submitter's offset from UTC, in minutes east, as filled in by the clip form's script.
+#[derive(Debug, Clone, Copy)]
+pub struct TzOffset(FixedOffset);
+
+impl TzOffset {
+    pub fn into_inner(self) -> FixedOffset {
+        self.0
+    }
+}
+
+impl Default for TzOffset {
+    fn default() -> Self {
+        Self(Utc.fix())
+    }
+}
+
+impl Serialize for TzOffset {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(self.0.local_minus_utc() / 60)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for TzOffset {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        let value = field.value.trim();
+        if value.is_empty() {
+            return Ok(<Self as Default>::default());
+        }
+        value
+            .parse::<i32>()
+            .ok()
+            .and_then(|minutes| minutes.checked_mul(60))
+            .and_then(FixedOffset::east_opt)
+            .map(Self)
+            .ok_or_else(|| form::Error::validation("invalid timezone offset").into())
+    }
+
+    fn default() -> Option<Self> {
+        Some(<Self as Default>::default())
+    }
+}
+
+/// An expiry as typed into the clip form. Dates and times without an explicit offset are
+/// read in the submitter's timezone, see [`LocalExpiry::at`].
+#[derive(Debug, Default, Serialize)]
+pub struct LocalExpiry(String);
+
+impl LocalExpiry {
+    pub fn at(&self, offset: TzOffset) -> field::ExpiresAt {
+        // Validated against UTC when parsed, and a fixed offset can't make it invalid.
+        field::ExpiresAt::parse_in(&self.0, offset.into_inner()).unwrap_or_default()
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for LocalExpiry {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        let value = field.value.trim();
+        if !value.is_empty() {
+            Time::parse_in(value, Utc.fix())
+                .map_err(|e| form::Error::validation(format!("{}", e)))?;
+        }
+        Ok(Self(value.to_owned()))
+    }
+}
+
 #[derive(Debug, Serialize, FromForm)]
 pub struct NewClip {
     pub title: field::Title,
     pub content: field::Content,
     pub password: field::Password,
-    pub expires_at: field::ExpiresAt,
+    pub expires_at: LocalExpiry,
+    pub tz_offset: TzOffset,
+}
+
+#[derive(Debug, Serialize, FromForm)]
+pub struct UpdateClip {
+    pub title: field::Title,
+    pub content: field::Content,
+    pub password: field::Password,
+    pub expires_at: LocalExpiry,
+    pub tz_offset: TzOffset,
+    pub edit_token: String,
+    /// Clears the password, which a blank password field keeps.
+    pub remove_password: bool,
+    /// The [`Version`](field::Version) the form was opened at, to detect concurrent edits.
+    pub version: Option<i64>,
+}
+
+#[derive(Debug, Serialize, FromForm)]
+pub struct SubmitEditToken {
+    pub edit_token: String,
 }
 
 #[derive(Debug, Serialize, FromForm)]
 pub struct GetPasswordProtectedClip {
     pub password: field::Password,
 }
+
+#[derive(Debug, Serialize, FromForm)]
+pub struct GenerateApiKey {
+    pub scopes: Vec<String>,
+}
@@ -0,0 +1,146 @@
+//! Liveness and readiness probes.
+
+use crate::data::AppDatabase;
+use crate::domain::maintenance::Maintenance;
+use crate::service::action;
+use crate::web::{HitCounter, KeyUsage};
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::Serialize;
+
+/// The outcome of a single readiness check.
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Check {
+    fn pass() -> Self {
+        Self {
+            ok: true,
+            detail: None,
+        }
+    }
+
+    fn fail<S: Into<String>>(detail: S) -> Self {
+        Self {
+            ok: false,
+            detail: Some(detail.into()),
+        }
+    }
+}
+
+/// Response body of the liveness probe.
+#[derive(Debug, Serialize)]
+pub struct Liveness {
+    pub status: &'static str,
+}
+
+/// Response body of the readiness probe.
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    /// Either `ready` or `degraded`.
+    pub status: &'static str,
+    pub database: Check,
+    pub migrations: Check,
+    pub hit_counter: Check,
+    pub key_usage: Check,
+    pub maintenance: Check,
+}
+
+impl Readiness {
+    fn is_ready(&self) -> bool {
+        self.database.ok
+            && self.migrations.ok
+            && self.hit_counter.ok
+            && self.key_usage.ok
+            && self.maintenance.ok
+    }
+}
+
+/// Reports that the process is up and serving requests.
+#[rocket::get("/healthz")]
+pub fn healthz() -> Json<Liveness> {
+    Json(Liveness { status: "ok" })
+}
+
+/// Reports whether the database and background tasks are usable.
+///
+/// Responds with `503 Service Unavailable` when any check fails.
+#[rocket::get("/readyz")]
+pub async fn readyz(
+    database: &State<AppDatabase>,
+    hit_counter: &State<HitCounter>,
+    key_usage: &State<KeyUsage>,
+    maintenance: &State<Maintenance>,
+) -> status::Custom<Json<Readiness>> {
+    let pool = database.get_pool();
+
+    let database = match action::ping_database(pool).await {
+        Ok(()) => Check::pass(),
+        Err(e) => Check::fail(format!("{}", e)),
+    };
+    let migrations = match action::pending_migrations(pool).await {
+        Ok(pending) if pending.is_empty() => Check::pass(),
+        Ok(pending) => Check::fail(format!("pending migrations: {:?}", pending)),
+        Err(e) => Check::fail(format!("{}", e)),
+    };
+    let hit_counter = match hit_counter.is_alive() {
+        true => Check::pass(),
+        false => Check::fail("hit counter thread stopped"),
+    };
+    let key_usage = match key_usage.is_alive() {
+        true => Check::pass(),
+        false => Check::fail("key usage thread stopped"),
+    };
+    let maintenance = match maintenance.is_alive() {
+        true => Check::pass(),
+        false => Check::fail("maintenance task stopped"),
+    };
+
+    let mut readiness = Readiness {
+        status: "ready",
+        database,
+        migrations,
+        hit_counter,
+        key_usage,
+        maintenance,
+    };
+    if readiness.is_ready() {
+        status::Custom(Status::Ok, Json(readiness))
+    } else {
+        readiness.status = "degraded";
+        status::Custom(Status::ServiceUnavailable, Json(readiness))
+    }
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![healthz, readyz]
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::web::test::client;
+    use rocket::http::Status;
+
+    #[test]
+    fn reports_alive() {
+        let client = client();
+        let response = client.get("/healthz").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn reports_ready() {
+        let client = client();
+        let response = client.get("/readyz").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(body["status"], "ready");
+        assert_eq!(body["migrations"]["ok"], true);
+    }
+}
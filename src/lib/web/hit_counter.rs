@@ -6,6 +6,7 @@ use crossbeam_channel::{unbounded, Sender};
 use parking_lot::Mutex;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::thread::JoinHandle;
 use std::time::Duration;
 use tokio::runtime::Handle;
 
@@ -40,6 +41,7 @@ enum HitCountMsg {
 /// database block all reads.
 pub struct HitCounter {
     tx: Sender<HitCountMsg>,
+    thread: JoinHandle<()>,
 }
 
 impl HitCounter {
@@ -57,7 +59,7 @@ impl HitCounter {
             hits_vec
         };
         handle.block_on(async move {
-            if hits.len() > 0 {
+            if !hits.is_empty() {
                 let transaction = service::action::begin_transaction(&pool).await?;
                 for (shortcode, hits) in hits {
                     if let Err(e) =
@@ -96,7 +98,7 @@ impl HitCounter {
         let (tx, rx) = unbounded();
         let tx_clone = tx.clone();
 
-        let _ = std::thread::spawn(move || {
+        let thread = std::thread::spawn(move || {
             let store: HitStore = Arc::new(Mutex::new(HashMap::new()));
 
             loop {
@@ -121,7 +123,12 @@ impl HitCounter {
             }
         });
 
-        Self { tx }
+        Self { tx, thread }
+    }
+
+    /// Whether the background thread is still processing hits.
+    pub fn is_alive(&self) -> bool {
+        !self.thread.is_finished()
     }
 
     /// Add `count` number of hits to the [`Clip`](crate::Clip) that is referenced by the [`ShortCode`](crate::domain::clip::field::ShortCode).
@@ -2,17 +2,28 @@
 
 use super::hit_counter::HitCounter;
 use crate::data::AppDatabase;
+use crate::domain::clip::field::{self, EditToken, Owner, Version};
+use crate::domain::{Quota, QuotaError, Scope, Scopes};
 use crate::service;
 use crate::service::action;
-use crate::web::PASSWORD_COOKIE;
-use crate::web::{ctx, form, renderer::Renderer, PageError};
-use crate::{ServiceError, ShortCode};
+use crate::web::etag::{IfNoneMatch, Tagged};
+use crate::web::key_generation::KeyGeneration;
+use crate::web::password_attempts::PasswordAttempt;
+use crate::web::rate_limit::{ClientIp, Create, Password, RateLimited, Read};
+use crate::web::{ctx, edit_cookie, form, renderer::Renderer, unlock, PageError};
+use crate::{Clip, ServiceError, ShortCode};
+use chrono::SecondsFormat;
 use rocket::form::{Contextual, Form};
-use rocket::http::{Cookie, CookieJar, Status};
+use rocket::http::{ContentType, CookieJar, Status};
 use rocket::response::content::RawHtml;
 
-use rocket::response::{status, Redirect};
-use rocket::{uri, State};
+use rocket::response::stream::ReaderStream;
+use rocket::response::{self, status, Redirect, Responder};
+use rocket::tokio::io::{AsyncRead, ReadBuf};
+use rocket::{uri, Request, State};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
 
 #[rocket::get("/")]
 fn home(renderer: &State<Renderer<'_>>) -> RawHtml<String> {
@@ -21,72 +32,153 @@ fn home(renderer: &State<Renderer<'_>>) -> RawHtml<String> {
 }
 
 #[rocket::get("/key/new")]
-fn api_key(renderer: &State<Renderer<'_>>) -> RawHtml<String> {
-    let context = ctx::ApiKeyGenerate::default();
+fn api_key(
+    renderer: &State<Renderer<'_>>,
+    key_generation: &State<KeyGeneration>,
+) -> RawHtml<String> {
+    let context = ctx::ApiKeyGenerate::new(key_generation);
     RawHtml(renderer.render(&context, &[]))
 }
 
-#[rocket::post("/key/new")]
+/// The scopes ticked on the key generation page.
+///
+/// Self-service keys may not grant themselves [`Scope::Admin`].
+fn requested_scopes(form: &form::GenerateApiKey) -> Result<Scopes, (Status, &'static str)> {
+    if form.scopes.is_empty() {
+        return Err((Status::BadRequest, "select at least one scope"));
+    }
+    let scopes = Scopes::from_str(&form.scopes.join(" "))
+        .map_err(|_| (Status::BadRequest, "unknown scope"))?;
+    if scopes.contains(Scope::Admin) {
+        return Err((Status::Forbidden, "the admin scope cannot be requested"));
+    }
+    Ok(scopes)
+}
+
+#[rocket::post("/key/new", data = "<form>")]
 pub async fn generate_api_key(
+    form: Form<form::GenerateApiKey>,
+    client_ip: ClientIp,
     database: &State<AppDatabase>,
     renderer: &State<Renderer<'_>>,
+    key_generation: &State<KeyGeneration>,
 ) -> Result<status::Custom<RawHtml<String>>, PageError> {
-    println!("Generating new api key...");
-    match action::generate_api_key(database.get_pool()).await {
+    let context = ctx::ApiKeyGenerate::new(key_generation);
+    let rejected = |status: Status, msg: &str| {
+        status::Custom(status, RawHtml(renderer.render(&context, &[msg])))
+    };
+    if !key_generation.is_enabled() {
+        return Ok(rejected(
+            Status::Forbidden,
+            "self-service key generation is disabled",
+        ));
+    }
+    let scopes = match requested_scopes(&form) {
+        Ok(scopes) => scopes,
+        Err((status, msg)) => return Ok(rejected(status, msg)),
+    };
+    if !key_generation.try_acquire(client_ip.into_inner()) {
+        return Ok(rejected(
+            Status::TooManyRequests,
+            "too many API keys generated, try again later",
+        ));
+    }
+    let pool = database.get_pool();
+    let req = service::ask::NewApiKey::with_scopes(scopes.clone());
+    match action::generate_api_key(req, pool).await {
         Ok(api_key) => {
-            println!("Api Key: {}", api_key.to_base64());
-            let context = ctx::ApiKeyGenerate::default();
+            // Only the public id is logged; the key itself is shown once, on the page.
+            if let Ok(Some(key)) = action::get_api_key(api_key.clone(), pool).await {
+                eprintln!("generated api key {} with scopes {}", key.id, scopes);
+            }
+            let context = ctx::ApiKeyGenerate {
+                scopes: Some(scopes.to_string()),
+                ..context
+            };
             Ok(status::Custom(
                 Status::Ok,
                 RawHtml(renderer.render_with_data(context, ("api_key", api_key.to_base64()), &[])),
             ))
         }
-        Err(e) => {
-            dbg!(&e);
-            Err(PageError::InternalError(format!("{}", e)))
-        }
+        Err(e) => Err(PageError::InternalError(format!("{}", e))),
+    }
+}
+
+/// The messages of all errors in a submitted form, to show above the form.
+fn form_errors<'a>(context: &'a rocket::form::Context<'_>) -> Vec<&'a str> {
+    context
+        .errors()
+        .map(|err| {
+            use rocket::form::error::ErrorKind;
+            if let ErrorKind::Validation(msg) = &err.kind {
+                msg.as_ref()
+            } else {
+                eprintln!("unhandled error: {}", err);
+                "An error occurred, please try again"
+            }
+        })
+        .collect()
+}
+
+/// The status and form error for a clip that could not be saved.
+fn write_error(err: &ServiceError) -> (Status, String) {
+    match err {
+        ServiceError::Clip(e) if e.is_too_long() => (Status::PayloadTooLarge, e.to_string()),
+        ServiceError::Clip(e) => (Status::BadRequest, e.to_string()),
+        ServiceError::Quota(e @ QuotaError::Bytes(_)) => (Status::PayloadTooLarge, e.to_string()),
+        ServiceError::Quota(e) => (Status::TooManyRequests, e.to_string()),
+        ServiceError::PreconditionFailed(_) => (
+            Status::PreconditionFailed,
+            "The clip was changed since you opened it, reload the page to see the changes"
+                .to_owned(),
+        ),
+        _ => (
+            Status::InternalServerError,
+            "A server error occurred, please try again".to_owned(),
+        ),
     }
 }
 
 #[rocket::post("/", data = "<form>")]
 pub async fn new_clip(
+    _rate_limit: RateLimited<Create>,
+    cookies: &CookieJar<'_>,
     form: Form<Contextual<'_, form::NewClip>>,
     database: &State<AppDatabase>,
+    quota: &State<Quota>,
     renderer: &State<Renderer<'_>>,
 ) -> Result<Redirect, (Status, RawHtml<String>)> {
     let form = form.into_inner();
     if let Some(value) = form.value {
+        let edit_token = EditToken::generate();
         let req = service::ask::NewClip {
             title: value.title,
             content: value.content,
-            password: value.password,
-            expires_at: value.expires_at,
+            password: value.password.clone(),
+            expires_at: value.expires_at.at(value.tz_offset),
+            owner: Owner::with_edit_token(&edit_token),
         };
 
-        match action::new_clip(req, database.get_pool()).await {
-            Ok(clip) => Ok(Redirect::to(uri!(get_clip(clip.shortcode)))),
-            Err(_) => Err((
-                Status::InternalServerError,
-                RawHtml(renderer.render(
-                    &ctx::Home::default(),
-                    &["A server error occurred, please try again"],
-                )),
-            )),
+        match action::new_clip(req, quota, database.get_pool()).await {
+            Ok(clip) => {
+                edit_cookie::remember(cookies, &clip.shortcode, &edit_token);
+                unlock::grant(cookies, &clip.shortcode, &value.password);
+                Ok(Redirect::to(uri!(get_clip(clip.shortcode))))
+            }
+            Err(e) => {
+                let (status, message) = write_error(&e);
+                Err((
+                    status,
+                    RawHtml(renderer.render_with_data(
+                        ctx::Home::default(),
+                        ("clip", &form.context),
+                        &[message.as_str()],
+                    )),
+                ))
+            }
         }
     } else {
-        let errors = form
-            .context
-            .errors()
-            .map(|err| {
-                use rocket::form::error::ErrorKind;
-                if let ErrorKind::Validation(msg) = &err.kind {
-                    msg.as_ref()
-                } else {
-                    eprintln!("unhandled error: {}", err);
-                    "An error occurred, please try again"
-                }
-            })
-            .collect::<Vec<_>>();
+        let errors = form_errors(&form.context);
         Err((
             Status::BadRequest,
             RawHtml(renderer.render_with_data(
@@ -98,13 +190,23 @@ pub async fn new_clip(
     }
 }
 
+/// A rendered clip, tagged with its version, or the page asking for its password.
+#[derive(rocket::Responder)]
+pub enum ClipPage {
+    View(Tagged<status::Custom<RawHtml<String>>>),
+    Locked(status::Custom<RawHtml<String>>),
+}
+
 #[rocket::get("/clip/<shortcode>")]
 pub async fn get_clip(
+    _rate_limit: RateLimited<Read>,
+    cookies: &CookieJar<'_>,
+    if_none_match: IfNoneMatch,
     shortcode: ShortCode,
     database: &State<AppDatabase>,
     renderer: &State<Renderer<'_>>,
     hit_counter: &State<HitCounter>,
-) -> Result<status::Custom<RawHtml<String>>, PageError> {
+) -> Result<ClipPage, PageError> {
     fn render_with_status<T: ctx::PageContext + serde::Serialize + std::fmt::Debug>(
         status: Status,
         context: T,
@@ -116,18 +218,27 @@ pub async fn get_clip(
         ))
     }
 
-    match action::get_clip(shortcode.clone().into(), database.get_pool()).await {
+    let req = service::ask::GetClip {
+        password: unlock::password(cookies, &shortcode),
+        shortcode: shortcode.clone(),
+    };
+    match action::get_clip(req, database.get_pool()).await {
         Ok(clip) => {
             hit_counter.hit(shortcode.clone(), 1);
-            let context = ctx::ClipView::new(clip);
-            render_with_status(Status::Ok, context, renderer)
+            let etag = clip.version.into();
+            let edit_url =
+                edit_cookie::token(cookies, &shortcode).map(|_| edit_cookie::edit_url(&shortcode));
+            let context = ctx::ClipView::new(clip, edit_url);
+            let page = render_with_status(Status::Ok, context, renderer)?;
+            Ok(ClipPage::View(Tagged::new(etag, page, &if_none_match)))
         }
         Err(e) => match e {
             ServiceError::PermissionError(_) => {
                 let context = ctx::ClipRequirePassword::new(shortcode);
-                render_with_status(Status::Unauthorized, context, renderer)
+                render_with_status(Status::Unauthorized, context, renderer).map(ClipPage::Locked)
             }
             ServiceError::NotFound => Err(PageError::NotFound("clip not found".to_owned())),
+            ServiceError::Expired => Err(PageError::Gone("clip expired".to_owned())),
             _ => Err(PageError::InternalError(format!("{}", e))),
         },
     }
@@ -135,76 +246,344 @@ pub async fn get_clip(
 
 #[rocket::post("/clip/<shortcode>", data = "<form>")]
 pub async fn submit_clip_password(
+    _rate_limit: RateLimited<Password>,
+    attempt: PasswordAttempt<'_>,
     cookies: &CookieJar<'_>,
     form: Form<Contextual<'_, form::GetPasswordProtectedClip>>,
     shortcode: ShortCode,
     database: &State<AppDatabase>,
     renderer: &State<Renderer<'_>>,
-) -> Result<RawHtml<String>, PageError> {
+) -> Result<status::Custom<RawHtml<String>>, PageError> {
+    let ok = |html| Ok(status::Custom(Status::Ok, RawHtml(html)));
     if let Some(form) = &form.value {
         let req = service::ask::GetClip {
             shortcode: shortcode.clone(),
             password: form.password.clone(),
         };
 
-        match action::get_clip(req, database.get_pool()).await {
+        let result = match attempt
+            .guard(&shortcode, true, action::get_clip(req, database.get_pool()))
+            .await
+        {
+            Ok(result) => result,
+            Err(lockout) => {
+                let context = ctx::ClipRequirePassword::locked_out(shortcode, &lockout);
+                let html = renderer.render(&context, &[]);
+                return Ok(status::Custom(Status::TooManyRequests, RawHtml(html)));
+            }
+        };
+        match result {
             Ok(clip) => {
-                let context = ctx::ClipView::new(clip);
-                cookies.add(Cookie::new(
-                    PASSWORD_COOKIE,
-                    form.password.clone().into_inner().unwrap_or_default(),
-                ));
-                Ok(RawHtml(renderer.render(&context, &[])))
+                let edit_url = edit_cookie::token(cookies, &shortcode)
+                    .map(|_| edit_cookie::edit_url(&shortcode));
+                let context = ctx::ClipView::new(clip, edit_url);
+                unlock::grant(cookies, &shortcode, &form.password);
+                ok(renderer.render(&context, &[]))
             }
             Err(e) => match e {
                 ServiceError::PermissionError(_) => {
                     let context = ctx::ClipRequirePassword::new(shortcode);
-                    Ok(RawHtml(renderer.render(&context, &["incorrect password"])))
+                    ok(renderer.render(&context, &["incorrect password"]))
                 }
                 ServiceError::NotFound => Err(PageError::NotFound("clip not found".to_owned())),
+                ServiceError::Expired => Err(PageError::Gone("clip expired".to_owned())),
                 _ => Err(PageError::InternalError(format!("{}", e))),
             },
         }
     } else {
         let context = ctx::ClipRequirePassword::new(shortcode);
-        Ok(RawHtml(renderer.render(&context, &[])))
+        ok(renderer.render(&context, &[]))
+    }
+}
+
+/// The values of `clip` in the shape of a submitted form, to fill in the edit page.
+///
+/// The expiry keeps its offset so that saving unchanged doesn't move it to the editor's timezone.
+fn form_values(clip: &Clip) -> serde_json::Value {
+    let expires_at = clip
+        .expires_at
+        .clone()
+        .into_inner()
+        .map(|time| time.into_inner().to_rfc3339_opts(SecondsFormat::Secs, true));
+    serde_json::json!({
+        "values": {
+            "title": [clip.title.clone().into_inner().unwrap_or_default()],
+            "content": [clip.content.as_str()],
+            "expires_at": [expires_at.unwrap_or_default()],
+            "version": [clip.version.into_inner()],
+        }
+    })
+}
+
+#[rocket::get("/clip/<shortcode>/edit", rank = 2)]
+pub async fn edit_clip(
+    cookies: &CookieJar<'_>,
+    shortcode: ShortCode,
+    database: &State<AppDatabase>,
+    renderer: &State<Renderer<'_>>,
+) -> Result<status::Custom<RawHtml<String>>, PageError> {
+    let ask_for_token = |errors: &[&str]| {
+        let context = ctx::EditRequireToken::new(shortcode.clone());
+        Ok(status::Custom(
+            Status::Forbidden,
+            RawHtml(renderer.render(&context, errors)),
+        ))
+    };
+    // The token only travels in the cookie or a form body, never in the URL.
+    let edit_token = match edit_cookie::token(cookies, &shortcode) {
+        Some(token) => token,
+        None => return ask_for_token(&[]),
+    };
+    let credentials = service::ask::Credentials {
+        edit_token: Some(edit_token.clone()),
+        ..Default::default()
+    };
+    match action::get_clip_for_update(&shortcode, &credentials, database.get_pool()).await {
+        Ok(clip) => {
+            let context = ctx::EditClip::new(&shortcode, &edit_token);
+            Ok(status::Custom(
+                Status::Ok,
+                RawHtml(renderer.render_with_data(context, ("clip", form_values(&clip)), &[])),
+            ))
+        }
+        Err(ServiceError::PermissionError(_)) => {
+            ask_for_token(&["The remembered edit token is no longer valid"])
+        }
+        Err(ServiceError::NotFound) => Err(PageError::NotFound("clip not found".to_owned())),
+        Err(ServiceError::Expired) => Err(PageError::Gone("clip expired".to_owned())),
+        Err(e) => Err(PageError::InternalError(format!("{}", e))),
+    }
+}
+
+/// Remembers a pasted edit token, so that the clip can be edited from another browser.
+#[rocket::post("/clip/<shortcode>/edit/token", data = "<form>")]
+pub async fn submit_edit_token(
+    _rate_limit: RateLimited<Password>,
+    cookies: &CookieJar<'_>,
+    shortcode: ShortCode,
+    form: Form<form::SubmitEditToken>,
+    database: &State<AppDatabase>,
+    renderer: &State<Renderer<'_>>,
+) -> Result<Redirect, (Status, RawHtml<String>)> {
+    let edit_token = EditToken::new(form.into_inner().edit_token);
+    let credentials = service::ask::Credentials {
+        edit_token: Some(edit_token.clone()),
+        ..Default::default()
+    };
+    match action::get_clip_for_update(&shortcode, &credentials, database.get_pool()).await {
+        Ok(_) => {
+            edit_cookie::remember(cookies, &shortcode, &edit_token);
+            Ok(Redirect::to(edit_cookie::edit_url(&shortcode)))
+        }
+        Err(e) => {
+            let (status, message) = match e {
+                ServiceError::PermissionError(_) => (Status::Forbidden, "invalid edit token"),
+                ServiceError::NotFound => (Status::NotFound, "clip not found"),
+                ServiceError::Expired => (Status::Gone, "clip expired"),
+                _ => (
+                    Status::InternalServerError,
+                    "A server error occurred, please try again",
+                ),
+            };
+            let context = ctx::EditRequireToken::new(shortcode);
+            Err((status, RawHtml(renderer.render(&context, &[message]))))
+        }
+    }
+}
+
+#[rocket::post("/clip/<shortcode>/edit", data = "<form>", rank = 2)]
+pub async fn update_clip(
+    cookies: &CookieJar<'_>,
+    shortcode: ShortCode,
+    form: Form<Contextual<'_, form::UpdateClip>>,
+    database: &State<AppDatabase>,
+    quota: &State<Quota>,
+    renderer: &State<Renderer<'_>>,
+) -> Result<Redirect, (Status, RawHtml<String>)> {
+    let form = form.into_inner();
+    let edit_token = EditToken::new(
+        form.context
+            .field_value("edit_token")
+            .unwrap_or_default()
+            .to_owned(),
+    );
+    // The form context is not `Sync`, so keep only its serialized values across awaits.
+    let submitted = Renderer::to_value(&form.context);
+    let render_error = |status: Status, errors: &[&str]| {
+        let context = ctx::EditClip::new(&shortcode, &edit_token);
+        let page = renderer.render_with_data(context, ("clip", &submitted), errors);
+        (status, RawHtml(page))
+    };
+    let value = match form.value {
+        Some(value) => value,
+        None => {
+            return Err(render_error(
+                Status::BadRequest,
+                &form_errors(&form.context),
+            ))
+        }
+    };
+    let credentials = service::ask::Credentials {
+        edit_token: Some(edit_token.clone()),
+        ..Default::default()
+    };
+    let pool = database.get_pool();
+    let current = match action::get_clip_for_update(&shortcode, &credentials, pool).await {
+        Ok(clip) => clip,
+        Err(ServiceError::PermissionError(_)) => {
+            return Err(render_error(Status::Forbidden, &["invalid edit link"]))
+        }
+        Err(ServiceError::NotFound) => {
+            return Err(render_error(Status::NotFound, &["clip not found"]))
+        }
+        Err(ServiceError::Expired) => return Err(render_error(Status::Gone, &["clip expired"])),
+        Err(_) => {
+            return Err(render_error(
+                Status::InternalServerError,
+                &["A server error occurred, please try again"],
+            ))
+        }
+    };
+    // A blank password keeps the current one, which the form never shows; a checkbox clears it.
+    let password = if value.remove_password {
+        field::Password::default()
+    } else if value.password.has_password() {
+        value.password
+    } else {
+        current.password
+    };
+    let req = service::ask::UpdateClip {
+        title: value.title,
+        content: value.content,
+        password: password.clone(),
+        expires_at: value.expires_at.at(value.tz_offset),
+        shortcode: shortcode.clone(),
+        version: value.version.map(Version::new),
+    };
+    match action::update_clip(req, &credentials, quota, pool).await {
+        Ok(clip) => {
+            unlock::grant(cookies, &shortcode, &password);
+            Ok(Redirect::to(uri!(get_clip(clip.shortcode))))
+        }
+        Err(e) => {
+            let (status, message) = write_error(&e);
+            Err(render_error(status, &[message.as_str()]))
+        }
+    }
+}
+
+/// Bytes of decompressed content handed to the response at a time.
+const CONTENT_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Clip content, decompressed as it is sent.
+///
+/// The stored bytes are read into memory with the clip. Decompressing them takes CPU
+/// time, so it runs on the blocking thread pool, a few chunks ahead of the response.
+pub struct ContentStream {
+    chunks: rocket::tokio::sync::mpsc::Receiver<std::io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    sent: usize,
+}
+
+impl ContentStream {
+    /// Starts decompressing `content`.
+    pub fn spawn(mut content: Box<dyn std::io::Read + Send + Sync>) -> Self {
+        let (tx, chunks) = rocket::tokio::sync::mpsc::channel(4);
+        rocket::tokio::task::spawn_blocking(move || loop {
+            let mut chunk = vec![0; CONTENT_CHUNK_BYTES];
+            let read = match content.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    let _ = tx.blocking_send(Err(e));
+                    break;
+                }
+            };
+            chunk.truncate(read);
+            // The response was dropped, so stop decompressing.
+            if tx.blocking_send(Ok(chunk)).is_err() {
+                break;
+            }
+        });
+        Self {
+            chunks,
+            chunk: Vec::new(),
+            sent: 0,
+        }
+    }
+}
+
+impl AsyncRead for ContentStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if self.sent == self.chunk.len() {
+            match self.chunks.poll_recv(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    self.chunk = chunk;
+                    self.sent = 0;
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        let len = buf.remaining().min(self.chunk.len() - self.sent);
+        buf.put_slice(&self.chunk[self.sent..self.sent + len]);
+        self.sent += len;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// The content of a clip as plain text, or the page asking for its password.
+pub enum RawClip {
+    Content(ContentStream),
+    Locked(status::Custom<String>),
+}
+
+impl<'r> Responder<'r, 'r> for RawClip {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'r> {
+        match self {
+            Self::Content(content) => {
+                (ContentType::Plain, ReaderStream::one(content)).respond_to(req)
+            }
+            Self::Locked(page) => page.respond_to(req),
+        }
     }
 }
 
 #[rocket::get("/clip/raw/<shortcode>")]
 pub async fn get_raw_clip(
+    _rate_limit: RateLimited<Read>,
     cookies: &CookieJar<'_>,
     shortcode: ShortCode,
     database: &State<AppDatabase>,
     renderer: &State<Renderer<'_>>,
     hit_counter: &State<HitCounter>,
-) -> Result<status::Custom<String>, Status> {
-    use crate::domain::clip::field::Password;
-
+) -> Result<RawClip, Status> {
     let req = service::ask::GetClip {
+        password: unlock::password(cookies, &shortcode),
         shortcode: shortcode.clone(),
-        password: cookies
-            .get(PASSWORD_COOKIE)
-            .map(|c| c.value())
-            .map(|raw| Password::new(raw.to_string()).ok())
-            .flatten()
-            .unwrap_or_else(|| Password::default()),
     };
 
-    match action::get_clip(req, database.get_pool()).await {
+    match action::get_clip_stream(req, database.get_pool()).await {
         Ok(clip) => {
             hit_counter.hit(shortcode.clone(), 1);
-            Ok(status::Custom(Status::Ok, clip.content.into_inner()))
+            Ok(RawClip::Content(ContentStream::spawn(clip.content)))
         }
         Err(e) => match e {
             ServiceError::PermissionError(_) => {
                 let context = ctx::ClipRequirePassword::new(shortcode);
-                Ok(status::Custom(
+                Ok(RawClip::Locked(status::Custom(
                     Status::Unauthorized,
                     renderer.render(&context, &[]),
-                ))
+                )))
             }
             ServiceError::NotFound => Err(Status::NotFound),
+            ServiceError::Expired => Err(Status::Gone),
             _ => Err(Status::InternalServerError),
         },
     }
@@ -216,6 +595,9 @@ pub fn routes() -> Vec<rocket::Route> {
         get_clip,
         new_clip,
         submit_clip_password,
+        edit_clip,
+        submit_edit_token,
+        update_clip,
         get_raw_clip,
         api_key,
         generate_api_key,
@@ -223,6 +605,9 @@ pub fn routes() -> Vec<rocket::Route> {
 }
 
 pub mod catcher {
+    use crate::web::{ctx, renderer::Renderer, request_id};
+    use rocket::http::Status;
+    use rocket::response::content::RawHtml;
     use rocket::Request;
     use rocket::{catch, catchers, Catcher};
 
@@ -233,7 +618,10 @@ pub mod catcher {
 
     #[catch(500)]
     fn internal_error(req: &Request) -> &'static str {
-        eprintln!("Internal error: {:?}", req);
+        eprintln!(
+            "Internal error: {}",
+            request_id::describe(Status::InternalServerError, req)
+        );
         "internal server error"
     }
 
@@ -243,9 +631,35 @@ pub mod catcher {
         "404"
     }
 
+    /// Catch request bodies over the data limit, which can only be oversized clips.
+    #[catch(413)]
+    fn payload_too_large(req: &Request) -> RawHtml<String> {
+        let limits = crate::domain::clip::field::Limits::get();
+        let message = format!(
+            "The clip is too large, the limit is {} KiB",
+            limits.max_content_bytes / 1024
+        );
+        match req.rocket().state::<Renderer>() {
+            Some(renderer) => RawHtml(renderer.render(&ctx::Home::default(), &[message.as_str()])),
+            None => RawHtml(message),
+        }
+    }
+
+    /// Catch rate limited requests.
+    #[catch(429)]
+    fn too_many_requests() -> &'static str {
+        "too many requests, try again later"
+    }
+
     /// The [`catchers`](rocket::Catcher) which can be registered by [`rocket`].
     pub fn catchers() -> Vec<Catcher> {
-        catchers![not_found, default, internal_error]
+        catchers![
+            not_found,
+            default,
+            internal_error,
+            payload_too_large,
+            too_many_requests
+        ]
     }
 }
 
@@ -253,7 +667,10 @@ pub mod catcher {
 pub mod test {
     use crate::data::AppDatabase;
     use crate::web::test::client;
-    use rocket::http::Status;
+    use crate::Clip;
+    use rocket::http::{ContentType, Cookie, Header, Status};
+    use rocket::local::blocking::{Client, LocalResponse};
+    use tokio::runtime::Runtime;
 
     #[test]
     fn gets_home() {
@@ -268,26 +685,38 @@ pub mod test {
         let response = client.get("/clip/notexist").dispatch();
         assert_eq!(response.status(), Status::NotFound);
     }
-    #[test]
-    fn requires_password_when_applicable() {
-        use crate::domain::clip::field::{Content, ExpiresAt, Password, Title};
+    fn new_clip(client: &Client, rt: &Runtime, password: &str) -> Clip {
+        use crate::domain::clip::field::{Content, ExpiresAt, Owner, Password, Title};
         use crate::service;
-        use rocket::http::{ContentType, Cookie};
-
-        let rt = tokio::runtime::Runtime::new().expect("failed to spawn tokio runtime");
 
-        let client = client();
         let db = client.rocket().state::<AppDatabase>().unwrap();
-
         let req = service::ask::NewClip {
             title: Title::default(),
             content: Content::new("content").unwrap(),
             expires_at: ExpiresAt::default(),
-            password: Password::new("123".to_owned()).unwrap(),
+            password: Password::new(password.to_owned()).unwrap(),
+            owner: Owner::default(),
         };
-        let clip = rt
-            .block_on(async move { service::action::new_clip(req, db.get_pool()).await })
-            .unwrap();
+        rt.block_on(async move {
+            service::action::new_clip(req, &Default::default(), db.get_pool()).await
+        })
+        .unwrap()
+    }
+
+    fn unlock(client: &Client, clip: &Clip, password: &str) -> Status {
+        client
+            .post(format!("/clip/{}", clip.shortcode.as_str()))
+            .header(ContentType::Form)
+            .body(format!("password={}", password))
+            .dispatch()
+            .status()
+    }
+
+    #[test]
+    fn requires_password_when_applicable() {
+        let rt = Runtime::new().expect("failed to spawn tokio runtime");
+        let client = client();
+        let clip = new_clip(&client, &rt, "123");
 
         // Block clip when no password is provided
         let response = client
@@ -300,24 +729,443 @@ pub mod test {
         assert_eq!(response.status(), Status::Unauthorized);
 
         // Get clip when the password is provided
+        assert_eq!(unlock(&client, &clip, "123"), Status::Ok);
+
+        // The unlock cookie grants access to both views of the clip
         let response = client
-            .post(format!("/clip/{}", clip.shortcode.as_str()))
-            .header(ContentType::Form)
-            .body("password=123")
+            .get(format!("/clip/raw/{}", clip.shortcode.as_str()))
             .dispatch();
         assert_eq!(response.status(), Status::Ok);
-        // Get clip when the password is provided
+        let response = client
+            .get(format!("/clip/{}", clip.shortcode.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn unlock_cookie_is_per_clip() {
+        let rt = Runtime::new().expect("failed to spawn tokio runtime");
+        let client = client();
+        let first = new_clip(&client, &rt, "123");
+        let second = new_clip(&client, &rt, "456");
+
+        assert_eq!(unlock(&client, &first, "123"), Status::Ok);
+        let response = client
+            .get(format!("/clip/raw/{}", second.shortcode.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        // Unlocking the second clip keeps the first one unlocked
+        assert_eq!(unlock(&client, &second, "456"), Status::Ok);
+        for clip in [&first, &second] {
+            let response = client
+                .get(format!("/clip/raw/{}", clip.shortcode.as_str()))
+                .dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
+    }
+
+    #[test]
+    fn rejects_forged_unlock_cookies() {
+        let rt = Runtime::new().expect("failed to spawn tokio runtime");
+        let client = client();
+        let clip = new_clip(&client, &rt, "123");
+
         let response = client
             .get(format!("/clip/raw/{}", clip.shortcode.as_str()))
             .cookie(Cookie::new("password", "123"))
             .dispatch();
-        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.status(), Status::Unauthorized);
+
+        let response = client
+            .get(format!("/clip/raw/{}", clip.shortcode.as_str()))
+            .cookie(Cookie::new(
+                crate::web::unlock::cookie_name(&clip.shortcode),
+                format!("{}:123", i64::MAX),
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::Unauthorized);
 
-        // Get clip when the password is provided, but incorrect
+        // Expired cookies are ignored even if the browser still sends them
         let response = client
             .get(format!("/clip/raw/{}", clip.shortcode.as_str()))
-            .cookie(Cookie::new("password", "abc"))
+            .private_cookie(Cookie::new(
+                crate::web::unlock::cookie_name(&clip.shortcode),
+                "0:123",
+            ))
             .dispatch();
         assert_eq!(response.status(), Status::Unauthorized);
     }
+
+    #[test]
+    fn streams_compressed_clips() {
+        use crate::domain::clip::field::{Content, ExpiresAt, Owner, Password, Title};
+        use crate::service;
+
+        let rt = Runtime::new().expect("failed to spawn tokio runtime");
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+        let log = "2022-10-28T09:00:00Z INFO request served\n".repeat(5000);
+        let req = service::ask::NewClip {
+            title: Title::default(),
+            content: Content::new(&log).unwrap(),
+            expires_at: ExpiresAt::default(),
+            password: Password::default(),
+            owner: Owner::default(),
+        };
+        let clip = rt
+            .block_on(service::action::new_clip(
+                req,
+                &Default::default(),
+                db.get_pool(),
+            ))
+            .unwrap();
+        let (codec,): (String,) = rt
+            .block_on(sqlx::query_as("SELECT codec FROM blobs").fetch_one(db.get_pool()))
+            .unwrap();
+        assert_eq!(codec, "zstd");
+
+        let response = client
+            .get(format!("/clip/raw/{}", clip.shortcode.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::Plain));
+        assert_eq!(response.into_string().unwrap(), log);
+    }
+
+    #[test]
+    fn clip_view_honors_if_none_match() {
+        let rt = Runtime::new().expect("failed to spawn tokio runtime");
+        let client = client();
+        let clip = new_clip(&client, &rt, "");
+        let uri = format!("/clip/{}", clip.shortcode.as_str());
+
+        let response = client.get(uri.as_str()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let etag = response.headers().get_one("ETag").unwrap().to_owned();
+        let response = client
+            .get(uri.as_str())
+            .header(Header::new("If-None-Match", etag))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotModified);
+    }
+
+    /// Creates a clip through the home page form, returning its location and edit token.
+    fn create_through_form(client: &Client, body: &str) -> (String, String) {
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body(body)
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        let location = response.headers().get_one("Location").unwrap().to_owned();
+
+        let page = client
+            .get(location.as_str())
+            .dispatch()
+            .into_string()
+            .unwrap();
+        let edit_url = format!("{}/edit", location);
+        assert!(page.contains(&format!(r#"href="{}""#, edit_url)));
+        assert!(!page.contains("token="));
+
+        // The edit page carries the token in its form, authorized by the edit cookie
+        let page = client
+            .get(edit_url.as_str())
+            .dispatch()
+            .into_string()
+            .unwrap();
+        let field = r#"name="edit_token" value=""#;
+        let start = page.find(field).expect("edit token in edit form") + field.len();
+        let end = start + page[start..].find('"').unwrap();
+        (location, page[start..end].to_owned())
+    }
+
+    #[test]
+    fn creator_can_edit_clip() {
+        let client = client();
+        let (location, token) =
+            create_through_form(&client, "content=first&title=&password=&expires_at=");
+
+        let response = client.get(format!("{}/edit", location)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.into_string().unwrap().contains("first"));
+
+        // Validation errors are shown on the edit page
+        let response = client
+            .post(format!("{}/edit", location))
+            .header(ContentType::Form)
+            .body(format!(
+                "content=&title=&password=&expires_at=&edit_token={}",
+                token
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        assert!(response.into_string().unwrap().contains("empty content"));
+
+        let response = client
+            .post(format!("{}/edit", location))
+            .header(ContentType::Form)
+            .body(format!(
+                "content=second&title=&password=&expires_at=&edit_token={}",
+                token
+            ))
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        let page = client
+            .get(location.as_str())
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(page.contains("second"));
+    }
+
+    #[test]
+    fn edit_form_removes_passwords() {
+        let client = client();
+        let (location, token) =
+            create_through_form(&client, "content=secret&title=&password=123&expires_at=");
+        let edit = |body: String| {
+            client
+                .post(format!("{}/edit", location))
+                .header(ContentType::Form)
+                .body(format!("{}&edit_token={}", body, token))
+                .dispatch()
+                .status()
+        };
+
+        // A blank password keeps the current one
+        assert_eq!(
+            edit("content=kept&title=&password=&expires_at=".to_owned()),
+            Status::SeeOther
+        );
+        let rt = Runtime::new().expect("failed to spawn tokio runtime");
+        let pool = client.rocket().state::<AppDatabase>().unwrap().get_pool();
+        let shortcode = location.rsplit('/').next().unwrap().to_owned();
+        let is_locked = || {
+            let req = crate::service::ask::GetClip {
+                shortcode: shortcode.clone().into(),
+                password: crate::domain::clip::field::Password::default(),
+            };
+            rt.block_on(crate::service::action::get_clip(req, pool))
+                .is_err()
+        };
+        assert!(is_locked());
+
+        assert_eq!(
+            edit("content=open&title=&password=&expires_at=&remove_password=on".to_owned()),
+            Status::SeeOther
+        );
+        assert!(!is_locked());
+    }
+
+    #[test]
+    fn edit_form_detects_concurrent_edits() {
+        let client = client();
+        let (location, token) =
+            create_through_form(&client, "content=first&title=&password=&expires_at=");
+
+        let page = client
+            .get(format!("{}/edit", location))
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(page.contains(r#"name="version" value="1""#));
+        let edit = |content: &str| {
+            client
+                .post(format!("{}/edit", location))
+                .header(ContentType::Form)
+                .body(format!(
+                    "content={}&title=&password=&expires_at=&version=1&edit_token={}",
+                    content, token
+                ))
+                .dispatch()
+        };
+
+        assert_eq!(edit("second").status(), Status::SeeOther);
+        let response = edit("third");
+        assert_eq!(response.status(), Status::PreconditionFailed);
+        let page = response.into_string().unwrap();
+        assert!(page.contains("The clip was changed since you opened it"));
+        assert!(page.contains("third"));
+    }
+
+    #[test]
+    fn reads_form_expiry_in_submitter_timezone() {
+        let rt = Runtime::new().expect("failed to spawn tokio runtime");
+        let client = client();
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body("content=zoned&title=&password=&expires_at=2999-05-02&tz_offset=120")
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        let location = response.headers().get_one("Location").unwrap().to_owned();
+        let shortcode = location.rsplit('/').next().unwrap().to_owned();
+
+        let pool = client.rocket().state::<AppDatabase>().unwrap().get_pool();
+        let clip = rt
+            .block_on(crate::service::action::get_clip(
+                crate::service::ask::GetClip {
+                    shortcode: shortcode.into(),
+                    password: crate::domain::clip::field::Password::default(),
+                },
+                pool,
+            ))
+            .unwrap();
+        let expected: chrono::DateTime<chrono::Utc> = "2999-05-01T22:00:00Z".parse().unwrap();
+        assert_eq!(clip.expires_at.into_inner().unwrap().into_inner(), expected);
+
+        let page = client
+            .get(location.as_str())
+            .dispatch()
+            .into_string()
+            .unwrap();
+        assert!(page.contains("Expires in "));
+        assert!(page.contains("just now"));
+
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body("content=zoned&title=&password=&expires_at=&tz_offset=nowhere")
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[test]
+    fn edit_requires_valid_token() {
+        let rt = Runtime::new().expect("failed to spawn tokio runtime");
+        let client = client();
+        let clip = new_clip(&client, &rt, "");
+        let uri = format!("/clip/{}/edit", clip.shortcode.as_str());
+
+        let response = client.get(uri.as_str()).dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        assert!(response.into_string().unwrap().contains("edit token"));
+        let response = client
+            .post(format!("{}/token", uri))
+            .header(ContentType::Form)
+            .body("edit_token=guess")
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let response = client
+            .post(uri.as_str())
+            .header(ContentType::Form)
+            .body("content=mine&title=&password=&expires_at=&edit_token=guess")
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn edit_token_can_be_pasted_in_another_browser() {
+        use crate::domain::clip::field::{Content, EditToken, ExpiresAt, Owner, Password, Title};
+        use crate::service;
+
+        let rt = Runtime::new().expect("failed to spawn tokio runtime");
+        let client = client();
+        let db = client.rocket().state::<AppDatabase>().unwrap();
+        // Created elsewhere, so this client has no edit cookie
+        let token = EditToken::generate();
+        let req = service::ask::NewClip {
+            title: Title::default(),
+            content: Content::new("first").unwrap(),
+            expires_at: ExpiresAt::default(),
+            password: Password::default(),
+            owner: Owner::with_edit_token(&token),
+        };
+        let clip = rt
+            .block_on(service::action::new_clip(
+                req,
+                &Default::default(),
+                db.get_pool(),
+            ))
+            .unwrap();
+        let edit_url = format!("/clip/{}/edit", clip.shortcode.as_str());
+
+        let response = client.get(edit_url.as_str()).dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        // Tokens in the query string are ignored
+        let response = client
+            .get(format!("{}?token={}", edit_url, token.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+
+        let response = client
+            .post(format!("{}/token", edit_url))
+            .header(ContentType::Form)
+            .body(format!("edit_token={}", token.as_str()))
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        assert_eq!(
+            response.headers().get_one("Location"),
+            Some(edit_url.as_str())
+        );
+        let response = client.get(edit_url.as_str()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert!(response.into_string().unwrap().contains("first"));
+    }
+
+    fn generate_key<'c>(client: &'c Client, scopes: &str) -> LocalResponse<'c> {
+        client
+            .post("/key/new")
+            .header(ContentType::Form)
+            .body(scopes)
+            .dispatch()
+    }
+
+    #[test]
+    fn self_service_keys_are_rate_limited() {
+        let client = client();
+        let response = generate_key(&client, "scopes=clip:read&scopes=admin");
+        assert_eq!(response.status(), Status::Forbidden);
+        for _ in 0..5 {
+            let response = generate_key(&client, "scopes=clip:read");
+            assert_eq!(response.status(), Status::Ok);
+        }
+        let response = generate_key(&client, "scopes=clip:read");
+        assert_eq!(response.status(), Status::TooManyRequests);
+    }
+
+    #[test]
+    fn self_service_keys_can_be_disabled() {
+        use crate::web::key_generation::KeyGeneration;
+
+        let mut config = crate::web::test::config();
+        config.key_generation = KeyGeneration::disabled();
+        let client = Client::tracked(crate::rocket(config)).unwrap();
+        let page = client.get("/key/new").dispatch().into_string().unwrap();
+        assert!(page.contains("Self-service API keys are disabled"));
+        let response = generate_key(&client, "scopes=clip:read");
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn explains_oversized_clips() {
+        use crate::domain::clip::field::Limits;
+
+        let client = client();
+        let create = |content: String| {
+            client
+                .post("/")
+                .header(ContentType::Form)
+                .body(format!("content={}&title=&password=&expires_at=", content))
+                .dispatch()
+        };
+        let limits = Limits::get();
+
+        let response = create("a".repeat(limits.max_content_bytes + 1));
+        assert_eq!(response.status(), Status::BadRequest);
+        let page = response.into_string().unwrap();
+        assert!(page.contains(&format!(
+            "content is longer than {} bytes",
+            limits.max_content_bytes
+        )));
+
+        let response = create("a".repeat(limits.max_body_bytes() + 1));
+        assert_eq!(response.status(), Status::PayloadTooLarge);
+        let page = response.into_string().unwrap();
+        assert!(page.contains("The clip is too large"));
+        assert!(page.contains("Stash it!"));
+    }
 }
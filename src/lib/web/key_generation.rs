@@ -0,0 +1,76 @@
+//! Policy for creating API keys from the web page.
+//!
+//! Keys can always be created with an admin key through the API. The self-service
+//! page can be turned off entirely; while it is on, each client may only generate a
+//! few keys per hour.
+
+use chrono::Utc;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+
+/// Length of the window in which [`KeyGeneration::per_hour`] keys may be generated.
+const WINDOW_SECONDS: i64 = 60 * 60;
+
+/// Whether, and how often, clients may generate their own API keys.
+pub struct KeyGeneration {
+    self_service: bool,
+    per_hour: u32,
+    /// Start of the current window and the keys generated in it, per client.
+    windows: Mutex<HashMap<IpAddr, (i64, u32)>>,
+}
+
+impl KeyGeneration {
+    /// Allow each client `per_hour` self-service keys per hour.
+    pub fn enabled(per_hour: u32) -> Self {
+        Self {
+            self_service: true,
+            per_hour,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Only allow keys to be created with an admin key.
+    pub fn disabled() -> Self {
+        Self {
+            self_service: false,
+            per_hour: 0,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether the self-service page may generate keys.
+    pub fn is_enabled(&self) -> bool {
+        self.self_service
+    }
+
+    /// The number of keys a client may generate per hour.
+    pub fn per_hour(&self) -> u32 {
+        self.per_hour
+    }
+
+    /// Counts a key generated by `client`, or returns `false` if it is over its limit.
+    ///
+    /// Clients without a known address share a single allowance.
+    pub fn try_acquire(&self, client: Option<IpAddr>) -> bool {
+        if !self.self_service {
+            return false;
+        }
+        let client = client.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        let now = Utc::now().timestamp();
+        let mut windows = self.windows.lock();
+        windows.retain(|_, (start, _)| now - *start < WINDOW_SECONDS);
+        let (_, count) = windows.entry(client).or_insert((now, 0));
+        if *count >= self.per_hour {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
+
+impl Default for KeyGeneration {
+    fn default() -> Self {
+        Self::enabled(5)
+    }
+}
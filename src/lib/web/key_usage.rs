@@ -0,0 +1,131 @@
+use crate::data::DatabasePool;
+use crate::service::{self, ServiceError};
+use crate::web::ApiKey;
+use chrono::Utc;
+use crossbeam_channel::TryRecvError;
+use crossbeam_channel::{unbounded, Sender};
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio::runtime::Handle;
+
+/// Thread-safe shared storage of when each key was last used.
+type UsageStore = Arc<Mutex<HashMap<Vec<u8>, i64>>>;
+
+/// The possible errors that can occur when recording key usage.
+#[derive(Debug, thiserror::Error)]
+enum KeyUsageError {
+    /// Problem with the service.
+    #[error("service error: {0}")]
+    Service(#[from] ServiceError),
+}
+
+/// Message used on the communication channel.
+enum KeyUsageMsg {
+    /// Save the usage times to the database.
+    Commit,
+    /// The [`ApiKey`] was used at this timestamp.
+    Used(Vec<u8>, i64),
+}
+
+/// A threaded recorder of when each [`ApiKey`] was last used.
+///
+/// Works like the [`HitCounter`](crate::web::hit_counter::HitCounter): uses are
+/// buffered and periodically written in one transaction, so authenticating a
+/// request never waits on a SQLite write.
+pub struct KeyUsage {
+    tx: Sender<KeyUsageMsg>,
+    thread: JoinHandle<()>,
+}
+
+impl KeyUsage {
+    /// Save the pending usage times to the database.
+    fn commit_usage(
+        usage: UsageStore,
+        handle: Handle,
+        pool: DatabasePool,
+    ) -> Result<(), KeyUsageError> {
+        let usage: Vec<(Vec<u8>, i64)> = usage.lock().drain().collect();
+        handle.block_on(async move {
+            if !usage.is_empty() {
+                let mut transaction = service::action::begin_transaction(&pool).await?;
+                for (api_key, used_at) in usage {
+                    if let Err(e) =
+                        service::action::touch_api_key(&api_key, used_at, &mut transaction).await
+                    {
+                        eprintln!("error recording api key usage: {}", e);
+                    }
+                }
+                Ok(service::action::end_transaction(transaction).await?)
+            } else {
+                Ok(())
+            }
+        })
+    }
+
+    /// Process an incoming [`message`](KeyUsageMsg).
+    fn process_msg(
+        msg: KeyUsageMsg,
+        usage: UsageStore,
+        handle: Handle,
+        pool: DatabasePool,
+    ) -> Result<(), KeyUsageError> {
+        match msg {
+            KeyUsageMsg::Commit => Self::commit_usage(usage, handle, pool)?,
+            KeyUsageMsg::Used(api_key, used_at) => {
+                let mut usage = usage.lock();
+                let last_used = usage.entry(api_key).or_insert(used_at);
+                *last_used = (*last_used).max(used_at);
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a new [`KeyUsage`].
+    pub fn new(pool: DatabasePool, handle: Handle) -> Self {
+        let (tx, rx) = unbounded();
+        let tx_clone = tx.clone();
+
+        let thread = std::thread::spawn(move || {
+            let store: UsageStore = Arc::new(Mutex::new(HashMap::new()));
+
+            loop {
+                match rx.try_recv() {
+                    Ok(msg) => {
+                        if let Err(e) =
+                            Self::process_msg(msg, store.clone(), handle.clone(), pool.clone())
+                        {
+                            eprintln!("message processing error: {}", e);
+                        }
+                    }
+                    Err(e) => match e {
+                        TryRecvError::Empty => {
+                            std::thread::sleep(Duration::from_secs(5));
+                            if let Err(e) = tx_clone.send(KeyUsageMsg::Commit) {
+                                eprintln!("error sending commit msg to key usage channel: {}", e);
+                            }
+                        }
+                        _ => break,
+                    },
+                }
+            }
+        });
+
+        Self { tx, thread }
+    }
+
+    /// Whether the background thread is still recording usage.
+    pub fn is_alive(&self) -> bool {
+        !self.thread.is_finished()
+    }
+
+    /// Record that `api_key` was used just now.
+    pub fn used(&self, api_key: &ApiKey) {
+        let msg = KeyUsageMsg::Used(api_key.hash(), Utc::now().timestamp());
+        if let Err(e) = self.tx.send(msg) {
+            eprintln!("key usage error: {}", e);
+        }
+    }
+}
@@ -1,25 +1,39 @@
 pub mod api;
 pub mod ctx;
+pub mod edit_cookie;
+pub mod etag;
 pub mod form;
+pub mod health;
 pub mod hit_counter;
 pub mod http;
+pub mod key_generation;
+pub mod key_usage;
+pub mod openapi;
+pub mod password_attempts;
+pub mod rate_limit;
 pub mod renderer;
+pub mod request_id;
+pub mod unlock;
 
 pub use api::ApiKey;
 pub use hit_counter::HitCounter;
+pub use key_usage::KeyUsage;
+pub use request_id::RequestId;
 
 use rocket;
 
-pub const PASSWORD_COOKIE: &str = "password";
-
 #[derive(rocket::Responder)]
 pub enum PageError {
     #[response(status = 500)]
     SerializationError(String),
     #[response(status = 500)]
     RenderError(String),
+    #[response(status = 403)]
+    Forbidden(String),
     #[response(status = 404)]
     NotFound(String),
+    #[response(status = 410)]
+    Gone(String),
     #[response(status = 500)]
     InternalError(String),
 }
@@ -37,20 +51,31 @@ impl From<serde_json::Error> for PageError {
 }
 
 pub mod test_helpers {
-    use crate::data::{AppDatabase, Database};
+    use crate::data::{AppDatabase, Database, MIGRATOR};
     use sqlx::migrate::Migrator;
     use std::path::Path;
     use tokio::runtime::Handle;
 
     pub fn new_db(handle: &Handle) -> AppDatabase {
         handle.block_on(async move {
-            let db = Database::new(":memory:").await;
+            let db = Database::in_memory().await;
             let migrator = Migrator::new(Path::new("./migrations")).await.unwrap();
             let pool = db.get_pool();
             migrator.run(pool).await.unwrap();
             db
         })
     }
+
+    /// Like [`new_db`], but backed by a file in `dir`, so that the pool holds several
+    /// connections that really run concurrently.
+    pub fn new_file_db(handle: &Handle, dir: &Path) -> AppDatabase {
+        let uri = format!("sqlite:{}?mode=rwc", dir.join("test.db").display());
+        handle.block_on(async move {
+            let db = Database::new(&uri).await;
+            MIGRATOR.run(db.get_pool()).await.unwrap();
+            db
+        })
+    }
 }
 
 #[cfg(test)]
@@ -59,20 +84,35 @@ pub mod test {
     use crate::RocketConfig;
     use rocket::local::blocking::Client;
     pub fn config() -> RocketConfig {
-        use crate::web::{hit_counter::HitCounter, renderer::Renderer};
-        let rt = tokio::runtime::Runtime::new().expect("failed to spawn tokio runtime");
+        use crate::domain::maintenance::jobs;
+        use crate::web::key_generation::KeyGeneration;
+        use crate::web::password_attempts::PasswordAttempts;
+        use crate::web::rate_limit::RateLimiter;
+        use crate::web::{hit_counter::HitCounter, key_usage::KeyUsage, renderer::Renderer};
+        // The runtime must outlive the test so the pool and background tasks keep running.
+        let rt: &'static _ = Box::leak(Box::new(
+            tokio::runtime::Runtime::new().expect("failed to spawn tokio runtime"),
+        ));
         let renderer = Renderer::new("templates/".into());
         let database = new_db(rt.handle());
         let maintenance = crate::domain::maintenance::Maintenance::spawn(
             database.get_pool().clone(),
             rt.handle().clone(),
+            jobs::builtin(&jobs::Settings::default()),
+            std::time::Duration::ZERO,
         );
         let hit_counter = HitCounter::new(database.get_pool().clone(), rt.handle().clone());
+        let key_usage = KeyUsage::new(database.get_pool().clone(), rt.handle().clone());
 
         RocketConfig {
             renderer,
             database,
             hit_counter,
+            key_usage,
+            key_generation: KeyGeneration::default(),
+            rate_limiter: RateLimiter::default(),
+            password_attempts: PasswordAttempts::default(),
+            quota: Default::default(),
             maintenance,
         }
     }
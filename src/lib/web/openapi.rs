@@ -0,0 +1,226 @@
+//! OpenAPI description of the JSON API and its documentation page.
+
+use crate::domain::clip::field;
+use crate::domain::{Quota, Scope};
+use crate::web::api::{self, jobs, keys, metrics, trash, v1, ErrorBody, API_KEY_HEADER};
+use crate::web::password_attempts::PasswordMetrics;
+use crate::web::{ctx, renderer::Renderer};
+use crate::{service, Time};
+use rocket::response::content::RawHtml;
+use rocket::serde::json::Json;
+use rocket::State;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+/// The OpenAPI 3 document for every route mounted under `/api`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "ClipStash API", version = "1"),
+    paths(
+        v1::get_clip,
+        v1::new_clip,
+        v1::update_clip,
+        v1::patch_clip,
+        v1::delete_clip,
+        api::legacy::get_clip,
+        api::legacy::new_clip,
+        api::legacy::update_clip,
+        api::legacy::patch_clip,
+        api::legacy::new_api_key,
+        keys::list_keys,
+        keys::new_key,
+        keys::revoke_key,
+        keys::revoke_own_key,
+        keys::usage,
+        keys::unversioned_usage,
+        trash::list_trash,
+        trash::restore_clip,
+        jobs::list_jobs,
+        metrics::metrics
+    ),
+    components(schemas(
+        v1::ClipResponse,
+        v1::NewClipRequest,
+        v1::UpdateClipRequest,
+        v1::PatchClipRequest,
+        keys::ApiKeyResponse,
+        keys::NewApiKeyRequest,
+        keys::NewApiKeyResponse,
+        keys::QuotaUsage,
+        keys::UsageResponse,
+        trash::TrashedClipResponse,
+        jobs::JobStatusResponse,
+        metrics::MetricsResponse,
+        PasswordMetrics,
+        Quota,
+        Scope,
+        ErrorBody,
+        service::ask::NewClip,
+        service::ask::UpdateClip,
+        field::Title,
+        field::Content,
+        field::ShortCode,
+        field::ExpiresAt,
+        field::Password,
+        Time,
+    )),
+    modifiers(&ApiKeyAuth),
+    tags(
+        (name = "clips", description = "Create, read and update clips"),
+        (name = "deprecated", description = "Unversioned aliases of the v1 routes"),
+        (name = "keys", description = "API key management"),
+        (name = "trash", description = "Restore deleted and expired clips"),
+        (name = "jobs", description = "Status of the maintenance jobs"),
+        (name = "metrics", description = "Counters of security relevant events"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Registers the `x-api-key` header as the `api_key` security scheme.
+struct ApiKeyAuth;
+
+impl Modify for ApiKeyAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(API_KEY_HEADER))),
+        );
+    }
+}
+
+/// Route serving the OpenAPI document.
+#[rocket::get("/openapi.json")]
+pub fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Route serving interactive documentation for the API.
+#[rocket::get("/docs")]
+pub fn docs(renderer: &State<Renderer<'_>>) -> RawHtml<String> {
+    let context = ctx::ApiDocs::default();
+    RawHtml(renderer.render(&context, &[]))
+}
+
+/// The URI [`routes`](rocket::Route) which can be mounted by [`rocket`].
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![openapi, docs]
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::web::test::client;
+    use rocket::http::Status;
+    use std::collections::BTreeSet;
+
+    /// Collects every `$ref` target in `value`.
+    fn refs(value: &serde_json::Value, found: &mut BTreeSet<String>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map {
+                    match (key.as_str(), value.as_str()) {
+                        ("$ref", Some(target)) => {
+                            found.insert(target.to_owned());
+                        }
+                        _ => refs(value, found),
+                    }
+                }
+            }
+            serde_json::Value::Array(values) => values.iter().for_each(|v| refs(v, found)),
+            _ => (),
+        }
+    }
+
+    #[test]
+    fn serves_docs_page() {
+        let client = client();
+        let response = client.get("/api/docs").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn spec_matches_mounted_routes() {
+        let client = client();
+        let response = client.get("/api/openapi.json").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let spec: serde_json::Value = response.into_json().unwrap();
+
+        let documented: BTreeSet<(String, String)> = spec["paths"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .flat_map(|(path, operations)| {
+                operations
+                    .as_object()
+                    .unwrap()
+                    .keys()
+                    .map(move |method| (method.to_uppercase(), path.clone()))
+            })
+            .collect();
+
+        let undocumented = ["/api/openapi.json", "/api/docs"];
+        let mounted: BTreeSet<(String, String)> = client
+            .rocket()
+            .routes()
+            .filter(|route| route.uri.base().starts_with("/api"))
+            .map(|route| {
+                let path = route
+                    .uri
+                    .path()
+                    .to_string()
+                    .replace('<', "{")
+                    .replace('>', "}");
+                (route.method.as_str().to_owned(), path)
+            })
+            .filter(|(_, path)| !undocumented.contains(&path.as_str()))
+            .collect();
+
+        assert_eq!(documented, mounted);
+
+        let mut targets = BTreeSet::new();
+        refs(&spec, &mut targets);
+        for target in targets {
+            let name = target.trim_start_matches("#/components/schemas/");
+            assert!(
+                spec["components"]["schemas"].get(name).is_some(),
+                "dangling schema reference {}",
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn legacy_aliases_document_v1_errors() {
+        let client = client();
+        let spec: serde_json::Value = client
+            .get("/api/openapi.json")
+            .dispatch()
+            .into_json()
+            .unwrap();
+        let errors = |path: &str, method: &str| {
+            let responses = spec["paths"][path][method]["responses"]
+                .as_object()
+                .unwrap();
+            responses
+                .iter()
+                .filter(|(status, _)| status.starts_with('4'))
+                .map(|(status, response)| (status.clone(), response.clone()))
+                .collect::<Vec<_>>()
+        };
+        let aliases = [
+            ("/api/clip/{shortcode}", "/api/v1/clip/{shortcode}", "get"),
+            ("/api/clip", "/api/v1/clip", "post"),
+            ("/api/clip", "/api/v1/clip/{shortcode}", "put"),
+            ("/api/clip/{shortcode}", "/api/v1/clip/{shortcode}", "patch"),
+        ];
+        for (legacy, v1, method) in aliases {
+            assert_eq!(
+                errors(legacy, method),
+                errors(v1, method),
+                "{} {}",
+                method,
+                legacy
+            );
+        }
+    }
+}
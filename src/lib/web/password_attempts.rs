@@ -0,0 +1,410 @@
+//! Brute-force protection for clip passwords.
+//!
+//! Wrong passwords are counted per [`ShortCode`] and per client. After a few free
+//! attempts each further guess has to wait twice as long as the previous one, and
+//! too many failures lock the clip or client out for a while. Counts are forgotten
+//! once no failure has been seen for the lockout period.
+
+use crate::web::rate_limit::ClientIp;
+use crate::{ServiceError, ShortCode};
+use parking_lot::Mutex;
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Request, State};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// How quickly repeated failures are slowed down and locked out.
+#[derive(Clone, Copy, Debug)]
+pub struct Backoff {
+    /// Failures allowed before any delay applies.
+    pub free_attempts: u32,
+    /// Delay after the first failure beyond the free attempts; doubles every failure.
+    pub base_delay: Duration,
+    /// Failures after which the lockout applies.
+    pub lockout_after: u32,
+    /// How long a lockout lasts, and how long failures are remembered.
+    pub lockout: Duration,
+}
+
+impl Backoff {
+    /// How long after the last of `failures` the next attempt must wait.
+    fn delay(&self, failures: u32) -> Duration {
+        if failures >= self.lockout_after {
+            self.lockout
+        } else if failures > self.free_attempts {
+            let doublings = (failures - self.free_attempts - 1).min(16);
+            (self.base_delay * 2u32.pow(doublings)).min(self.lockout)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+/// Failure counts for one kind of key.
+struct Tracker<K> {
+    backoff: Backoff,
+    failures: Mutex<HashMap<K, Failures>>,
+}
+
+impl<K: Hash + Eq> Tracker<K> {
+    fn new(backoff: Backoff) -> Self {
+        Self {
+            backoff,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The time `key` still has to wait before its next attempt.
+    fn wait(&self, key: &K, now: Instant) -> Duration {
+        match self.failures.lock().get(key) {
+            Some(failures) => {
+                let ready = failures.last + self.backoff.delay(failures.count);
+                ready.saturating_duration_since(now)
+            }
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Counts a failure, returning whether it started a lockout.
+    fn fail(&self, key: K, now: Instant) -> bool {
+        let lockout = self.backoff.lockout;
+        let mut failures = self.failures.lock();
+        failures.retain(|_, failures| now.duration_since(failures.last) < lockout);
+        let failures = failures.entry(key).or_insert(Failures {
+            count: 0,
+            last: now,
+        });
+        failures.count += 1;
+        failures.last = now;
+        failures.count == self.backoff.lockout_after
+    }
+
+    fn clear(&self, key: &K) {
+        self.failures.lock().remove(key);
+    }
+}
+
+/// Counters of password events since startup.
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct PasswordMetrics {
+    /// Wrong passwords submitted.
+    pub failures: u64,
+    /// Lockouts started, of either a clip or a client.
+    pub lockouts: u64,
+    /// Attempts refused because of a delay or lockout.
+    pub refused: u64,
+}
+
+/// Tracks wrong clip passwords per [`ShortCode`] and per client.
+pub struct PasswordAttempts {
+    shortcodes: Tracker<ShortCode>,
+    clients: Tracker<IpAddr>,
+    failures: AtomicU64,
+    lockouts: AtomicU64,
+    refused: AtomicU64,
+}
+
+impl PasswordAttempts {
+    pub fn new(per_shortcode: Backoff, per_client: Backoff) -> Self {
+        Self {
+            shortcodes: Tracker::new(per_shortcode),
+            clients: Tracker::new(per_client),
+            failures: AtomicU64::new(0),
+            lockouts: AtomicU64::new(0),
+            refused: AtomicU64::new(0),
+        }
+    }
+
+    /// Fails if `client` or `shortcode` must wait before trying another password.
+    pub fn check(&self, shortcode: &ShortCode, client: IpAddr) -> Result<(), Lockout> {
+        let now = Instant::now();
+        let wait = self
+            .shortcodes
+            .wait(shortcode, now)
+            .max(self.clients.wait(&client, now));
+        if wait.is_zero() {
+            Ok(())
+        } else {
+            self.refused.fetch_add(1, Ordering::Relaxed);
+            Err(Lockout(wait))
+        }
+    }
+
+    /// Records a wrong password for `shortcode` from `client`.
+    pub fn failed(&self, shortcode: &ShortCode, client: IpAddr) {
+        let now = Instant::now();
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        eprintln!(
+            "wrong password for clip {} from {}",
+            shortcode.as_str(),
+            client
+        );
+        if self.shortcodes.fail(shortcode.clone(), now) {
+            self.lockouts.fetch_add(1, Ordering::Relaxed);
+            eprintln!("clip {} locked after wrong passwords", shortcode.as_str());
+        }
+        if self.clients.fail(client, now) {
+            self.lockouts.fetch_add(1, Ordering::Relaxed);
+            eprintln!("client {} locked out after wrong passwords", client);
+        }
+    }
+
+    /// Forgets the failures of `shortcode` after a correct password.
+    ///
+    /// The client's failures are left to expire, since unlocking a clip it knows the
+    /// password of must not let a client reset its own lockout.
+    pub fn succeeded(&self, shortcode: &ShortCode) {
+        self.shortcodes.clear(shortcode);
+    }
+
+    /// The event counters since startup.
+    pub fn metrics(&self) -> PasswordMetrics {
+        PasswordMetrics {
+            failures: self.failures.load(Ordering::Relaxed),
+            lockouts: self.lockouts.load(Ordering::Relaxed),
+            refused: self.refused.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Clients get 3 free attempts and are locked out for 15 minutes after 10 failures.
+/// Clips tolerate more failures, since many clients may share a link.
+impl Default for PasswordAttempts {
+    fn default() -> Self {
+        let per_client = Backoff {
+            free_attempts: 3,
+            base_delay: Duration::from_secs(1),
+            lockout_after: 10,
+            lockout: Duration::from_secs(15 * 60),
+        };
+        let per_shortcode = Backoff {
+            free_attempts: 10,
+            lockout_after: 50,
+            ..per_client
+        };
+        Self::new(per_shortcode, per_client)
+    }
+}
+
+/// A refused password attempt, with the time until the next one is allowed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("too many wrong passwords, try again in {} seconds", self.retry_after())]
+pub struct Lockout(Duration);
+
+impl Lockout {
+    /// Whole seconds until the next attempt is allowed.
+    pub fn retry_after(&self) -> u64 {
+        self.0.as_secs() + u64::from(self.0.subsec_nanos() > 0)
+    }
+}
+
+/// A password attempt by the client of the current request.
+pub struct PasswordAttempt<'r> {
+    attempts: &'r PasswordAttempts,
+    client: IpAddr,
+}
+
+impl PasswordAttempt<'_> {
+    /// Fails if the client or clip is locked out.
+    pub fn check(&self, shortcode: &ShortCode) -> Result<(), Lockout> {
+        self.attempts.check(shortcode, self.client)
+    }
+
+    /// Records the outcome of an attempt: wrong passwords show up as a
+    /// [`PermissionError`](ServiceError::PermissionError).
+    pub fn record<T>(&self, shortcode: &ShortCode, result: &Result<T, ServiceError>) {
+        match result {
+            Ok(_) => self.attempts.succeeded(shortcode),
+            Err(ServiceError::PermissionError(_)) => self.attempts.failed(shortcode, self.client),
+            Err(_) => (),
+        }
+    }
+
+    /// Runs `action` unless the client or clip is locked out, and records its outcome.
+    ///
+    /// Requests that did not send a password (`tried` is false) are neither checked
+    /// nor counted, since being asked for a password is not a wrong guess.
+    pub async fn guard<T, F>(
+        &self,
+        shortcode: &ShortCode,
+        tried: bool,
+        action: F,
+    ) -> Result<Result<T, ServiceError>, Lockout>
+    where
+        F: Future<Output = Result<T, ServiceError>>,
+    {
+        if !tried {
+            return Ok(action.await);
+        }
+        self.check(shortcode)?;
+        let result = action.await;
+        self.record(shortcode, &result);
+        Ok(result)
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for PasswordAttempt<'r> {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let attempts = match req.guard::<&State<PasswordAttempts>>().await {
+            Outcome::Success(attempts) => attempts.inner(),
+            Outcome::Failure((status, _)) => return Outcome::Failure((status, ())),
+            Outcome::Forward(f) => return Outcome::Forward(f),
+        };
+        let client = match req.guard::<ClientIp>().await {
+            Outcome::Success(client) => client.into_inner(),
+            _ => None,
+        };
+        Outcome::Success(Self {
+            attempts,
+            client: client.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{Backoff, PasswordAttempts};
+    use crate::web::api::test::{api_key, runtime};
+    use crate::web::api::CLIP_PASSWORD_HEADER;
+    use crate::web::rate_limit::{Limit, RateLimiter};
+    use crate::web::test::config;
+    use crate::ShortCode;
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::blocking::Client;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Duration;
+
+    /// Locks clients out after three wrong passwords, without any delay before.
+    fn three_strikes() -> Backoff {
+        Backoff {
+            free_attempts: 3,
+            base_delay: Duration::from_secs(1),
+            lockout_after: 3,
+            lockout: Duration::from_secs(15 * 60),
+        }
+    }
+
+    fn client() -> Client {
+        let mut config = config();
+        let limit = Limit::new(100, Duration::from_secs(60));
+        config.rate_limiter = RateLimiter::new(limit, limit, limit);
+        let per_shortcode = Backoff {
+            lockout_after: 50,
+            ..three_strikes()
+        };
+        config.password_attempts = PasswordAttempts::new(per_shortcode, three_strikes());
+        Client::tracked(crate::rocket(config)).expect("failed to build rocket instance")
+    }
+
+    #[test]
+    fn delays_double_until_lockout() {
+        let backoff = Backoff {
+            free_attempts: 2,
+            base_delay: Duration::from_secs(1),
+            lockout_after: 6,
+            lockout: Duration::from_secs(60),
+        };
+        let delays: Vec<_> = (1..=6).map(|n| backoff.delay(n).as_secs()).collect();
+        assert_eq!(delays, [0, 0, 1, 2, 4, 60]);
+    }
+
+    #[test]
+    fn unlocking_another_clip_keeps_client_failures() {
+        let attempts = PasswordAttempts::new(three_strikes(), three_strikes());
+        let client = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let (target, own) = (ShortCode::from("target"), ShortCode::from("own"));
+
+        attempts.failed(&target, client);
+        attempts.failed(&target, client);
+        attempts.succeeded(&own);
+        attempts.failed(&target, client);
+        assert!(attempts.check(&ShortCode::from("other"), client).is_err());
+    }
+
+    #[test]
+    fn locks_out_web_password_guesses() {
+        let client = client();
+        let response = client
+            .post("/")
+            .header(ContentType::Form)
+            .body("content=secret&title=&password=123&expires_at=")
+            .dispatch();
+        let location = response.headers().get_one("Location").unwrap().to_owned();
+        let unlock = |password: &str| {
+            client
+                .post(location.as_str())
+                .header(ContentType::Form)
+                .body(format!("password={}", password))
+                .dispatch()
+        };
+
+        for _ in 0..3 {
+            assert_eq!(unlock("wrong").status(), Status::Ok);
+        }
+        // Even the right password is refused during the lockout.
+        let response = unlock("123");
+        assert_eq!(response.status(), Status::TooManyRequests);
+        let body = response.into_string().unwrap();
+        assert!(body.contains("Too many wrong passwords. Try again in 900 seconds."));
+
+        let metrics = client
+            .rocket()
+            .state::<PasswordAttempts>()
+            .unwrap()
+            .metrics();
+        assert_eq!(metrics.failures, 3);
+        assert_eq!(metrics.lockouts, 1);
+        assert_eq!(metrics.refused, 1);
+    }
+
+    #[test]
+    fn locks_out_api_password_guesses() {
+        let rt = runtime();
+        let client = client();
+        let key = api_key(&client, &rt);
+        let response = client
+            .post("/api/v1/clip")
+            .header(key.clone())
+            .header(ContentType::JSON)
+            .body(r#"{"content": "secret", "password": "123"}"#)
+            .dispatch();
+        let clip: crate::web::api::v1::ClipResponse = response.into_json().unwrap();
+        let get = |password: Option<&str>| {
+            let mut request = client
+                .get(format!("/api/v1/clip/{}", clip.shortcode))
+                .header(key.clone());
+            if let Some(password) = password {
+                request = request.header(Header::new(CLIP_PASSWORD_HEADER, password.to_owned()));
+            }
+            request.dispatch()
+        };
+
+        assert_eq!(get(Some("123")).status(), Status::Ok);
+        for _ in 0..3 {
+            assert_eq!(get(Some("wrong")).status(), Status::Forbidden);
+        }
+        let response = get(Some("123"));
+        assert_eq!(response.status(), Status::TooManyRequests);
+        assert_eq!(response.headers().get_one("Retry-After"), Some("900"));
+        let body: crate::web::api::ErrorBody = response.into_json().unwrap();
+        assert_eq!(body.code, "too_many_requests");
+
+        // Asking without a password is not a guess.
+        assert_eq!(get(None).status(), Status::Forbidden);
+    }
+}
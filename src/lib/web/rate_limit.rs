@@ -0,0 +1,512 @@
+//! Token-bucket rate limiting per client.
+//!
+//! Routes opt in with a [`RateLimited`] request guard naming their [`RouteClass`].
+//! Requests made with a valid [`ApiKey`] draw from a bucket for that key; all others
+//! draw from a bucket for the client IP. The [`RateLimitFairing`] reports the state of
+//! the bucket in `RateLimit-*` headers, and adds `Retry-After` when it is empty.
+
+use crate::web::api::{ApiError, API_KEY_HEADER};
+use crate::web::ApiKey;
+use parking_lot::Mutex;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::request::{FromRequest, Outcome};
+use rocket::{Request, Response, State};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::net::{IpAddr, Ipv4Addr};
+use std::num::NonZeroU32;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// How often buckets that have refilled completely are dropped. A full bucket is the
+/// same as no bucket, so this only frees memory.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Buckets kept by default before the least recently used ones are evicted.
+const MAX_BUCKETS: usize = 100_000;
+
+/// A group of routes sharing one limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum RouteClass {
+    /// Creating clips.
+    Create,
+    /// Reading clips.
+    Read,
+    /// Submitting clip passwords.
+    Password,
+}
+
+/// A [`RouteClass`] that can be named by a [`RateLimited`] guard.
+pub trait LimitedClass {
+    const CLASS: RouteClass;
+}
+
+/// Marker for [`RouteClass::Create`].
+pub struct Create;
+/// Marker for [`RouteClass::Read`].
+pub struct Read;
+/// Marker for [`RouteClass::Password`].
+pub struct Password;
+
+impl LimitedClass for Create {
+    const CLASS: RouteClass = RouteClass::Create;
+}
+impl LimitedClass for Read {
+    const CLASS: RouteClass = RouteClass::Read;
+}
+impl LimitedClass for Password {
+    const CLASS: RouteClass = RouteClass::Password;
+}
+
+/// Allows bursts of `requests`, refilled evenly over `period`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limit {
+    requests: u32,
+    period: Duration,
+}
+
+impl Limit {
+    /// # Panics
+    ///
+    /// If `requests` or `period` is zero, since such a bucket never refills.
+    pub fn new(requests: u32, period: Duration) -> Self {
+        assert!(requests > 0, "a rate limit must allow at least one request");
+        assert!(!period.is_zero(), "a rate limit must have a period");
+        Self { requests, period }
+    }
+
+    /// Tokens added to a bucket per second.
+    fn refill_rate(&self) -> f64 {
+        self.requests as f64 / self.period.as_secs_f64()
+    }
+}
+
+/// Parses limits such as `10/min`, `5/s` or `100/hour`.
+impl FromStr for Limit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (requests, period) = s
+            .split_once('/')
+            .ok_or_else(|| format!("expected <requests>/<period>, got `{}`", s))?;
+        let requests = requests
+            .trim()
+            .parse::<NonZeroU32>()
+            .map_err(|_| format!("invalid request count `{}`, expected 1 or more", requests))?;
+        let seconds = match period.trim() {
+            "s" | "sec" | "second" => 1,
+            "m" | "min" | "minute" => 60,
+            "h" | "hour" => 60 * 60,
+            "d" | "day" => 24 * 60 * 60,
+            other => return Err(format!("unknown period `{}`", other)),
+        };
+        Ok(Self::new(requests.get(), Duration::from_secs(seconds)))
+    }
+}
+
+/// Who a bucket belongs to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Client {
+    Ip(IpAddr),
+    Key(Vec<u8>),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// The state of a bucket after a request, reported in the response headers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    /// Size of the bucket.
+    pub limit: u32,
+    /// Requests left in the bucket.
+    pub remaining: u32,
+    /// Seconds until the bucket is full again.
+    pub reset: u64,
+    /// Seconds until the next request is allowed, if this one was refused.
+    pub retry_after: Option<u64>,
+}
+
+/// The buckets of all clients, and when full ones were last dropped.
+struct Buckets {
+    map: HashMap<(RouteClass, Client), Bucket>,
+    swept: Instant,
+}
+
+/// Token buckets for every client and [`RouteClass`].
+pub struct RateLimiter {
+    create: Limit,
+    read: Limit,
+    password: Limit,
+    trusted_proxies: Vec<IpAddr>,
+    max_buckets: usize,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    pub fn new(create: Limit, read: Limit, password: Limit) -> Self {
+        Self {
+            create,
+            read,
+            password,
+            trusted_proxies: vec![],
+            max_buckets: MAX_BUCKETS,
+            buckets: Mutex::new(Buckets {
+                map: HashMap::new(),
+                swept: Instant::now(),
+            }),
+        }
+    }
+
+    /// Trust the `X-Forwarded-For` header of requests from these addresses.
+    pub fn with_trusted_proxies(mut self, trusted_proxies: Vec<IpAddr>) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    /// Keep at most `max_buckets` buckets, evicting the least recently used ones.
+    pub fn with_max_buckets(mut self, max_buckets: usize) -> Self {
+        self.max_buckets = max_buckets.max(1);
+        self
+    }
+
+    /// The [`Limit`] of `class`.
+    pub fn limit(&self, class: RouteClass) -> Limit {
+        match class {
+            RouteClass::Create => self.create,
+            RouteClass::Read => self.read,
+            RouteClass::Password => self.password,
+        }
+    }
+
+    /// The address of the client that sent `req`.
+    ///
+    /// When the peer is a trusted proxy, `X-Forwarded-For` is read from the right,
+    /// skipping further trusted proxies, so clients cannot pick their own address.
+    pub fn client_ip(&self, req: &Request<'_>) -> Option<IpAddr> {
+        let peer = req.remote()?.ip();
+        if !self.trusted_proxies.contains(&peer) {
+            return Some(peer);
+        }
+        let forwarded: Vec<IpAddr> = req
+            .headers()
+            .get("X-Forwarded-For")
+            .flat_map(|header| header.split(','))
+            .filter_map(|ip| ip.trim().parse().ok())
+            .collect();
+        Some(
+            forwarded
+                .iter()
+                .rev()
+                .find(|ip| !self.trusted_proxies.contains(ip))
+                .copied()
+                .unwrap_or(peer),
+        )
+    }
+
+    /// Takes a token from the bucket of `client` for `class`.
+    fn acquire(&self, class: RouteClass, client: Client) -> RateLimitStatus {
+        let limit = self.limit(class);
+        let capacity = limit.requests as f64;
+        let rate = limit.refill_rate();
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock();
+        if now.duration_since(buckets.swept) >= SWEEP_INTERVAL {
+            self.sweep(&mut buckets.map, now);
+            buckets.swept = now;
+        }
+        let key = (class, client);
+        if buckets.map.len() >= self.max_buckets && !buckets.map.contains_key(&key) {
+            self.evict(&mut buckets.map);
+        }
+        let bucket = buckets.map.entry(key).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+
+        let retry_after = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(((1.0 - bucket.tokens) / rate).ceil() as u64)
+        };
+        RateLimitStatus {
+            limit: limit.requests,
+            remaining: bucket.tokens.floor() as u32,
+            reset: ((capacity - bucket.tokens) / rate).ceil() as u64,
+            retry_after,
+        }
+    }
+
+    /// Drops the buckets that have refilled completely by `now`.
+    fn sweep(&self, buckets: &mut HashMap<(RouteClass, Client), Bucket>, now: Instant) {
+        buckets.retain(|(class, _), bucket| {
+            let limit = self.limit(*class);
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens + elapsed * limit.refill_rate() < limit.requests as f64
+        });
+    }
+
+    /// Evicts the least recently used tenth of the buckets, so that the scan is paid
+    /// for by the many inserts that fit before the next eviction.
+    fn evict(&self, buckets: &mut HashMap<(RouteClass, Client), Bucket>) {
+        let mut updated: Vec<Instant> = buckets.values().map(|bucket| bucket.updated).collect();
+        let evicted = (buckets.len() / 10).max(1);
+        let (_, newest_evicted, _) = updated.select_nth_unstable(evicted - 1);
+        let cutoff = *newest_evicted;
+        let mut remaining = evicted;
+        buckets.retain(|_, bucket| {
+            if remaining > 0 && bucket.updated <= cutoff {
+                remaining -= 1;
+                false
+            } else {
+                true
+            }
+        });
+    }
+}
+
+/// Allows 10 clips to be created, 120 clips to be read and 5 passwords to be tried
+/// per minute.
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(
+            Limit::new(10, Duration::from_secs(60)),
+            Limit::new(120, Duration::from_secs(60)),
+            Limit::new(5, Duration::from_secs(60)),
+        )
+    }
+}
+
+/// The address of the client, as determined by [`RateLimiter::client_ip`].
+pub struct ClientIp(Option<IpAddr>);
+
+impl ClientIp {
+    pub fn into_inner(self) -> Option<IpAddr> {
+        self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientIp {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let ip = match req.guard::<&State<RateLimiter>>().await {
+            Outcome::Success(limiter) => limiter.client_ip(req),
+            _ => req.remote().map(|remote| remote.ip()),
+        };
+        Outcome::Success(Self(ip))
+    }
+}
+
+/// The [`RateLimitStatus`] of the current request, if a [`RateLimited`] guard ran.
+struct CachedStatus(Option<RateLimitStatus>);
+
+/// A request that was within the limit of the [`RouteClass`] `C`.
+///
+/// Fails with `429 Too Many Requests` otherwise.
+pub struct RateLimited<C>(PhantomData<C>);
+
+#[rocket::async_trait]
+impl<'r, C: LimitedClass> FromRequest<'r> for RateLimited<C> {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let limiter = match req.guard::<&State<RateLimiter>>().await {
+            Outcome::Success(limiter) => limiter,
+            _ => {
+                let e = ApiError::Server("server error".to_owned());
+                return Outcome::Failure(e.cache(req));
+            }
+        };
+        let client = match req.headers().get_one(API_KEY_HEADER) {
+            Some(_) => match req.guard::<ApiKey>().await {
+                Outcome::Success(api_key) => Some(Client::Key(api_key.into_inner())),
+                _ => None,
+            },
+            None => None,
+        };
+        let client = client.unwrap_or_else(|| {
+            Client::Ip(
+                limiter
+                    .client_ip(req)
+                    .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            )
+        });
+
+        let status = limiter.acquire(C::CLASS, client);
+        req.local_cache(|| CachedStatus(Some(status)));
+        match status.retry_after {
+            None => Outcome::Success(Self(PhantomData)),
+            Some(retry_after) => {
+                let e = ApiError::TooManyRequests {
+                    message: format!(
+                        "too many {} requests, retry in {} seconds",
+                        C::CLASS,
+                        retry_after
+                    ),
+                    retry_after: Some(retry_after),
+                };
+                Outcome::Failure(e.cache(req))
+            }
+        }
+    }
+}
+
+/// Adds the `RateLimit-*` and `Retry-After` headers to rate limited responses.
+pub struct RateLimitFairing;
+
+#[rocket::async_trait]
+impl Fairing for RateLimitFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Rate limit headers",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        if let Some(status) = req.local_cache(|| CachedStatus(None)).0 {
+            res.set_raw_header("RateLimit-Limit", status.limit.to_string());
+            res.set_raw_header("RateLimit-Remaining", status.remaining.to_string());
+            res.set_raw_header("RateLimit-Reset", status.reset.to_string());
+            if let Some(retry_after) = status.retry_after {
+                res.set_raw_header("Retry-After", retry_after.to_string());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{Limit, RateLimiter, RouteClass, SWEEP_INTERVAL};
+    use crate::web::api::test::{api_key, runtime};
+    use crate::web::test::config;
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::blocking::Client;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::time::{Duration, Instant};
+
+    fn client(limiter: RateLimiter) -> Client {
+        let mut config = config();
+        config.rate_limiter = limiter;
+        Client::tracked(crate::rocket(config)).expect("failed to build rocket instance")
+    }
+
+    fn ip(n: u8) -> super::Client {
+        super::Client::Ip(IpAddr::V4(Ipv4Addr::new(10, 0, 0, n)))
+    }
+
+    fn two_per_minute() -> RateLimiter {
+        let limit = Limit::new(2, Duration::from_secs(60));
+        RateLimiter::new(limit, limit, limit)
+    }
+
+    #[test]
+    fn parses_limits() {
+        let limit: Limit = "10/min".parse().unwrap();
+        assert_eq!(limit, Limit::new(10, Duration::from_secs(60)));
+        assert!("10".parse::<Limit>().is_err());
+        assert!("10/fortnight".parse::<Limit>().is_err());
+    }
+
+    #[test]
+    fn rejects_limits_without_requests() {
+        let e = "0/min".parse::<Limit>().unwrap_err();
+        assert_eq!(e, "invalid request count `0`, expected 1 or more");
+        assert!(std::panic::catch_unwind(|| Limit::new(0, Duration::from_secs(60))).is_err());
+    }
+
+    #[test]
+    fn limits_each_api_key() {
+        let rt = runtime();
+        let client = client(two_per_minute());
+        let first = api_key(&client, &rt);
+        let second = api_key(&client, &rt);
+        let create = |key: &Header<'static>| {
+            client
+                .post("/api/v1/clip")
+                .header(key.clone())
+                .header(ContentType::JSON)
+                .body(r#"{"content": "limited"}"#)
+                .dispatch()
+        };
+
+        let response = create(&first);
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("RateLimit-Limit"), Some("2"));
+        assert_eq!(response.headers().get_one("RateLimit-Remaining"), Some("1"));
+        assert_eq!(create(&first).status(), Status::Ok);
+
+        let response = create(&first);
+        assert_eq!(response.status(), Status::TooManyRequests);
+        assert_eq!(response.headers().get_one("RateLimit-Remaining"), Some("0"));
+        assert_eq!(response.headers().get_one("Retry-After"), Some("30"));
+        let body: crate::web::api::ErrorBody = response.into_json().unwrap();
+        assert_eq!(body.code, "too_many_requests");
+
+        assert_eq!(create(&second).status(), Status::Ok);
+    }
+
+    #[test]
+    fn limits_each_client_ip() {
+        let proxy: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+        let limiter = two_per_minute().with_trusted_proxies(vec![proxy.ip()]);
+        let client = client(limiter);
+        let get = |remote: &str, forwarded: &str| {
+            client
+                .get("/clip/notexist")
+                .remote(remote.parse().unwrap())
+                .header(Header::new("X-Forwarded-For", forwarded.to_owned()))
+                .dispatch()
+                .status()
+        };
+
+        assert_eq!(get("10.0.0.1:4000", "1.1.1.1"), Status::NotFound);
+        assert_eq!(get("10.0.0.1:4000", "1.1.1.1"), Status::NotFound);
+        assert_eq!(get("10.0.0.1:4000", "1.1.1.1"), Status::TooManyRequests);
+        assert_eq!(get("10.0.0.1:4000", "2.2.2.2"), Status::NotFound);
+
+        // Untrusted peers cannot choose their address.
+        assert_eq!(get("3.3.3.3:4000", "4.4.4.4"), Status::NotFound);
+        assert_eq!(get("3.3.3.3:4000", "5.5.5.5"), Status::NotFound);
+        assert_eq!(get("3.3.3.3:4000", "6.6.6.6"), Status::TooManyRequests);
+    }
+
+    #[test]
+    fn bounds_the_number_of_buckets() {
+        let limiter = two_per_minute().with_max_buckets(10);
+        for n in 0..25 {
+            limiter.acquire(RouteClass::Read, ip(n));
+        }
+        let buckets = limiter.buckets.lock();
+        assert!(buckets.map.len() <= 10);
+        assert!(buckets.map.contains_key(&(RouteClass::Read, ip(24))));
+        assert!(!buckets.map.contains_key(&(RouteClass::Read, ip(0))));
+    }
+
+    #[test]
+    fn sweeps_full_buckets() {
+        let fast = Limit::new(1000, Duration::from_secs(1));
+        let limiter = RateLimiter::new(fast, two_per_minute().limit(RouteClass::Read), fast);
+        limiter.acquire(RouteClass::Create, ip(1));
+        limiter.acquire(RouteClass::Read, ip(1));
+        std::thread::sleep(Duration::from_millis(10));
+
+        limiter.buckets.lock().swept = Instant::now() - SWEEP_INTERVAL;
+        limiter.acquire(RouteClass::Read, ip(2));
+        let buckets = limiter.buckets.lock();
+        assert!(!buckets.map.contains_key(&(RouteClass::Create, ip(1))));
+        assert!(buckets.map.contains_key(&(RouteClass::Read, ip(1))));
+        assert!(buckets.map.contains_key(&(RouteClass::Read, ip(2))));
+    }
+}
@@ -1,5 +1,6 @@
 use crate::web::ctx;
-use handlebars;
+use chrono::{DateTime, Utc};
+use handlebars::{self, handlebars_helper};
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -17,6 +18,7 @@ impl<'a> Renderer<'a> {
         renderer
             .register_templates_directory(".hbs", &template_dir)
             .expect("failed to registry handlebars renderer");
+        renderer.register_helper("relative_time", Box::new(relative_time_helper));
         Self(renderer)
     }
     /// Convert a serializable struct into a `serde_json::Value`.
@@ -24,7 +26,7 @@ impl<'a> Renderer<'a> {
     where
         S: serde::Serialize + std::fmt::Debug,
     {
-        match serde_json::to_value(&s) {
+        match serde_json::to_value(s) {
             Ok(v) => v,
             Err(_) => serde_json::Value::Null,
         }
@@ -65,3 +67,42 @@ impl<'a> Renderer<'a> {
         self.do_render(context.template_path(), value)
     }
 }
+
+handlebars_helper!(relative_time_helper: |time: str| relative_time(time, Utc::now()));
+
+/// Describes an RFC 3339 `time` relative to `now`, as "in 3h" or "2d ago".
+///
+/// Pages show the exact time in the viewer's timezone alongside, see `base.hbs`.
+pub fn relative_time(time: &str, now: DateTime<Utc>) -> String {
+    let time = match DateTime::parse_from_rfc3339(time) {
+        Ok(time) => time,
+        Err(_) => return time.to_owned(),
+    };
+    let seconds = (time.with_timezone(&Utc) - now).num_seconds();
+    let amount = match seconds.abs() {
+        s if s < 60 => return "just now".to_owned(),
+        s if s < 60 * 60 => format!("{}m", s / 60),
+        s if s < 24 * 60 * 60 => format!("{}h", s / (60 * 60)),
+        s => format!("{}d", s / (24 * 60 * 60)),
+    };
+    if seconds > 0 {
+        format!("in {}", amount)
+    } else {
+        format!("{} ago", amount)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn describes_times_relative_to_now() {
+        let now: DateTime<Utc> = "2030-05-01T12:00:00Z".parse().unwrap();
+        assert_eq!(relative_time("2030-05-01T15:30:00Z", now), "in 3h");
+        assert_eq!(relative_time("2030-05-01T14:00:30+02:00", now), "just now");
+        assert_eq!(relative_time("2030-05-01T11:15:00Z", now), "45m ago");
+        assert_eq!(relative_time("2030-04-28T12:00:00Z", now), "3d ago");
+        assert_eq!(relative_time("soon", now), "soon");
+    }
+}
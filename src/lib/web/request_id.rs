@@ -0,0 +1,88 @@
+//! Per-request identifiers that tie error responses to log lines.
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Status;
+use rocket::{Request, Response};
+
+/// HTTP header carrying the [`RequestId`], both on requests and responses.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest client-supplied request id that is accepted as-is.
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+/// Identifies a single request.
+///
+/// A well-formed `x-request-id` header sent by the client (or a proxy in front of
+/// us) is reused; otherwise a random UUID is generated.
+#[derive(Debug, Clone)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// Returns the id of `req`, assigning one on first use.
+    pub fn of<'r>(req: &'r Request<'_>) -> &'r str {
+        req.local_cache(|| {
+            let id = req
+                .headers()
+                .get_one(REQUEST_ID_HEADER)
+                .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+                .filter(|id| id.chars().all(|c| c.is_ascii_graphic()))
+                .map(ToOwned::to_owned)
+                .unwrap_or_else(|| uuid::Uuid::new_v4().to_hyphenated().to_string());
+            RequestId(id)
+        })
+        .0
+        .as_str()
+    }
+}
+
+/// A log line for a failed request.
+///
+/// Only the method, path, status and [`RequestId`] are included: headers, cookies and the
+/// query string may carry API keys, clip passwords and edit tokens.
+pub fn describe(status: Status, req: &Request<'_>) -> String {
+    format!(
+        "{} {} -> {} (request {})",
+        req.method(),
+        req.uri().path(),
+        status.code,
+        RequestId::of(req)
+    )
+}
+
+/// Echoes the [`RequestId`] of every request in the response headers.
+pub struct RequestIdFairing;
+
+#[rocket::async_trait]
+impl Fairing for RequestIdFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request ID",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        res.set_raw_header(REQUEST_ID_HEADER, RequestId::of(req).to_owned());
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::web::test::client;
+
+    #[test]
+    fn describes_requests_without_credentials() {
+        let client = client();
+        let req = client
+            .get("/api/clip/abc?token=secret-token")
+            .header(rocket::http::Header::new(
+                "X-Clip-Password",
+                "secret-password",
+            ))
+            .header(rocket::http::Header::new("x-api-key", "secret-key"))
+            .header(rocket::http::Header::new(REQUEST_ID_HEADER, "req-1"));
+        let line = describe(Status::Forbidden, req.inner());
+        assert_eq!(line, "GET /api/clip/abc -> 403 (request req-1)");
+    }
+}
@@ -0,0 +1,52 @@
+//! Per-clip unlock cookies for password protected clips.
+//!
+//! Submitting the correct password on the web issues a private (encrypted and
+//! authenticated) cookie scoped to that one [`ShortCode`]. The cookie records when it
+//! expires, so a copied cookie stops working even if the browser keeps it around.
+
+use crate::domain::clip::field::Password;
+use crate::ShortCode;
+use chrono::Utc;
+use rocket::http::{Cookie, CookieJar, SameSite};
+
+/// Prefix of the unlock cookie name; the clip [`ShortCode`] is appended.
+pub const UNLOCK_COOKIE_PREFIX: &str = "unlock-";
+
+/// How long an unlocked clip stays readable without asking for the password again.
+pub const UNLOCK_TTL_SECONDS: i64 = 60 * 60;
+
+/// Name of the unlock cookie for `shortcode`.
+pub fn cookie_name(shortcode: &ShortCode) -> String {
+    format!("{}{}", UNLOCK_COOKIE_PREFIX, shortcode.as_str())
+}
+
+/// Remembers that `password` unlocks the clip at `shortcode`.
+pub fn grant(cookies: &CookieJar<'_>, shortcode: &ShortCode, password: &Password) {
+    let password = match password.clone().into_inner() {
+        Some(password) => password,
+        None => return,
+    };
+    let expires = Utc::now().timestamp() + UNLOCK_TTL_SECONDS;
+    let cookie = Cookie::build(cookie_name(shortcode), format!("{}:{}", expires, password))
+        .path("/clip")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .max_age(rocket::time::Duration::seconds(UNLOCK_TTL_SECONDS))
+        .finish();
+    cookies.add_private(cookie);
+}
+
+/// The password remembered for `shortcode`, if its unlock cookie is valid and unexpired.
+pub fn password(cookies: &CookieJar<'_>, shortcode: &ShortCode) -> Password {
+    cookies
+        .get_private(&cookie_name(shortcode))
+        .and_then(|cookie| {
+            let (expires, password) = cookie.value().split_once(':')?;
+            let expires: i64 = expires.parse().ok()?;
+            if expires < Utc::now().timestamp() {
+                return None;
+            }
+            Password::new(password.to_owned()).ok()
+        })
+        .unwrap_or_default()
+}
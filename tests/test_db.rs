@@ -1,5 +1,5 @@
-use clipstash::data::query::new_clip;
 use clipstash::data::query::test_helpers::*;
+use clipstash::data::query::{new_clip, patch_clip};
 use clipstash::web::test_helpers::new_db;
 
 #[test]
@@ -12,7 +12,244 @@ fn test_db() {
     let clip = rt.block_on(async move { new_clip(model_new_clip("1"), &pool.clone()).await });
     assert!(clip.is_ok());
     let clip = clip.unwrap();
-    assert!(clip.content == format!("content for clip '1'"));
+    assert_eq!(clip.content, b"content for clip '1'");
+}
+
+#[test]
+fn test_patch_clip() {
+    use clipstash::domain::clip::field::{Content, Title};
+    use clipstash::service::ask::PatchClip;
+
+    let rt = async_runtime();
+    let db = new_db(rt.handle());
+    let pool = db.get_pool();
+
+    let patched = rt.block_on(async move {
+        new_clip(model_new_clip("1"), pool).await.unwrap();
+        let req = PatchClip {
+            shortcode: "1".into(),
+            title: Some(Title::new(Some("title".to_owned())).unwrap()),
+            ..Default::default()
+        };
+        patch_clip(req, pool).await
+    });
+    assert_eq!(patched.unwrap().content, b"content for clip '1'");
+
+    let missing = rt.block_on(async move {
+        let req = PatchClip {
+            shortcode: "2".into(),
+            content: Some(Content::new("content").unwrap()),
+            ..Default::default()
+        };
+        patch_clip(req, pool).await
+    });
+    assert!(missing.is_err());
+}
+
+#[test]
+fn test_api_key_last_used() {
+    use clipstash::domain::Scopes;
+    use clipstash::service::{action, ask::NewApiKey};
+
+    let rt = async_runtime();
+    let db = new_db(rt.handle());
+    let pool = db.get_pool();
+
+    let info = rt.block_on(async move {
+        let req = NewApiKey::with_scopes(Scopes::clips());
+        let api_key = action::generate_api_key(req, pool).await.unwrap();
+        let mut transaction = action::begin_transaction(pool).await.unwrap();
+        action::touch_api_key(&api_key.hash(), 1_666_000_000, &mut transaction)
+            .await
+            .unwrap();
+        action::end_transaction(transaction).await.unwrap();
+        action::get_api_key(api_key, pool).await.unwrap().unwrap()
+    });
+    assert!(!info.is_expired());
+    let last_used_at = info.last_used_at.unwrap();
+    assert_eq!(last_used_at.to_timestamp(), 1_666_000_000);
+}
+
+#[test]
+fn test_bootstrap_admin_key() {
+    use clipstash::domain::Scope;
+    use clipstash::service::action;
+    use clipstash::web::ApiKey;
+
+    let rt = async_runtime();
+    let db = new_db(rt.handle());
+    let pool = db.get_pool();
+
+    let (generated, again) = rt.block_on(async move {
+        let generated = action::bootstrap_admin_key(None, pool).await.unwrap();
+        let again = action::bootstrap_admin_key(None, pool).await.unwrap();
+        (generated, again)
+    });
+    assert!(generated.is_some());
+    assert!(again.is_none());
+
+    let configured = ApiKey::default();
+    let info = rt.block_on(async move {
+        action::bootstrap_admin_key(Some(configured.clone()), pool)
+            .await
+            .unwrap();
+        action::get_api_key(configured, pool)
+            .await
+            .unwrap()
+            .unwrap()
+    });
+    assert!(info.scopes.contains(Scope::Admin));
+}
+
+#[test]
+fn test_legacy_api_keys_are_hashed() {
+    use clipstash::service::action;
+    use clipstash::web::ApiKey;
+
+    let rt = async_runtime();
+    let db = new_db(rt.handle());
+    let pool = db.get_pool();
+    let legacy = ApiKey::default();
+
+    let (info, owner, hashed) = rt.block_on(async move {
+        // A key saved as it was before keys were hashed, owning a clip.
+        sqlx::query("INSERT INTO api_keys (api_key, key_id) VALUES (?, 'legacy')")
+            .bind(legacy.as_bytes())
+            .execute(pool)
+            .await
+            .unwrap();
+        new_clip(model_new_clip("1"), pool).await.unwrap();
+        sqlx::query("UPDATE clips SET owner_key = ?")
+            .bind(legacy.as_bytes())
+            .execute(pool)
+            .await
+            .unwrap();
+        assert!(action::get_api_key(legacy.clone(), pool)
+            .await
+            .unwrap()
+            .is_none());
+
+        assert_eq!(action::hash_legacy_api_keys(pool).await.unwrap(), 1);
+        assert_eq!(action::hash_legacy_api_keys(pool).await.unwrap(), 0);
+        let info = action::get_api_key(legacy.clone(), pool).await.unwrap();
+        let owner: (Vec<u8>,) = sqlx::query_as("SELECT owner_key FROM clips")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        (info, owner.0, legacy.hash())
+    });
+    assert_eq!(info.unwrap().id, "legacy");
+    assert_eq!(owner, hashed);
+}
+
+#[test]
+fn test_content_is_deduplicated() {
+    use clipstash::data::query::{
+        dedupe_inline_content, delete_orphaned_blobs, get_clip, purge_trash,
+    };
+    use clipstash::data::DatabasePool;
+    use clipstash::domain::clip::field::Content;
+    use clipstash::service::ask::PatchClip;
+
+    async fn blobs(pool: &DatabasePool) -> Vec<(i64,)> {
+        sqlx::query_as("SELECT refs FROM blobs ORDER BY refs")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    let rt = async_runtime();
+    let db = new_db(rt.handle());
+    let pool = db.get_pool();
+
+    rt.block_on(async move {
+        new_clip(model_new_clip("1"), pool).await.unwrap();
+        new_clip(model_new_clip("2"), pool).await.unwrap();
+        assert_eq!(blobs(pool).await, vec![(1,), (1,)]);
+
+        let req = PatchClip {
+            shortcode: "2".into(),
+            content: Some(Content::new("content for clip '1'").unwrap()),
+            ..Default::default()
+        };
+        patch_clip(req, pool).await.unwrap();
+        assert_eq!(blobs(pool).await, vec![(0,), (2,)]);
+        assert_eq!(delete_orphaned_blobs(pool).await.unwrap(), 1);
+        assert_eq!(blobs(pool).await, vec![(2,)]);
+
+        // Deleted clips keep their content until purged from the trash.
+        sqlx::query("UPDATE clips SET deleted_at = 0")
+            .execute(pool)
+            .await
+            .unwrap();
+        assert_eq!(delete_orphaned_blobs(pool).await.unwrap(), 0);
+        purge_trash(1, pool).await.unwrap();
+        assert_eq!(delete_orphaned_blobs(pool).await.unwrap(), 1);
+        assert!(blobs(pool).await.is_empty());
+
+        // Clips from before deduplication keep their content inline until it is moved.
+        sqlx::query(
+            "INSERT INTO clips (id, shortcode, content, created_at, hits) \
+             VALUES ('legacy', '3', 'inline', 0, 0)",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            get_clip("3".to_owned(), pool).await.unwrap().content,
+            b"inline"
+        );
+        assert_eq!(dedupe_inline_content(100, pool).await.unwrap(), 1);
+        assert_eq!(dedupe_inline_content(100, pool).await.unwrap(), 0);
+        assert_eq!(blobs(pool).await, vec![(1,)]);
+        assert_eq!(
+            get_clip("3".to_owned(), pool).await.unwrap().content,
+            b"inline"
+        );
+    });
+}
+
+#[test]
+fn test_compression_backfill() {
+    use clipstash::data::query::{compress_blobs, content_storage, get_clip};
+    use clipstash::domain::{Clip, ContentStorage};
+    use std::convert::TryFrom;
+
+    let rt = async_runtime();
+    let db = new_db(rt.handle());
+    let pool = db.get_pool();
+    let log = "GET /clip/raw/abc 200\n".repeat(500);
+
+    rt.block_on(async move {
+        // Content stored before compression: plain, with no codec.
+        sqlx::query("INSERT INTO blobs (hash, content, refs, size) VALUES ('legacy', ?, 0, ?)")
+            .bind(&log)
+            .bind(log.len() as i64)
+            .execute(pool)
+            .await
+            .unwrap();
+        sqlx::query(
+            "INSERT INTO clips (id, shortcode, content, content_hash, created_at, hits) \
+             VALUES ('00000000-0000-0000-0000-000000000001', '1', '', 'legacy', 0, 0)",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+        let before = ContentStorage::from(content_storage(pool).await.unwrap());
+        assert_eq!(before.stored, before.size);
+
+        let batch = ContentStorage::from(compress_blobs(100, pool).await.unwrap());
+        let after = ContentStorage::from(content_storage(pool).await.unwrap());
+        assert_eq!(after.size, before.size);
+        assert!(after.stored < before.stored / 10);
+        assert!(after.saved_percent() > 90.0);
+        assert_eq!(batch, after);
+        let again = ContentStorage::from(compress_blobs(100, pool).await.unwrap());
+        assert_eq!(again.blobs, 0);
+
+        let clip = Clip::try_from(get_clip("1".to_owned(), pool).await.unwrap()).unwrap();
+        assert_eq!(clip.content.into_inner(), log);
+    });
 }
 
 pub fn async_runtime() -> tokio::runtime::Runtime {